@@ -0,0 +1,444 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt::Write as _;
+
+use crate::{error, error::Result, parse, print};
+
+/// A builder to generate dockerfile programmatically.
+///
+/// Instructions are added in the order of method calls. Methods that modify
+/// the last instruction, such as [`as_`](Self::as_) and [`flag`](Self::flag),
+/// can be called after the instruction is added.
+///
+/// ```
+/// use parse_dockerfile::DockerfileBuilder;
+///
+/// let text = DockerfileBuilder::new()
+///     .from("rust:1")
+///     .as_("build")
+///     .run_shell("cargo build --release")
+///     .from("debian:bookworm-slim")
+///     .copy_from("build", "/app/target/release/app", "/usr/local/bin/app")
+///     .cmd_exec(["app", "--port", "8080"])
+///     .build()
+///     .unwrap();
+/// assert_eq!(
+///     text,
+///     "FROM rust:1 AS build
+/// RUN cargo build --release
+///
+/// FROM debian:bookworm-slim
+/// COPY --from=build /app/target/release/app /usr/local/bin/app
+/// CMD [\"app\", \"--port\", \"8080\"]
+/// ",
+/// );
+/// ```
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct DockerfileBuilder {
+    syntax: Option<String>,
+    escape: Option<char>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone)]
+enum Entry {
+    Comment(String),
+    Instruction(Instruction),
+}
+
+#[derive(Debug, Clone)]
+struct Instruction {
+    keyword: &'static str,
+    flags: Vec<(String, Option<String>)>,
+    arguments: Arguments,
+}
+
+#[derive(Debug, Clone)]
+enum Arguments {
+    From {
+        image: String,
+        name: Option<String>,
+    },
+    Arg {
+        name: String,
+        default: Option<String>,
+    },
+    /// Shell form command.
+    Shell(String),
+    /// Exec form command or JSON array.
+    Exec(Vec<String>),
+    /// Space-separated words, or JSON array if needed.
+    Paths(Vec<String>),
+    /// Space-separated words that cannot contain whitespace.
+    Words(Vec<String>),
+    /// The rest of the line.
+    Line(String),
+    KeyValues(Vec<(String, String)>),
+}
+
+impl DockerfileBuilder {
+    /// Creates a new empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the `syntax` parser directive.
+    pub fn syntax(mut self, syntax: &str) -> Self {
+        self.syntax = Some(syntax.into());
+        self
+    }
+    /// Sets the `escape` parser directive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `escape` is neither `\` nor `` ` ``.
+    pub fn escape(mut self, escape: char) -> Self {
+        assert!(matches!(escape, '\\' | '`'), "escape character must be '\\' or '`'");
+        self.escape = Some(escape);
+        self
+    }
+    /// Adds a comment line. Multi-line comments are split into multiple lines.
+    pub fn comment(mut self, comment: &str) -> Self {
+        self.entries.push(Entry::Comment(comment.into()));
+        self
+    }
+
+    /// Adds a `FROM` instruction that starts a new stage.
+    pub fn from(self, image: &str) -> Self {
+        self.push("FROM", Arguments::From { image: image.into(), name: None })
+    }
+    /// Sets the name of the stage started by the last `FROM` instruction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last instruction is not `FROM`.
+    pub fn as_(mut self, name: &str) -> Self {
+        match self.last_instruction("as_") {
+            Instruction { arguments: Arguments::From { name: n, .. }, .. } => {
+                *n = Some(name.into());
+            }
+            _ => panic!("as_ must be called after from"),
+        }
+        self
+    }
+    /// Sets `--platform` flag of the last `FROM` instruction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last instruction is not `FROM`.
+    pub fn platform(self, platform: &str) -> Self {
+        assert!(
+            matches!(
+                self.entries.last(),
+                Some(Entry::Instruction(Instruction { arguments: Arguments::From { .. }, .. }))
+            ),
+            "platform must be called after from"
+        );
+        self.flag("platform", platform)
+    }
+    /// Adds a flag `--name=value` to the last instruction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no instruction has been added.
+    pub fn flag(mut self, name: &str, value: &str) -> Self {
+        self.last_instruction("flag").flags.push((name.into(), Some(value.into())));
+        self
+    }
+    /// Adds a flag `--name` without value to the last instruction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no instruction has been added.
+    pub fn bool_flag(mut self, name: &str) -> Self {
+        self.last_instruction("bool_flag").flags.push((name.into(), None));
+        self
+    }
+
+    /// Adds an `ARG` instruction without default value.
+    pub fn arg(self, name: &str) -> Self {
+        self.push("ARG", Arguments::Arg { name: name.into(), default: None })
+    }
+    /// Adds an `ARG` instruction with default value.
+    pub fn arg_default(self, name: &str, default: &str) -> Self {
+        self.push("ARG", Arguments::Arg { name: name.into(), default: Some(default.into()) })
+    }
+    /// Adds a `RUN` instruction in shell form.
+    pub fn run_shell(self, command: &str) -> Self {
+        self.push("RUN", Arguments::Shell(command.into()))
+    }
+    /// Adds a `RUN` instruction in exec form.
+    pub fn run_exec<I: IntoIterator<Item = S>, S: AsRef<str>>(self, args: I) -> Self {
+        self.push("RUN", Arguments::Exec(collect(args)))
+    }
+    /// Adds a `CMD` instruction in shell form.
+    pub fn cmd_shell(self, command: &str) -> Self {
+        self.push("CMD", Arguments::Shell(command.into()))
+    }
+    /// Adds a `CMD` instruction in exec form.
+    pub fn cmd_exec<I: IntoIterator<Item = S>, S: AsRef<str>>(self, args: I) -> Self {
+        self.push("CMD", Arguments::Exec(collect(args)))
+    }
+    /// Adds an `ENTRYPOINT` instruction in shell form.
+    pub fn entrypoint_shell(self, command: &str) -> Self {
+        self.push("ENTRYPOINT", Arguments::Shell(command.into()))
+    }
+    /// Adds an `ENTRYPOINT` instruction in exec form.
+    pub fn entrypoint_exec<I: IntoIterator<Item = S>, S: AsRef<str>>(self, args: I) -> Self {
+        self.push("ENTRYPOINT", Arguments::Exec(collect(args)))
+    }
+    /// Adds a `SHELL` instruction.
+    pub fn shell<I: IntoIterator<Item = S>, S: AsRef<str>>(self, args: I) -> Self {
+        self.push("SHELL", Arguments::Exec(collect(args)))
+    }
+    /// Adds a `COPY` instruction.
+    pub fn copy(self, src: &str, dest: &str) -> Self {
+        self.push("COPY", Arguments::Paths(vec![src.into(), dest.into()]))
+    }
+    /// Adds a `COPY` instruction with `--from` flag.
+    pub fn copy_from(self, from: &str, src: &str, dest: &str) -> Self {
+        self.copy(src, dest).flag("from", from)
+    }
+    /// Adds an `ADD` instruction.
+    pub fn add(self, src: &str, dest: &str) -> Self {
+        self.push("ADD", Arguments::Paths(vec![src.into(), dest.into()]))
+    }
+    /// Adds an `ENV` instruction.
+    pub fn env(self, key: &str, value: &str) -> Self {
+        self.push("ENV", Arguments::KeyValues(vec![(key.into(), value.into())]))
+    }
+    /// Adds a `LABEL` instruction.
+    pub fn label(self, key: &str, value: &str) -> Self {
+        self.push("LABEL", Arguments::KeyValues(vec![(key.into(), value.into())]))
+    }
+    /// Adds an `EXPOSE` instruction.
+    pub fn expose(self, port: &str) -> Self {
+        self.push("EXPOSE", Arguments::Words(vec![port.into()]))
+    }
+    /// Adds a `VOLUME` instruction.
+    pub fn volume(self, path: &str) -> Self {
+        self.push("VOLUME", Arguments::Paths(vec![path.into()]))
+    }
+    /// Adds a `USER` instruction.
+    pub fn user(self, user: &str) -> Self {
+        self.push("USER", Arguments::Line(user.into()))
+    }
+    /// Adds a `WORKDIR` instruction.
+    pub fn workdir(self, path: &str) -> Self {
+        self.push("WORKDIR", Arguments::Line(path.into()))
+    }
+    /// Adds a `STOPSIGNAL` instruction.
+    pub fn stopsignal(self, signal: &str) -> Self {
+        self.push("STOPSIGNAL", Arguments::Words(vec![signal.into()]))
+    }
+
+    fn push(mut self, keyword: &'static str, arguments: Arguments) -> Self {
+        self.entries.push(Entry::Instruction(Instruction { keyword, flags: vec![], arguments }));
+        self
+    }
+    #[track_caller]
+    fn last_instruction(&mut self, method: &str) -> &mut Instruction {
+        match self.entries.iter_mut().rev().find_map(|e| match e {
+            Entry::Instruction(i) => Some(i),
+            Entry::Comment(..) => None,
+        }) {
+            Some(i) => i,
+            None => panic!("{method} must be called after an instruction is added"),
+        }
+    }
+
+    /// Renders the dockerfile to text, and checks that the text can be parsed.
+    ///
+    /// Values are escaped or quoted as needed, and values that must be in JSON
+    /// array (e.g., paths containing whitespace in `COPY`) are rendered as
+    /// JSON array. Values of `ARG`, `ENV`, and `LABEL` are taken literally, so
+    /// `$` in them is escaped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a value cannot be represented in dockerfile (e.g.,
+    /// a newline in shell form command), or if the rendered text cannot be
+    /// parsed.
+    pub fn build(&self) -> Result<String> {
+        let escape = self.escape.unwrap_or('\\');
+        let mut out = String::new();
+        if let Some(syntax) = &self.syntax {
+            check_line("syntax", syntax)?;
+            let _ = writeln!(out, "# syntax={syntax}");
+        }
+        if let Some(escape) = self.escape {
+            let _ = writeln!(out, "# escape={escape}");
+        }
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        let mut seen_from = false;
+        for entry in &self.entries {
+            match entry {
+                Entry::Comment(comment) => {
+                    // A leading comment in the form of `key=value` is a parser directive.
+                    if out.is_empty() && comment.lines().next().is_some_and(is_directive_like) {
+                        out.push('\n');
+                    }
+                    for line in comment.lines() {
+                        if line.is_empty() {
+                            out.push_str("#\n");
+                        } else {
+                            let _ = writeln!(out, "# {line}");
+                        }
+                    }
+                }
+                Entry::Instruction(instruction) => {
+                    if instruction.keyword == "FROM" && seen_from {
+                        out.push('\n');
+                    }
+                    seen_from |= instruction.keyword == "FROM";
+                    render_instruction(&mut out, instruction, escape)?;
+                    out.push('\n');
+                }
+            }
+        }
+        parse(&out)?;
+        Ok(out)
+    }
+}
+
+fn collect<I: IntoIterator<Item = S>, S: AsRef<str>>(args: I) -> Vec<String> {
+    args.into_iter().map(|s| s.as_ref().into()).collect()
+}
+
+fn render_instruction(out: &mut String, instruction: &Instruction, escape: char) -> Result<()> {
+    let keyword = instruction.keyword;
+    out.push_str(keyword);
+    for (name, value) in &instruction.flags {
+        check_word(keyword, name)?;
+        let _ = write!(out, " --{name}");
+        if let Some(value) = value {
+            check_word(keyword, value)?;
+            let _ = write!(out, "={value}");
+        }
+    }
+    match &instruction.arguments {
+        Arguments::From { image, name } => {
+            check_word(keyword, image)?;
+            let _ = write!(out, " {image}");
+            if let Some(name) = name {
+                check_word(keyword, name)?;
+                let _ = write!(out, " AS {name}");
+            }
+        }
+        Arguments::Arg { name, default } => {
+            check_key(keyword, name)?;
+            let _ = write!(out, " {name}");
+            if let Some(default) = default {
+                out.push('=');
+                push_quoted(out, keyword, default, escape)?;
+            }
+        }
+        Arguments::Shell(command) => {
+            check_line(keyword, command)?;
+            if command.trim_end_matches([' ', '\t']).ends_with(escape) {
+                return Err(error::without_location(
+                    format!("{keyword} instruction must not end with escape character").into(),
+                ));
+            }
+            let _ = write!(out, " {command}");
+        }
+        Arguments::Exec(args) => {
+            out.push(' ');
+            let _ = print::print_json_array(out, args.iter().map(String::as_str));
+        }
+        Arguments::Paths(paths) => {
+            out.push(' ');
+            if print::needs_json_form(paths.iter().map(String::as_str)) {
+                let _ = print::print_json_array(out, paths.iter().map(String::as_str));
+            } else {
+                for (i, path) in paths.iter().enumerate() {
+                    check_word(keyword, path)?;
+                    if i != 0 {
+                        out.push(' ');
+                    }
+                    out.push_str(path);
+                }
+            }
+        }
+        Arguments::Words(words) => {
+            for word in words {
+                check_word(keyword, word)?;
+                let _ = write!(out, " {word}");
+            }
+        }
+        Arguments::Line(line) => {
+            check_line(keyword, line)?;
+            if line.trim_matches([' ', '\t']) != line || line.ends_with(escape) {
+                return Err(invalid_value(keyword, line));
+            }
+            let _ = write!(out, " {line}");
+        }
+        Arguments::KeyValues(key_values) => {
+            for (key, value) in key_values {
+                out.push(' ');
+                check_key(keyword, key)?;
+                push_quoted(out, keyword, key, escape)?;
+                out.push('=');
+                push_quoted(out, keyword, value, escape)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Pushes `value` to `out`, quoting with double quotes if needed.
+///
+/// `$` is also escaped so that the value is not subject to variable substitution.
+fn push_quoted(out: &mut String, keyword: &str, value: &str, escape: char) -> Result<()> {
+    check_line(keyword, value)?;
+    if !value.is_empty() && !value.contains([' ', '\t', '"', '\'', '$', escape]) {
+        out.push_str(value);
+        return Ok(());
+    }
+    out.push('"');
+    for ch in value.chars() {
+        if matches!(ch, '"' | '$') || ch == escape {
+            out.push(escape);
+        }
+        out.push(ch);
+    }
+    out.push('"');
+    Ok(())
+}
+
+/// Returns `true` if `# {line}` can be parsed as a parser directive.
+fn is_directive_like(line: &str) -> bool {
+    let line = line.trim_start_matches([' ', '\t']);
+    let key_len = line.len() - line.trim_start_matches(|c: char| c.is_ascii_alphanumeric()).len();
+    key_len != 0 && line[key_len..].trim_start_matches([' ', '\t']).starts_with('=')
+}
+
+fn check_line(keyword: &str, value: &str) -> Result<()> {
+    if value.contains(['\n', '\r']) {
+        return Err(error::without_location(
+            format!("{keyword} instruction must not contain newline: {value:?}").into(),
+        ));
+    }
+    Ok(())
+}
+fn check_word(keyword: &str, value: &str) -> Result<()> {
+    if value.is_empty() || value.contains([' ', '\t', '\n', '\r']) {
+        return Err(invalid_value(keyword, value));
+    }
+    Ok(())
+}
+fn check_key(keyword: &str, key: &str) -> Result<()> {
+    check_word(keyword, key)?;
+    if key.contains('=') {
+        return Err(invalid_value(keyword, key));
+    }
+    Ok(())
+}
+#[cold]
+fn invalid_value(keyword: &str, value: &str) -> error::Error {
+    error::without_location(format!("invalid value for {keyword} instruction: {value:?}").into())
+}
@@ -0,0 +1,463 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    string::String,
+    vec,
+    vec::Vec,
+};
+use core::time::Duration;
+
+use crate::{
+    Command, Dockerfile, Flag, HealthcheckArguments, Instruction, JsonOrStringArray, error,
+    error::Result,
+    expand::Env,
+    scope::{Scope, VariableKind},
+    words,
+    workdir::join_path,
+};
+
+/// The default shell used for shell-form commands.
+const DEFAULT_SHELL: &[&str] = &["/bin/sh", "-c"];
+
+/// The runtime configuration of the image built from a stage.
+///
+/// Fields (and their serialized names) correspond to `config` of the
+/// [OCI image configuration](https://github.com/opencontainers/image-spec/blob/main/config.md),
+/// plus the fields Docker adds (`Healthcheck`, `Shell`, and `OnBuild`).
+///
+/// See [`Dockerfile::image_configs`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+#[non_exhaustive]
+pub struct ImageConfig {
+    /// The user (`USER`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "String::is_empty"))]
+    pub user: String,
+    /// The exposed ports in the form `<port>/<protocol>` (`EXPOSE`).
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "BTreeSet::is_empty", serialize_with = "ser::empty_objects")
+    )]
+    pub exposed_ports: BTreeSet<String>,
+    /// The environment variables in the form `<name>=<value>` (`ENV`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub env: Vec<String>,
+    /// The entrypoint (`ENTRYPOINT`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub entrypoint: Vec<String>,
+    /// The default arguments to the entrypoint (`CMD`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub cmd: Vec<String>,
+    /// The volumes (`VOLUME`).
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "BTreeSet::is_empty", serialize_with = "ser::empty_objects")
+    )]
+    pub volumes: BTreeSet<String>,
+    /// The working directory (`WORKDIR`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "String::is_empty"))]
+    pub working_dir: String,
+    /// The labels (`LABEL`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "BTreeMap::is_empty"))]
+    pub labels: BTreeMap<String, String>,
+    /// The signal sent to stop the container (`STOPSIGNAL`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "String::is_empty"))]
+    pub stop_signal: String,
+    /// The health check (`HEALTHCHECK`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub healthcheck: Option<Healthcheck>,
+    /// The shell used for shell-form commands (`SHELL`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub shell: Vec<String>,
+    /// The trigger instructions executed when the image is used as a base
+    /// (`ONBUILD`).
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Vec::is_empty"))]
+    pub on_build: Vec<String>,
+}
+
+/// The health check of [`ImageConfig`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "PascalCase"))]
+#[non_exhaustive]
+pub struct Healthcheck {
+    /// The test to perform: `["NONE"]`, `["CMD", args...]`, or
+    /// `["CMD-SHELL", command]`.
+    pub test: Vec<String>,
+    /// The value of `--interval` flag. Serialized in nanoseconds.
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none", serialize_with = "ser::nanos")
+    )]
+    pub interval: Option<Duration>,
+    /// The value of `--timeout` flag. Serialized in nanoseconds.
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none", serialize_with = "ser::nanos")
+    )]
+    pub timeout: Option<Duration>,
+    /// The value of `--start-period` flag. Serialized in nanoseconds.
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none", serialize_with = "ser::nanos")
+    )]
+    pub start_period: Option<Duration>,
+    /// The value of `--start-interval` flag. Serialized in nanoseconds.
+    #[cfg_attr(
+        feature = "serde",
+        serde(skip_serializing_if = "Option::is_none", serialize_with = "ser::nanos")
+    )]
+    pub start_interval: Option<Duration>,
+    /// The value of `--retries` flag.
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub retries: Option<u32>,
+}
+
+impl Dockerfile<'_> {
+    /// Computes the image config of each stage, in the order of stages.
+    ///
+    /// `text` must be the text passed to the parsing function that returned
+    /// this dockerfile (it is used to get the text of `ONBUILD` triggers), and
+    /// `build_args` are values passed with `--build-arg`.
+    ///
+    /// Like BuildKit:
+    ///
+    /// - A stage based on another stage inherits its config, except for
+    ///   `ONBUILD` triggers. Stages based on external images (or `scratch`)
+    ///   start from an empty config, since the configs of external images are
+    ///   unknown.
+    /// - `ENTRYPOINT` resets `CMD` unless `CMD` is set in the same stage before it.
+    /// - Shell-form commands are run with the shell set by `SHELL` (default:
+    ///   `/bin/sh -c`).
+    /// - Variables in `ENV`, `WORKDIR`, `USER`, `EXPOSE`, `VOLUME`, `LABEL`,
+    ///   and `STOPSIGNAL` are expanded, and relative `WORKDIR` is resolved
+    ///   against the previous one.
+    ///
+    /// Note that the effect of `ONBUILD` triggers of the base stage is not
+    /// applied.
+    ///
+    /// ```
+    /// use parse_dockerfile::parse;
+    ///
+    /// let text = "FROM alpine AS base
+    /// ENV APP_HOME=/app
+    /// WORKDIR $APP_HOME
+    /// CMD [\"serve\"]
+    ///
+    /// FROM base
+    /// ARG PORT
+    /// ENTRYPOINT [\"/app/bin\"]
+    /// EXPOSE $PORT
+    /// ";
+    /// let dockerfile = parse(text).unwrap();
+    /// let configs = dockerfile.image_configs(text, &[("PORT", "8080")]).unwrap();
+    ///
+    /// let config = &configs[1];
+    /// assert_eq!(config.env, ["APP_HOME=/app"]);
+    /// assert_eq!(config.working_dir, "/app");
+    /// assert_eq!(config.entrypoint, ["/app/bin"]);
+    /// // `ENTRYPOINT` resets `CMD` inherited from the base stage.
+    /// assert!(config.cmd.is_empty());
+    /// assert!(config.exposed_ports.contains("8080/tcp"));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if expanding variables failed, `VOLUME` is empty, or
+    /// the value of a `HEALTHCHECK` flag is invalid.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the span of an `ONBUILD` instruction is out of bounds of `text`.
+    pub fn image_configs<E: ?Sized + Env>(
+        &self,
+        text: &str,
+        build_args: &E,
+    ) -> Result<Vec<ImageConfig>> {
+        let expander = self.expander();
+        let expand =
+            |s: &str, scope: &Scope<'_>| -> Result<String> { Ok(expander.expand(s, scope)?.value) };
+        let scopes = self.scopes(build_args)?;
+        let mut configs: Vec<ImageConfig> = Vec::with_capacity(self.stages.len());
+        for stage in self.stages() {
+            let start = self.stages[stage.index()].start;
+            let scope = scopes.at(start);
+            let image = expand(&stage.from.image.value, scope)?;
+            let mut config = match self.stage(&image).filter(|b| b.index() < stage.index()) {
+                Some(base) => ImageConfig { on_build: vec![], ..configs[base.index()].clone() },
+                None => ImageConfig::default(),
+            };
+            let mut cmd_set = false;
+            for (i, instruction) in stage.instructions.iter().enumerate() {
+                let scope = scopes.at(start + 1 + i);
+                match instruction {
+                    Instruction::Cmd(cmd) => {
+                        config.cmd = self.command(&cmd.arguments, &config.shell);
+                        cmd_set = true;
+                    }
+                    Instruction::Entrypoint(entrypoint) => {
+                        config.entrypoint = self.command(&entrypoint.arguments, &config.shell);
+                        if !cmd_set {
+                            config.cmd.clear();
+                        }
+                    }
+                    Instruction::Expose(expose) => {
+                        for port in &expose.arguments {
+                            add_ports(&mut config.exposed_ports, &expand(&port.value, scope)?);
+                        }
+                    }
+                    Instruction::Healthcheck(healthcheck) => {
+                        let test = match &healthcheck.arguments {
+                            HealthcheckArguments::Cmd { arguments, .. } => match arguments {
+                                Command::Exec(arguments) => {
+                                    let mut test = vec![String::from("CMD")];
+                                    test.extend(
+                                        arguments.value.iter().map(|a| String::from(&*a.value)),
+                                    );
+                                    test
+                                }
+                                Command::Shell(command) => {
+                                    vec![
+                                        String::from("CMD-SHELL"),
+                                        join_lines(command.value, self.escape_byte),
+                                    ]
+                                }
+                            },
+                            HealthcheckArguments::None { .. } => vec![String::from("NONE")],
+                        };
+                        let mut healthcheck_config = Healthcheck { test, ..Default::default() };
+                        for flag in &healthcheck.options {
+                            set_healthcheck_option(&mut healthcheck_config, flag)?;
+                        }
+                        config.healthcheck = Some(healthcheck_config);
+                    }
+                    Instruction::Label(label) => {
+                        for kv in words::key_values(&label.arguments, self.escape_byte, true) {
+                            let key = expand(kv.key, scope)?;
+                            let value = expand(kv.value.unwrap_or_default(), scope)?;
+                            config.labels.insert(key, value);
+                        }
+                    }
+                    Instruction::Onbuild(onbuild) => {
+                        let trigger = onbuild.instruction.raw_text(text);
+                        config.on_build.push(join_lines(trigger, self.escape_byte));
+                    }
+                    Instruction::Shell(shell) => {
+                        config.shell =
+                            shell.arguments.iter().map(|a| String::from(&*a.value)).collect();
+                    }
+                    Instruction::Stopsignal(stopsignal) => {
+                        config.stop_signal = expand(&stopsignal.arguments.value, scope)?;
+                    }
+                    Instruction::User(user) => {
+                        config.user = expand(&user.arguments.value, scope)?;
+                    }
+                    Instruction::Volume(volume) => {
+                        let volumes = match &volume.arguments {
+                            JsonOrStringArray::Json(volumes) => &volumes.value,
+                            JsonOrStringArray::String(volumes) => volumes,
+                        };
+                        for v in volumes {
+                            let v = expand(&v.value, scope)?;
+                            if v.is_empty() {
+                                return Err(error::without_location(
+                                    "VOLUME specified can not be an empty string".into(),
+                                ));
+                            }
+                            config.volumes.insert(v);
+                        }
+                    }
+                    Instruction::Workdir(workdir) => {
+                        let dir = expand(&workdir.arguments.value, scope)?;
+                        config.working_dir = join_path(&config.working_dir, &dir);
+                    }
+                    _ => {}
+                }
+            }
+            config.env = scopes
+                .end_at(stage.index())
+                .iter()
+                .filter(|v| matches!(v.kind, VariableKind::Env | VariableKind::Inherited))
+                .map(|v| format!("{}={}", v.name, v.value.as_deref().unwrap_or_default()))
+                .collect();
+            configs.push(config);
+        }
+        Ok(configs)
+    }
+
+    /// Converts `CMD` or `ENTRYPOINT` arguments into the form stored in image config.
+    fn command(&self, command: &Command<'_>, shell: &[String]) -> Vec<String> {
+        match command {
+            Command::Exec(arguments) => {
+                arguments.value.iter().map(|a| String::from(&*a.value)).collect()
+            }
+            Command::Shell(command) => {
+                let mut v: Vec<String> = if shell.is_empty() {
+                    DEFAULT_SHELL.iter().map(|&s| String::from(s)).collect()
+                } else {
+                    shell.to_vec()
+                };
+                v.push(join_lines(command.value, self.escape_byte));
+                v
+            }
+        }
+    }
+}
+
+/// Removes line continuations (and comment lines between continuation
+/// lines) from `s`, like the dockerfile parser of BuildKit.
+pub(crate) fn join_lines(s: &str, escape: u8) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut lines = s.split('\n').peekable();
+    let mut continued = false;
+    while let Some(line) = lines.next() {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if continued {
+            let trimmed = line.trim_start_matches([' ', '\t']);
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+        }
+        let trimmed = line.trim_end_matches([' ', '\t']);
+        match trimmed.strip_suffix(escape as char) {
+            Some(rest) if lines.peek().is_some() => {
+                out.push_str(rest);
+                continued = true;
+            }
+            _ => {
+                out.push_str(line);
+                if lines.peek().is_some() {
+                    out.push('\n');
+                }
+                continued = false;
+            }
+        }
+    }
+    out
+}
+
+/// Adds ports of `EXPOSE` argument (e.g., `80`, `53/udp`, or `8000-8002/tcp`)
+/// to `ports`.
+fn add_ports(ports: &mut BTreeSet<String>, spec: &str) {
+    let (port, protocol) = spec.split_once('/').unwrap_or((spec, "tcp"));
+    let protocol = protocol.to_ascii_lowercase();
+    if let Some((start, end)) = port.split_once('-') {
+        if let (Ok(start), Ok(end)) = (start.parse::<u16>(), end.parse::<u16>()) {
+            if start <= end {
+                ports.extend((start..=end).map(|port| format!("{port}/{protocol}")));
+                return;
+            }
+        }
+    }
+    ports.insert(format!("{port}/{protocol}"));
+}
+
+fn set_healthcheck_option(healthcheck: &mut Healthcheck, flag: &Flag<'_>) -> Result<()> {
+    let name = &*flag.name.value;
+    let value = flag.value.as_ref().map_or("", |v| &v.value);
+    let duration = match name {
+        "interval" => &mut healthcheck.interval,
+        "timeout" => &mut healthcheck.timeout,
+        "start-period" => &mut healthcheck.start_period,
+        "start-interval" => &mut healthcheck.start_interval,
+        "retries" => {
+            let Ok(retries) = value.parse() else {
+                return Err(error::without_location(
+                    format!("invalid value {value:?} for --retries in HEALTHCHECK").into(),
+                ));
+            };
+            healthcheck.retries = Some(retries);
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+    let Some(d) = parse_duration(value) else {
+        return Err(error::without_location(
+            format!("invalid duration {value:?} for --{name} in HEALTHCHECK").into(),
+        ));
+    };
+    *duration = Some(d);
+    Ok(())
+}
+
+/// Parses a duration in the format of Go's `time.ParseDuration`, such as
+/// `30s`, `1m30s`, or `1.5h`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    if s == "0" {
+        return Some(Duration::ZERO);
+    }
+    let mut rest = s;
+    let mut total = Duration::ZERO;
+    if rest.is_empty() {
+        return None;
+    }
+    while !rest.is_empty() {
+        let number_len = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+        let (number, after) = rest.split_at(number_len);
+        let unit_len = after.find(|c: char| c.is_ascii_digit() || c == '.').unwrap_or(after.len());
+        let (unit, after) = after.split_at(unit_len);
+        let nanos_per_unit: u64 = match unit {
+            "ns" => 1,
+            "us" | "µs" | "μs" => 1_000,
+            "ms" => 1_000_000,
+            "s" => 1_000_000_000,
+            "m" => 60_000_000_000,
+            "h" => 3_600_000_000_000,
+            _ => return None,
+        };
+        let (int, frac) = number.split_once('.').unwrap_or((number, ""));
+        if int.is_empty() && frac.is_empty() || !frac.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let int: u64 = if int.is_empty() { 0 } else { int.parse().ok()? };
+        let mut nanos = int.checked_mul(nanos_per_unit)?;
+        let mut scale = nanos_per_unit;
+        for digit in frac.bytes() {
+            scale /= 10;
+            nanos = nanos.checked_add(u64::from(digit - b'0') * scale)?;
+        }
+        total = total.checked_add(Duration::from_nanos(nanos))?;
+        rest = after;
+    }
+    Some(total)
+}
+
+#[cfg(feature = "serde")]
+mod ser {
+    use alloc::{collections::BTreeSet, string::String};
+    use core::time::Duration;
+
+    use serde::ser::{Serialize, SerializeMap as _, Serializer};
+
+    struct Empty;
+    impl Serialize for Empty {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_map(Some(0))?.end()
+        }
+    }
+
+    /// Serializes a set as `{"<value>": {}, ...}`, like Go's `map[string]struct{}`.
+    pub(super) fn empty_objects<S: Serializer>(
+        set: &BTreeSet<String>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(set.len()))?;
+        for key in set {
+            map.serialize_entry(key, &Empty)?;
+        }
+        map.end()
+    }
+
+    /// Serializes a duration in nanoseconds, like Go's `time.Duration`.
+    #[allow(clippy::ref_option)] // signature required by serialize_with
+    pub(super) fn nanos<S: Serializer>(
+        duration: &Option<Duration>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let nanos = duration.map_or(0, |d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX));
+        serializer.serialize_u64(nanos)
+    }
+}
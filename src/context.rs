@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use crate::{
+    Dockerfile, Instruction, Source, error,
+    error::Result,
+    expand::{self, Env, Expander},
+    graph::{self, is_remote},
+    scope::Scope,
+    words,
+};
+
+/// A file or directory in the build context read by an instruction.
+///
+/// See [`Dockerfile::context_inputs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub struct ContextInput {
+    /// The index of the stage.
+    pub stage: usize,
+    /// The index of the instruction in [`Dockerfile::instructions`].
+    pub instruction: usize,
+    /// The kind of the instruction.
+    pub kind: ContextInputKind,
+    /// The source path or pattern, relative to the root of the build context,
+    /// with variables expanded and quotes removed.
+    ///
+    /// For a bind mount without `source`, this is `.` (the whole build
+    /// context).
+    pub source: String,
+    /// The destination path (`target` of a bind mount), with variables
+    /// expanded and quotes removed.
+    ///
+    /// See also [`Dockerfile::copy_destinations`] that resolves destinations
+    /// of `COPY` and `ADD` to absolute paths.
+    pub destination: String,
+    /// The values of `--exclude` flags, with variables expanded and quotes
+    /// removed.
+    pub excludes: Vec<String>,
+    /// Whether `--parents` flag is set, i.e., the parent directories of the
+    /// source are preserved in the destination.
+    pub parents: bool,
+    /// Whether the source contains variables before expansion, i.e., whether
+    /// the source depends on build args or other variables.
+    pub has_variables: bool,
+    /// Whether the source contains glob patterns (`*`, `?`, or `[`).
+    pub has_globs: bool,
+}
+
+/// The kind of [`ContextInput`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub enum ContextInputKind {
+    /// `COPY` without `--from`.
+    Copy,
+    /// `ADD` with a local source.
+    Add,
+    /// `RUN --mount=type=bind` without `from`.
+    Mount,
+}
+
+impl Dockerfile<'_> {
+    /// Returns the files and directories in the build context read by all
+    /// stages, in the order of instructions.
+    ///
+    /// This includes local sources of `COPY` without `--from` and `ADD`
+    /// (excluding URLs and here-documents), and bind mounts of `RUN` without
+    /// `from`. `COPY --from=context` is not included since it refers to a
+    /// named context.
+    ///
+    /// Variables are expanded in the same way as [`Dockerfile::scopes`], with
+    /// the given build args. Like BuildKit, unset variables are expanded to
+    /// empty strings.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if expanding variables fails.
+    pub fn context_inputs<E: ?Sized + Env>(&self, build_args: &E) -> Result<Vec<ContextInput>> {
+        let scopes = self.scopes(build_args)?;
+        let expander = self.expander();
+        let mut inputs = vec![];
+        for stage in self.stages() {
+            let start = self.stages[stage.index()].start;
+            for (i, instruction) in stage.instructions.iter().enumerate() {
+                let index = start + 1 + i;
+                let cx = Context { stage: stage.index(), index, expander, scope: scopes.at(index) };
+                self.push_context_inputs(&mut inputs, &cx, instruction)?;
+            }
+        }
+        Ok(inputs)
+    }
+
+    /// Returns the files and directories in the build context read by the
+    /// stages needed to build `target`, in the order of instructions.
+    ///
+    /// See [`Dockerfile::context_inputs`] for what this includes. The stages
+    /// needed to build `target` are determined with the same build args (see
+    /// [`Dockerfile::graph_with_args`]).
+    ///
+    /// ```
+    /// use parse_dockerfile::{ContextInputKind, parse};
+    ///
+    /// let text = "FROM rust AS build
+    /// COPY --exclude=*.md . /src
+    /// RUN --mount=type=bind,source=Cargo.lock,target=/src/Cargo.lock cargo build
+    ///
+    /// FROM alpine AS app
+    /// COPY --from=build /src/target/app /app
+    /// COPY config/*.toml /etc/app/
+    /// ";
+    /// let dockerfile = parse(text).unwrap();
+    /// let no_args = &[("", ""); 0];
+    /// let inputs = dockerfile.context_inputs_for("build", no_args).unwrap();
+    /// assert_eq!(inputs.len(), 2);
+    /// assert_eq!(inputs[0].source, ".");
+    /// assert_eq!(inputs[0].excludes, ["*.md"]);
+    /// assert_eq!(inputs[1].kind, ContextInputKind::Mount);
+    /// assert_eq!(inputs[1].source, "Cargo.lock");
+    ///
+    /// // `app` also reads the inputs of `build` it depends on.
+    /// let inputs = dockerfile.context_inputs_for("app", no_args).unwrap();
+    /// assert_eq!(inputs.len(), 3);
+    /// assert_eq!(inputs[2].source, "config/*.toml");
+    /// assert!(inputs[2].has_globs);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `target` is not found, building the stage
+    /// dependency graph failed, or expanding variables fails.
+    pub fn context_inputs_for<E: ?Sized + Env>(
+        &self,
+        target: &str,
+        build_args: &E,
+    ) -> Result<Vec<ContextInput>> {
+        let Some(target) = self.stage(target) else {
+            return Err(error::without_location(format!("stage {target:?} not found").into()));
+        };
+        let mut needed = vec![false; self.stages.len()];
+        for i in graph::collect(self, build_args, false)?.build_order(target.index()) {
+            needed[i] = true;
+        }
+        let mut inputs = self.context_inputs(build_args)?;
+        inputs.retain(|input| needed[input.stage]);
+        Ok(inputs)
+    }
+
+    fn push_context_inputs(
+        &self,
+        inputs: &mut Vec<ContextInput>,
+        cx: &Context<'_, '_>,
+        instruction: &Instruction<'_>,
+    ) -> Result<()> {
+        let (kind, options, src, dest) = match instruction {
+            Instruction::Copy(copy) => {
+                let from = copy.options.iter().rev().find(|f| f.name.value == "from");
+                if let Some(value) = from.and_then(|f| f.value.as_ref()) {
+                    if !cx.expand(&value.value)?.is_empty() {
+                        return Ok(());
+                    }
+                }
+                (ContextInputKind::Copy, &copy.options, &copy.src, &copy.dest)
+            }
+            Instruction::Add(add) => (ContextInputKind::Add, &add.options, &add.src, &add.dest),
+            Instruction::Run(run) => {
+                for flag in &run.options {
+                    let Some(mount) = words::expand_mount(flag, cx.expander, cx.scope)? else {
+                        continue;
+                    };
+                    if !mount.is_context_bind() {
+                        continue;
+                    }
+                    // The source is checked for variables before expansion.
+                    let raw = words::mount_field(flag, &["source", "src"]);
+                    let mut input = cx.input(
+                        ContextInputKind::Mount,
+                        mount.source.unwrap_or_else(|| String::from(".")),
+                        mount.target.unwrap_or_default(),
+                    );
+                    input.has_variables = raw.is_some_and(|f| self.has_variables(&f.value));
+                    inputs.push(input);
+                }
+                return Ok(());
+            }
+            _ => return Ok(()),
+        };
+        let mut excludes = vec![];
+        for flag in options.iter().filter(|f| f.name.value == "exclude") {
+            if let Some(value) = &flag.value {
+                excludes.push(cx.expand(&value.value)?);
+            }
+        }
+        let parents = options
+            .iter()
+            .rev()
+            .find(|f| f.name.value == "parents")
+            .is_some_and(|f| f.value.as_ref().map_or(true, |v| v.value == "true"));
+        let dest = cx.expand(&dest.value)?;
+        for src in src {
+            let Source::Path(src) = src else { continue };
+            let source = cx.expand(&src.value)?;
+            if is_remote(&source) {
+                continue;
+            }
+            let mut input = cx.input(kind, source, dest.clone());
+            input.excludes.clone_from(&excludes);
+            input.parents = parents;
+            input.has_variables = self.has_variables(&src.value);
+            inputs.push(input);
+        }
+        Ok(())
+    }
+
+    fn has_variables(&self, s: &str) -> bool {
+        !expand::references_in(s, self.escape_byte).is_empty()
+    }
+}
+
+/// The instruction whose inputs are being collected.
+struct Context<'s, 'b> {
+    stage: usize,
+    index: usize,
+    expander: Expander,
+    scope: &'s Scope<'b>,
+}
+
+impl Context<'_, '_> {
+    fn input(&self, kind: ContextInputKind, source: String, destination: String) -> ContextInput {
+        ContextInput {
+            stage: self.stage,
+            instruction: self.index,
+            kind,
+            has_globs: source.contains(['*', '?', '[']),
+            source,
+            destination,
+            excludes: vec![],
+            parents: false,
+            has_variables: false,
+        }
+    }
+    fn expand(&self, s: &str) -> Result<String> {
+        Ok(self.expander.expand(s, self.scope)?.value)
+    }
+}
@@ -0,0 +1,258 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    Command, Dockerfile, HealthcheckArguments, Instruction, MaintainerInstruction, StageBase,
+    TextEdit, UnescapedString, print,
+};
+
+/// Options for [`Dockerfile::convert`].
+///
+/// All conversions are enabled by default.
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct ConvertOptions {
+    exec_form: bool,
+    key_value_form: bool,
+    maintainer_to_label: bool,
+}
+impl ConvertOptions {
+    /// Creates a new `ConvertOptions` with all conversions enabled.
+    pub const fn new() -> Self {
+        Self { exec_form: true, key_value_form: true, maintainer_to_label: true }
+    }
+    /// Sets whether to convert shell form of `CMD`, `ENTRYPOINT`, and
+    /// `HEALTHCHECK CMD` to exec form.
+    ///
+    /// Only commands without shell metacharacters (quotes, variables,
+    /// redirections, etc.) that don't start with a shell builtin (`cd`,
+    /// `exit`, etc.) are converted. Commands in a stage that has `SHELL`
+    /// (including in its base stages) are not converted. `CMD` and
+    /// `ENTRYPOINT` are not converted if the stage (including its base
+    /// stages) has both of them, since `ENTRYPOINT` in exec form receives
+    /// `CMD` as arguments. Triggers of `ONBUILD` are not converted.
+    ///
+    /// Default is `true`.
+    pub const fn exec_form(mut self, exec_form: bool) -> Self {
+        self.exec_form = exec_form;
+        self
+    }
+    /// Sets whether to convert legacy `ENV key value` and `LABEL key value`
+    /// to `key=value` form.
+    ///
+    /// Default is `true`.
+    pub const fn key_value_form(mut self, key_value_form: bool) -> Self {
+        self.key_value_form = key_value_form;
+        self
+    }
+    /// Sets whether to convert deprecated `MAINTAINER` to
+    /// `LABEL org.opencontainers.image.authors=...`.
+    ///
+    /// Default is `true`.
+    pub const fn maintainer_to_label(mut self, maintainer_to_label: bool) -> Self {
+        self.maintainer_to_label = maintainer_to_label;
+        self
+    }
+}
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dockerfile<'_> {
+    /// Returns edits that convert instructions to modern forms.
+    ///
+    /// Conversions are only applied when they don't change the meaning of
+    /// the instruction. Instructions containing line continuations are left
+    /// as is, so that comments in them are not lost.
+    ///
+    /// ```
+    /// use parse_dockerfile::ConvertOptions;
+    ///
+    /// let text = "FROM alpine\nMAINTAINER Jane <jane@example.com>\nENV PORT 8080\nHEALTHCHECK CMD curl -f http://localhost:$PORT/\nENTRYPOINT tini -- app\n";
+    /// let dockerfile = parse_dockerfile::parse(text).unwrap();
+    /// let edits = dockerfile.convert(ConvertOptions::new());
+    /// let new = parse_dockerfile::apply_edits(text, &edits).unwrap();
+    /// assert_eq!(
+    ///     new,
+    ///     "FROM alpine\n\
+    ///      LABEL org.opencontainers.image.authors=\"Jane <jane@example.com>\"\n\
+    ///      ENV PORT=8080\n\
+    ///      HEALTHCHECK CMD curl -f http://localhost:$PORT/\n\
+    ///      ENTRYPOINT [\"tini\", \"--\", \"app\"]\n",
+    /// );
+    /// ```
+    #[must_use]
+    pub fn convert(&self, options: ConvertOptions) -> Vec<TextEdit> {
+        let escape = char::from(self.escape_byte);
+        let mut shells: Vec<StageShell> = Vec::with_capacity(self.stages.len());
+        for stage in self.stages() {
+            let mut shell = match stage.base() {
+                StageBase::Stage(base) => shells[base.index()],
+                StageBase::Scratch | StageBase::Image(..) => StageShell::default(),
+            };
+            for instruction in stage.instructions {
+                match instruction {
+                    Instruction::Cmd(..) => shell.cmd = true,
+                    Instruction::Entrypoint(..) => shell.entrypoint = true,
+                    Instruction::Shell(..) => shell.shell = true,
+                    _ => {}
+                }
+            }
+            shells.push(shell);
+        }
+        let mut edits = Vec::new();
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            // Instructions outside of stages are not converted to exec form.
+            let shell = self.stages.iter().position(|s| s.contains(&i)).map(|i| shells[i]);
+            if let Some(edit) = convert_instruction(instruction, options, escape, shell) {
+                edits.push(edit);
+            }
+        }
+        edits
+    }
+}
+
+/// Instructions in a stage and its base stages that affect the meaning of
+/// shell form commands.
+#[derive(Debug, Clone, Copy, Default)]
+struct StageShell {
+    cmd: bool,
+    entrypoint: bool,
+    shell: bool,
+}
+
+/// Converts `instruction`. Shell form is converted to exec form only if
+/// `shell` is `Some`.
+fn convert_instruction(
+    instruction: &Instruction<'_>,
+    options: ConvertOptions,
+    escape: char,
+    shell: Option<StageShell>,
+) -> Option<TextEdit> {
+    // Commands in shell form are run with the shell set by `SHELL`, and
+    // `ENTRYPOINT` in exec form receives `CMD` as arguments whereas
+    // `ENTRYPOINT` in shell form ignores `CMD`.
+    let exec_form_enabled = options.exec_form && shell.is_some_and(|s| !s.shell);
+    match instruction {
+        Instruction::Cmd(cmd) if exec_form_enabled && shell.is_some_and(|s| !s.entrypoint) => {
+            exec_form(&cmd.arguments)
+        }
+        Instruction::Entrypoint(entrypoint)
+            if exec_form_enabled && shell.is_some_and(|s| !s.cmd) =>
+        {
+            exec_form(&entrypoint.arguments)
+        }
+        Instruction::Healthcheck(healthcheck) if exec_form_enabled => {
+            match &healthcheck.arguments {
+                HealthcheckArguments::Cmd { arguments, .. } => exec_form(arguments),
+                HealthcheckArguments::None { .. } => None,
+            }
+        }
+        Instruction::Env(env) if options.key_value_form => key_value_form(&env.arguments),
+        Instruction::Label(label) if options.key_value_form => key_value_form(&label.arguments),
+        Instruction::Maintainer(maintainer) if options.maintainer_to_label => {
+            maintainer_to_label(maintainer, escape)
+        }
+        // The stage in which the triggers are executed is unknown.
+        Instruction::Onbuild(onbuild) => {
+            convert_instruction(&onbuild.instruction, options, escape, None)
+        }
+        _ => None,
+    }
+}
+
+/// Returns `true` if `s` contains line continuations, in which case the
+/// text at the span may contain comments that are removed from the value.
+fn has_line_continuation(s: &UnescapedString<'_>) -> bool {
+    s.span.len() != s.value.len()
+}
+
+fn exec_form(command: &Command<'_>) -> Option<TextEdit> {
+    // The value of shell form is the raw text, so commands with line
+    // continuations are rejected as they contain unsafe characters.
+    let Command::Shell(s) = command else { return None };
+    let words: Vec<&str> = s.value.split([' ', '\t']).filter(|w| !w.is_empty()).collect();
+    // `%` is not safe because cmd.exe expands `%name%`.
+    let safe = |w: &str| {
+        w.bytes().all(|b| {
+            b.is_ascii_alphanumeric()
+                || matches!(b, b'-' | b'_' | b'.' | b'/' | b':' | b',' | b'+' | b'@' | b'=')
+        })
+    };
+    // A command starting with `name=value` is a variable assignment, and
+    // shell builtins and keywords cannot be run without shell.
+    if words.is_empty()
+        || words[0].contains('=')
+        || SHELL_BUILTINS.contains(&words[0])
+        || !words.iter().all(|w| safe(w))
+    {
+        return None;
+    }
+    let mut replacement = String::with_capacity(s.value.len() + words.len() * 4 + 2);
+    // Writing to String never fails.
+    let _ = print::print_json_array(&mut replacement, words.into_iter());
+    Some(TextEdit::replace(s.span.clone(), replacement))
+}
+
+/// Builtins and reserved words of POSIX shell and common shells, which are
+/// not executables.
+const SHELL_BUILTINS: &[&str] = &[
+    ".", "alias", "bg", "break", "case", "cd", "command", "continue", "do", "done", "elif", "else",
+    "esac", "eval", "exec", "exit", "export", "fc", "fg", "fi", "for", "function", "getopts",
+    "hash", "if", "in", "jobs", "local", "read", "readonly", "return", "select", "set", "shift",
+    "source", "then", "time", "times", "trap", "type", "ulimit", "umask", "unalias", "unset",
+    "until", "wait", "while",
+];
+
+fn key_value_form(arguments: &UnescapedString<'_>) -> Option<TextEdit> {
+    if has_line_continuation(arguments) {
+        return None;
+    }
+    let (key, value) = arguments.value.split_once([' ', '\t'])?;
+    if key.contains('=') {
+        // Already in key=value form.
+        return None;
+    }
+    let value = value.trim_matches([' ', '\t']);
+    if value.is_empty() {
+        return None;
+    }
+    let mut replacement = String::with_capacity(arguments.value.len() + 2);
+    replacement.push_str(key);
+    replacement.push('=');
+    if value.contains([' ', '\t']) {
+        // In the legacy form, the value is the rest of the line including
+        // whitespaces, so it needs to be quoted. Values that already contain
+        // quotes or escapes are left as is.
+        if value.contains(['"', '\'', '\\', '`']) {
+            return None;
+        }
+        replacement.push('"');
+        replacement.push_str(value);
+        replacement.push('"');
+    } else {
+        replacement.push_str(value);
+    }
+    Some(TextEdit::replace(arguments.span.clone(), replacement))
+}
+
+fn maintainer_to_label(maintainer: &MaintainerInstruction<'_>, escape: char) -> Option<TextEdit> {
+    let name = &maintainer.name;
+    if has_line_continuation(name) {
+        return None;
+    }
+    let mut replacement = String::from("LABEL org.opencontainers.image.authors=\"");
+    // The value of MAINTAINER is used as is, whereas the value of LABEL is
+    // unquoted and variables in it are expanded.
+    for ch in name.value.chars() {
+        if ch == '"' || ch == '$' || ch == escape {
+            replacement.push(escape);
+        }
+        replacement.push(ch);
+    }
+    replacement.push('"');
+    Some(TextEdit::replace(maintainer.maintainer.span.start..name.span.end, replacement))
+}
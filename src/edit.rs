@@ -0,0 +1,328 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{string::String, vec::Vec};
+
+use crate::{
+    AddInstruction, CopyInstruction, Dockerfile, Flag, Instruction, JsonOrStringArray,
+    ParseOptions, Source, Span, StopsignalInstruction, UTF8_BOM, UnescapedString, UserInstruction,
+    WorkdirInstruction, error, error::Result, parse_with, print,
+};
+
+/// A text edit: replacement of the text at a span.
+///
+/// Spans are offsets in the text without UTF-8 BOM, like spans in the AST.
+/// Use [`apply_edits`] to apply a set of edits to the original text.
+///
+/// ```
+/// let text = "FROM rust:1.79 AS build\nRUN cargo build\n";
+/// let dockerfile = parse_dockerfile::parse(text).unwrap();
+/// let parse_dockerfile::Instruction::From(from) = &dockerfile.instructions[0] else {
+///     unreachable!()
+/// };
+/// let edits = [from.image.replace_with("rust:1.80"), dockerfile.instructions[1].remove()];
+/// let new = parse_dockerfile::apply_edits(text, &edits).unwrap();
+/// assert_eq!(new, "FROM rust:1.80 AS build\n");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    #[allow(missing_docs)]
+    pub span: Span,
+    #[allow(missing_docs)]
+    pub replacement: String,
+    kind: EditKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EditKind {
+    Replace,
+    /// Replacement of a value that is escaped for its position. See
+    /// [`UnescapedString::replace_with`].
+    Value,
+    /// Insertion of `=` and a flag value, escaped like the value of a flag.
+    FlagValue,
+    /// Removal of an instruction, including its indentation and trailing newline.
+    Line,
+    /// Removal of a word, including its trailing whitespaces.
+    Word,
+}
+
+impl TextEdit {
+    /// Creates an edit that replaces the text at `span` with `replacement`.
+    ///
+    /// `replacement` is inserted as is.
+    #[must_use]
+    pub fn replace<S: Into<String>>(span: Span, replacement: S) -> Self {
+        Self { span, replacement: replacement.into(), kind: EditKind::Replace }
+    }
+    /// Creates an edit that inserts `text` at `pos`.
+    #[must_use]
+    pub fn insert<S: Into<String>>(pos: usize, text: S) -> Self {
+        Self::replace(pos..pos, text)
+    }
+    /// Creates an edit that deletes the text at `span`.
+    #[must_use]
+    pub fn delete(span: Span) -> Self {
+        Self::replace(span, String::new())
+    }
+    /// Creates an edit that deletes the text at `span`, including its
+    /// indentation and the trailing newline if it is on its own lines.
+    pub(crate) fn delete_lines(span: Span) -> Self {
+        Self { kind: EditKind::Line, ..Self::delete(span) }
+    }
+}
+
+impl UnescapedString<'_> {
+    /// Returns an edit that replaces this string with `value`.
+    ///
+    /// `value` is escaped when the edit is applied, so that BuildKit reads it
+    /// literally:
+    ///
+    /// - In positions where BuildKit expands variables (e.g., image of `FROM`,
+    ///   values of flags, paths of `COPY`, and arguments of `WORKDIR`), `$`,
+    ///   quotes, and the escape character are escaped with the escape
+    ///   character of the dockerfile. Whitespace cannot be escaped in
+    ///   positions that are split at whitespace (all of them except arguments
+    ///   of `USER`, `WORKDIR`, and `STOPSIGNAL`), and applying the edit
+    ///   returns an error.
+    /// - If this string is an element of JSON array (e.g., exec form of
+    ///   `CMD`), `value` is then escaped as JSON string.
+    /// - Otherwise (e.g., arguments of `ENV`, which contain multiple
+    ///   key-value pairs), `value` is inserted as is.
+    #[must_use]
+    pub fn replace_with(&self, value: &str) -> TextEdit {
+        TextEdit { span: self.span.clone(), replacement: value.into(), kind: EditKind::Value }
+    }
+}
+
+impl Flag<'_> {
+    /// Returns an edit that sets the value of this flag to `value`.
+    ///
+    /// ```text
+    /// --platform=linux/amd64
+    ///            ^^^^^^^^^^^
+    /// ```
+    ///
+    /// `value` is escaped in the same way as
+    /// [`UnescapedString::replace_with`].
+    #[must_use]
+    pub fn set_value(&self, value: &str) -> TextEdit {
+        match &self.value {
+            Some(v) => v.replace_with(value),
+            None => TextEdit {
+                kind: EditKind::FlagValue,
+                ..TextEdit::insert(self.name.span.end, value)
+            },
+        }
+    }
+    /// Returns an edit that removes this flag and its trailing whitespaces.
+    #[must_use]
+    pub fn remove(&self) -> TextEdit {
+        TextEdit { kind: EditKind::Word, ..TextEdit::delete(self.span()) }
+    }
+}
+
+impl Instruction<'_> {
+    /// Returns an edit that removes this instruction, including its line
+    /// continuations and here-documents.
+    ///
+    /// If this instruction is on its own line, its indentation and the
+    /// trailing newline are removed as well.
+    #[must_use]
+    pub fn remove(&self) -> TextEdit {
+        TextEdit::delete_lines(self.span())
+    }
+}
+
+/// Applies the given edits to `text` and checks that the result can be parsed.
+///
+/// `text` must be the text from which spans of the edits are obtained. The
+/// edits can be given in any order but must not overlap, except that
+/// multiple insertions can be at the same position, in which case they are
+/// applied in the given order.
+///
+/// # Errors
+///
+/// Returns an error if edits are overlapping or out of range, or if the
+/// result cannot be parsed. In the latter case, the location in the error
+/// refers to the result.
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> Result<String> {
+    apply_edits_with(text, edits, ParseOptions::new())
+}
+
+/// Applies the given edits to `text` and checks that the result can be parsed
+/// with the given `options`.
+///
+/// See [`apply_edits`] for more.
+///
+/// # Errors
+///
+/// Returns an error if edits are overlapping or out of range, or if the
+/// result cannot be parsed.
+pub fn apply_edits_with(text: &str, edits: &[TextEdit], options: ParseOptions) -> Result<String> {
+    let bom_len = if text.as_bytes().starts_with(UTF8_BOM) { UTF8_BOM.len() } else { 0 };
+    let (bom, body) = text.split_at(bom_len);
+    // The original is parsed only if needed to escape values.
+    let mut dockerfile = None;
+    let mut resolved = Vec::with_capacity(edits.len());
+    for edit in edits {
+        let span = &edit.span;
+        if span.start > span.end
+            || span.end > body.len()
+            || !body.is_char_boundary(span.start)
+            || !body.is_char_boundary(span.end)
+        {
+            return Err(error::new(
+                "edit out of range",
+                bom_len + span.start.min(body.len()),
+                text,
+            ));
+        }
+        let dockerfile = match (&dockerfile, edit.kind) {
+            (Some(dockerfile), _) => Some(dockerfile),
+            (None, EditKind::Value | EditKind::FlagValue) => {
+                Some(&*dockerfile.insert(parse_with(text, options)?))
+            }
+            (None, _) => None,
+        };
+        resolved.push(resolve(body, edit, dockerfile).map_err(|pos| {
+            error::new("value containing whitespace cannot be escaped", bom_len + pos, text)
+        })?);
+    }
+    // Stable sort to keep the order of insertions at the same position.
+    resolved.sort_by_key(|(span, _)| (span.start, span.end));
+    let mut out = String::with_capacity(text.len());
+    out.push_str(bom);
+    let mut pos = 0;
+    for (span, replacement) in resolved {
+        if span.start < pos {
+            return Err(error::new("overlapping edits", bom_len + span.start, text));
+        }
+        out.push_str(&body[pos..span.start]);
+        out.push_str(&replacement);
+        pos = span.end;
+    }
+    out.push_str(&body[pos..]);
+    parse_with(&out, options)?;
+    Ok(out)
+}
+
+/// Returns the span and replacement of `edit`, or the position of the edit if
+/// its value cannot be escaped.
+fn resolve(
+    text: &str,
+    edit: &TextEdit,
+    dockerfile: Option<&Dockerfile<'_>>,
+) -> Result<(Span, String), usize> {
+    let s = text.as_bytes();
+    let mut span = edit.span.clone();
+    match (edit.kind, dockerfile) {
+        (EditKind::Value | EditKind::FlagValue, Some(dockerfile)) => {
+            let position = if edit.kind == EditKind::FlagValue {
+                Some(Position::Word)
+            } else {
+                dockerfile.instructions.iter().find_map(|i| position(i, &span))
+            };
+            let json =
+                span.start != 0 && s[span.start - 1] == b'"' && s.get(span.end) == Some(&b'"');
+            let mut value = match position {
+                Some(Position::Word) if !json && edit.replacement.contains(char::is_whitespace) => {
+                    return Err(span.start);
+                }
+                Some(_) => escape(&edit.replacement, dockerfile.escape_byte),
+                None => edit.replacement.clone(),
+            };
+            if json {
+                let mut replacement = String::with_capacity(value.len());
+                // Writing to String never fails.
+                let _ = print::print_json_string_contents(&mut replacement, &value);
+                value = replacement;
+            }
+            if edit.kind == EditKind::FlagValue {
+                value.insert(0, '=');
+            }
+            return Ok((span, value));
+        }
+        (EditKind::Replace | EditKind::Value | EditKind::FlagValue, _) => {}
+        (EditKind::Line, _) => {
+            let mut start = span.start;
+            while start != 0 && matches!(s[start - 1], b' ' | b'\t') {
+                start -= 1;
+            }
+            let mut end = span.end;
+            while end < s.len() && matches!(s[end], b' ' | b'\t') {
+                end += 1;
+            }
+            let at_line_start = start == 0 || s[start - 1] == b'\n';
+            if at_line_start {
+                match s.get(end..end + 2) {
+                    Some(b"\r\n") => end += 2,
+                    _ if s.get(end) == Some(&b'\n') => end += 1,
+                    _ => {}
+                }
+                let at_line_end = end == s.len() || s[end - 1] == b'\n';
+                if at_line_end {
+                    span = start..end;
+                }
+            }
+        }
+        (EditKind::Word, _) => {
+            while span.end < s.len() && matches!(s[span.end], b' ' | b'\t') {
+                span.end += 1;
+            }
+        }
+    }
+    Ok((span, edit.replacement.clone()))
+}
+
+/// A position of a string where BuildKit expands variables.
+#[derive(Clone, Copy)]
+enum Position {
+    /// A word split at whitespace.
+    Word,
+    /// The arguments of an instruction that takes a single argument.
+    Argument,
+}
+
+/// Returns the position of the string at `span` in `instruction`, or `None`
+/// if it is not in a position where BuildKit expands variables (e.g., exec
+/// form of `CMD`) or contains multiple values (e.g., arguments of `ENV`).
+fn position(instruction: &Instruction<'_>, span: &Span) -> Option<Position> {
+    let is = |s: &UnescapedString<'_>| s.span == *span;
+    let flag = |flags: &[Flag<'_>]| flags.iter().any(|f| f.value.as_ref().is_some_and(is));
+    let found = match instruction {
+        Instruction::From(from) => flag(&from.options) || is(&from.image),
+        Instruction::Add(AddInstruction { options, src, dest, .. })
+        | Instruction::Copy(CopyInstruction { options, src, dest, .. }) => {
+            flag(options)
+                || is(dest)
+                || src.iter().any(|src| matches!(src, Source::Path(path) if is(path)))
+        }
+        Instruction::Run(run) => flag(&run.options),
+        Instruction::Expose(expose) => expose.arguments.iter().any(is),
+        Instruction::Volume(volume) => match &volume.arguments {
+            JsonOrStringArray::Json(arguments) => arguments.value.iter().any(is),
+            JsonOrStringArray::String(arguments) => arguments.iter().any(is),
+        },
+        Instruction::User(UserInstruction { arguments, .. })
+        | Instruction::Workdir(WorkdirInstruction { arguments, .. })
+        | Instruction::Stopsignal(StopsignalInstruction { arguments, .. }) => {
+            return is(arguments).then_some(Position::Argument);
+        }
+        Instruction::Onbuild(onbuild) => return position(&onbuild.instruction, span),
+        _ => false,
+    };
+    found.then_some(Position::Word)
+}
+
+/// Escapes `$`, quotes, and the escape character in `value`.
+fn escape(value: &str, escape: u8) -> String {
+    let escape = escape as char;
+    let mut out = String::with_capacity(value.len());
+    for ch in value.chars() {
+        if matches!(ch, '$' | '\'' | '"') || ch == escape {
+            out.push(escape);
+        }
+        out.push(ch);
+    }
+    out
+}
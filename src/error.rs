@@ -87,6 +87,21 @@ pub(crate) fn json(arguments_start: usize) -> ErrorKind<'static> {
     ErrorKind::Json { arguments_start }
 }
 
+/// Creates an error that is not associated with the parser state, such as an
+/// error during applying edits.
+#[cold]
+#[inline(never)]
+pub(crate) fn new(msg: &'static str, pos: usize, text: &str) -> Error {
+    let (line, column) = find_location_from_pos(pos, text.as_bytes());
+    Error(Box::new(ErrorInner { msg: msg.into(), line, column }), PhantomData)
+}
+/// Creates an error that is not associated with any location.
+#[cold]
+#[inline(never)]
+pub(crate) fn without_location(msg: Box<str>) -> Error {
+    Error(Box::new(ErrorInner { msg, line: 0, column: 0 }), PhantomData)
+}
+
 #[derive(Debug)]
 struct ErrorInner {
     msg: Box<str>,
@@ -208,6 +223,18 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+/// An error which can be returned when parsing an [`InstructionKind`](crate::InstructionKind).
+#[derive(Debug)]
+pub struct ParseInstructionKindError(pub(crate) ());
+
+impl fmt::Display for ParseInstructionKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("unknown instruction")
+    }
+}
+
+impl std::error::Error for ParseInstructionKindError {}
+
 #[cold]
 fn find_location_from_pos(pos: usize, text: &[u8]) -> (usize, usize) {
     let line = find_line_from_pos(pos, text);
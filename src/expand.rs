@@ -0,0 +1,1071 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{
+    borrow::Cow,
+    collections::BTreeMap,
+    format,
+    string::{String, ToString as _},
+    vec,
+    vec::Vec,
+};
+use core::{
+    borrow::Borrow,
+    hash::{BuildHasher, Hash},
+    mem,
+    ops::Range,
+};
+use std::collections::HashMap;
+
+use crate::{
+    AddInstruction, ArgInstruction, CopyInstruction, DEFAULT_ESCAPE_BYTE, Dockerfile,
+    EnvInstruction, Flag, Instruction, JsonOrStringArray, LabelInstruction, Source, Span,
+    StopsignalInstruction, UTF8_BOM, UserInstruction, WorkdirInstruction, error, error::Result,
+};
+
+/// Variables used by [`Expander`].
+pub trait Env {
+    /// Returns the value of the variable `name`, or `None` if it is not set.
+    fn get(&self, name: &str) -> Option<&str>;
+}
+impl<E: ?Sized + Env> Env for &E {
+    fn get(&self, name: &str) -> Option<&str> {
+        (**self).get(name)
+    }
+}
+impl<K, V, S> Env for HashMap<K, V, S>
+where
+    K: Borrow<str> + Eq + Hash,
+    V: AsRef<str>,
+    S: BuildHasher,
+{
+    fn get(&self, name: &str) -> Option<&str> {
+        HashMap::get(self, name).map(AsRef::as_ref)
+    }
+}
+impl<K: Borrow<str> + Ord, V: AsRef<str>> Env for BTreeMap<K, V> {
+    fn get(&self, name: &str) -> Option<&str> {
+        BTreeMap::get(self, name).map(AsRef::as_ref)
+    }
+}
+/// If a variable appears multiple times, the last one is used.
+impl<K: AsRef<str>, V: AsRef<str>> Env for [(K, V)] {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.iter().rev().find(|(k, _)| k.as_ref() == name).map(|(_, v)| v.as_ref())
+    }
+}
+impl<K: AsRef<str>, V: AsRef<str>, const N: usize> Env for [(K, V); N] {
+    fn get(&self, name: &str) -> Option<&str> {
+        Env::get(&self[..], name)
+    }
+}
+impl<K: AsRef<str>, V: AsRef<str>> Env for Vec<(K, V)> {
+    fn get(&self, name: &str) -> Option<&str> {
+        Env::get(&self[..], name)
+    }
+}
+
+/// Build args used by analyses that don't take build args, so that only the
+/// default values of `ARG`s are used.
+pub(crate) const NO_BUILD_ARGS: &[(&str, &str)] = &[];
+
+/// Expands variables in the same way as BuildKit's `shell.Lex`.
+///
+/// The following forms are supported:
+///
+/// - `$name`, `${name}`
+/// - `${name:-word}`, `${name-word}`: `word` if `name` is unset or empty (`:-`)
+///   or unset (`-`), otherwise the value of `name`
+/// - `${name:+word}`, `${name+word}`: `word` if `name` is set and non-empty
+///   (`:+`) or set (`+`), otherwise empty
+/// - `${name:?message}`, `${name?message}`: error if `name` is unset or empty
+///   (`:?`) or unset (`?`), otherwise the value of `name`
+/// - `${name#pattern}`, `${name##pattern}`: removes the shortest (`#`) or
+///   longest (`##`) prefix matching `pattern`
+/// - `${name%pattern}`, `${name%%pattern}`: removes the shortest (`%`) or
+///   longest (`%%`) suffix matching `pattern`
+/// - `${name/pattern/replacement}`, `${name//pattern/replacement}`: replaces
+///   the first (`/`) or all (`//`) matches of `pattern`
+///
+/// Patterns support `*`, `?`, and `[...]`.
+///
+/// Single-quoted strings are not expanded, and the escape character escapes
+/// the next character (in double-quoted strings, only `"`, `$`, and the escape
+/// character can be escaped).
+///
+/// Use [`Dockerfile::expander`] to get an expander that uses the escape
+/// character of the dockerfile.
+///
+/// # Examples
+///
+/// ```
+/// use parse_dockerfile::Expander;
+///
+/// let env = [("FILES", "a.txt b.txt"), ("DIR", "/usr/local/bin")];
+/// let expander = Expander::new();
+///
+/// let expanded = expander.expand("${DIR%/*}/'$DIR'", &env).unwrap();
+/// assert_eq!(expanded.value, "/usr/local/$DIR");
+///
+/// let words = expander.expand_words("$FILES \"${DIR##*/} x\"", &env).unwrap();
+/// let words: Vec<_> = words.iter().map(|w| &*w.value).collect();
+/// assert_eq!(words, ["a.txt", "b.txt", "bin x"]);
+/// ```
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct Expander {
+    escape_byte: u8,
+    raw_quotes: bool,
+    raw_escapes: bool,
+    skip_unset: bool,
+}
+impl Expander {
+    /// Creates a new `Expander` with the default options.
+    pub const fn new() -> Self {
+        Self {
+            escape_byte: DEFAULT_ESCAPE_BYTE,
+            raw_quotes: false,
+            raw_escapes: false,
+            skip_unset: false,
+        }
+    }
+    /// Sets the escape character.
+    ///
+    /// Default: `\`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `escape` is neither `\` nor `` ` ``.
+    pub const fn escape(mut self, escape: char) -> Self {
+        match escape {
+            '\\' | '`' => self.escape_byte = escape as u8,
+            _ => panic!("escape character must be '\\' or '`'"),
+        }
+        self
+    }
+    /// Sets whether to keep quotes in the result.
+    ///
+    /// Quotes still prevent expansion (single quotes) and word splitting.
+    ///
+    /// Default: `false`
+    pub const fn raw_quotes(mut self, raw_quotes: bool) -> Self {
+        self.raw_quotes = raw_quotes;
+        self
+    }
+    /// Sets whether to keep escape characters in the result.
+    ///
+    /// Default: `false`
+    pub const fn raw_escapes(mut self, raw_escapes: bool) -> Self {
+        self.raw_escapes = raw_escapes;
+        self
+    }
+    /// Sets whether to keep references to unset variables (`$name` and
+    /// `${name}`) as is instead of expanding them to empty strings.
+    ///
+    /// Default: `false`
+    pub const fn skip_unset(mut self, skip_unset: bool) -> Self {
+        self.skip_unset = skip_unset;
+        self
+    }
+
+    /// Expands variables in `s` and removes quotes, without word splitting.
+    ///
+    /// This is how BuildKit processes most of instruction arguments, such as
+    /// `FROM` image, `WORKDIR` path, and values of `ENV` and `ARG`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `s` contains an unterminated quote or substitution,
+    /// an unsupported substitution, or `${name?message}` with unset `name`.
+    pub fn expand<'s, E: ?Sized + Env>(self, s: &'s str, env: &E) -> Result<Expanded<'s>> {
+        let mut sink = Words { split: false, ..Words::default() };
+        Lexer { s, pos: 0, options: self, env }.process(&mut sink, &[])?;
+        Ok(sink.current)
+    }
+    /// Expands variables in `s`, removes quotes, and splits the result into
+    /// words at unquoted whitespace.
+    ///
+    /// Whitespace in the values of unquoted variables also splits words.
+    /// This is how BuildKit processes arguments of instructions such as `COPY`,
+    /// `EXPOSE`, and `VOLUME`.
+    ///
+    /// # Errors
+    ///
+    /// See [`expand`](Self::expand).
+    pub fn expand_words<'s, E: ?Sized + Env>(
+        self,
+        s: &'s str,
+        env: &E,
+    ) -> Result<Vec<Expanded<'s>>> {
+        let mut sink = Words { split: true, ..Words::default() };
+        Lexer { s, pos: 0, options: self, env }.process(&mut sink, &[])?;
+        sink.finish_word();
+        Ok(sink.words)
+    }
+}
+impl Default for Expander {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dockerfile<'_> {
+    /// Returns an [`Expander`] that uses the escape character of this dockerfile.
+    ///
+    /// ```
+    /// use std::collections::HashMap;
+    ///
+    /// use parse_dockerfile::parse;
+    ///
+    /// let text = "ARG UBUNTU_VERSION=latest\nFROM ubuntu:${UBUNTU_VERSION:-22.04}\n";
+    /// let dockerfile = parse(text).unwrap();
+    /// let image = &dockerfile.stages().next().unwrap().from.image;
+    /// let env = HashMap::from([("UBUNTU_VERSION", "24.04")]);
+    ///
+    /// let expanded = dockerfile.expander().expand(&image.value, &env).unwrap();
+    /// assert_eq!(expanded.value, "ubuntu:24.04");
+    /// let substitution = &expanded.substitutions[0];
+    /// assert_eq!(substitution.name, "UBUNTU_VERSION");
+    /// assert_eq!(&image.value[substitution.value_range.clone()], "${UBUNTU_VERSION:-22.04}");
+    /// assert_eq!(&expanded.value[substitution.range.clone()], "24.04");
+    /// ```
+    pub fn expander(&self) -> Expander {
+        Expander { escape_byte: self.escape_byte, ..Expander::new() }
+    }
+}
+
+/// The result of [`Expander::expand`] and [`Expander::expand_words`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Expanded<'s> {
+    /// The expanded value.
+    pub value: String,
+    /// Substitutions in this value, in order of appearance.
+    pub substitutions: Vec<Substitution<'s>>,
+}
+
+/// A substituted part of [`Expanded`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Substitution<'s> {
+    /// The name of the variable.
+    pub name: &'s str,
+    /// The range of the substitution (e.g., `${name:-word}`) in the string
+    /// passed to [`Expander::expand`] or [`Expander::expand_words`].
+    ///
+    /// Note that this is not a span in the source text: the string is
+    /// usually [`UnescapedString::value`](crate::UnescapedString::value),
+    /// whose offsets differ from the source text after line continuations.
+    pub value_range: Range<usize>,
+    /// The range of the substituted text in [`Expanded::value`].
+    ///
+    /// If word splitting split the substituted text into multiple words,
+    /// each word has its own part.
+    pub range: Range<usize>,
+}
+
+/// Receives the output of [`Lexer`].
+trait Sink<'s> {
+    /// Appends `s`. If `quoted` is `false`, whitespace in `s` splits words.
+    fn push(&mut self, s: &str, quoted: bool);
+    /// Marks that a word exists even if it is empty (e.g., `""`).
+    fn mark_word(&mut self) {}
+    /// Appends the result of a substitution.
+    fn substitution(&mut self, name: &'s str, span: Span, value: &str, quoted: bool) {
+        let _ = (name, span);
+        self.push(value, quoted);
+    }
+}
+impl Sink<'_> for String {
+    fn push(&mut self, s: &str, _quoted: bool) {
+        self.push_str(s);
+    }
+}
+
+#[derive(Default)]
+struct Words<'s> {
+    words: Vec<Expanded<'s>>,
+    current: Expanded<'s>,
+    in_word: bool,
+    split: bool,
+}
+impl Words<'_> {
+    fn finish_word(&mut self) {
+        if self.in_word {
+            self.words.push(mem::take(&mut self.current));
+            self.in_word = false;
+        }
+    }
+}
+impl<'s> Sink<'s> for Words<'s> {
+    fn push(&mut self, s: &str, quoted: bool) {
+        if !self.split || quoted {
+            self.in_word = true;
+            self.current.value.push_str(s);
+            return;
+        }
+        for ch in s.chars() {
+            if ch.is_whitespace() {
+                self.finish_word();
+            } else {
+                self.in_word = true;
+                self.current.value.push(ch);
+            }
+        }
+    }
+    fn mark_word(&mut self) {
+        self.in_word = true;
+    }
+    fn substitution(&mut self, name: &'s str, span: Span, value: &str, quoted: bool) {
+        if !self.split || quoted {
+            let start = self.current.value.len();
+            self.push(value, true);
+            let range = start..self.current.value.len();
+            self.current.substitutions.push(Substitution { name, value_range: span, range });
+            return;
+        }
+        let mut start = None;
+        for ch in value.chars() {
+            if ch.is_whitespace() {
+                if let Some(start) = start.take() {
+                    let range = start..self.current.value.len();
+                    self.current.substitutions.push(Substitution {
+                        name,
+                        value_range: span.clone(),
+                        range,
+                    });
+                }
+                self.finish_word();
+            } else {
+                if start.is_none() {
+                    start = Some(self.current.value.len());
+                }
+                self.in_word = true;
+                self.current.value.push(ch);
+            }
+        }
+        if let Some(start) = start {
+            let range = start..self.current.value.len();
+            self.current.substitutions.push(Substitution { name, value_range: span, range });
+        }
+    }
+}
+
+fn push_char<'s, S: Sink<'s>>(sink: &mut S, ch: char, quoted: bool) {
+    sink.push(ch.encode_utf8(&mut [0; 4]), quoted);
+}
+
+struct Lexer<'s, 'e, E: ?Sized> {
+    s: &'s str,
+    pos: usize,
+    options: Expander,
+    env: &'e E,
+}
+
+impl<'s, E: ?Sized + Env> Lexer<'s, '_, E> {
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
+        Some(ch)
+    }
+    fn escape(&self) -> char {
+        char::from(self.options.escape_byte)
+    }
+
+    /// Processes the input until one of `stop` characters (which is consumed
+    /// and returned) or the end of the input.
+    fn process<S: Sink<'s>>(&mut self, sink: &mut S, stop: &[char]) -> Result<Option<char>> {
+        let escape = self.escape();
+        while let Some(ch) = self.next() {
+            match ch {
+                _ if stop.contains(&ch) => return Ok(Some(ch)),
+                _ if ch == escape => {
+                    if self.options.raw_escapes {
+                        push_char(sink, escape, true);
+                    }
+                    // An escape character at the end of the input is ignored.
+                    if let Some(ch) = self.next() {
+                        push_char(sink, ch, true);
+                    }
+                }
+                '\'' => self.single_quote(sink)?,
+                '"' => self.double_quote(sink)?,
+                '$' => self.dollar(sink, false)?,
+                _ => push_char(sink, ch, false),
+            }
+        }
+        Ok(None)
+    }
+    fn single_quote<S: Sink<'s>>(&mut self, sink: &mut S) -> Result<()> {
+        sink.mark_word();
+        let Some(len) = self.s[self.pos..].find('\'') else {
+            return Err(error::without_location(
+                "unexpected end of statement while looking for matching single-quote".into(),
+            ));
+        };
+        let start = if self.options.raw_quotes { self.pos - 1 } else { self.pos };
+        self.pos += len + 1;
+        let end = if self.options.raw_quotes { self.pos } else { self.pos - 1 };
+        sink.push(&self.s[start..end], true);
+        Ok(())
+    }
+    fn double_quote<S: Sink<'s>>(&mut self, sink: &mut S) -> Result<()> {
+        sink.mark_word();
+        if self.options.raw_quotes {
+            sink.push("\"", true);
+        }
+        let escape = self.escape();
+        loop {
+            let Some(ch) = self.next() else {
+                return Err(error::without_location(
+                    "unexpected end of statement while looking for matching double-quote".into(),
+                ));
+            };
+            match ch {
+                '"' => break,
+                '$' => self.dollar(sink, true)?,
+                _ if ch == escape => match self.peek() {
+                    // An escape character at the end of the input is ignored.
+                    None => {}
+                    Some(next) if next == '"' || next == '$' || next == escape => {
+                        if self.options.raw_escapes {
+                            push_char(sink, escape, true);
+                        }
+                        self.pos += next.len_utf8();
+                        push_char(sink, next, true);
+                    }
+                    // Other characters cannot be escaped, and the escape
+                    // character is kept.
+                    Some(_) => push_char(sink, escape, true),
+                },
+                _ => push_char(sink, ch, true),
+            }
+        }
+        if self.options.raw_quotes {
+            sink.push("\"", true);
+        }
+        Ok(())
+    }
+
+    /// Reads a variable name, like BuildKit's `processName`.
+    fn name(&mut self) -> &'s str {
+        let start = self.pos;
+        match self.peek() {
+            Some(ch) if ch.is_ascii_digit() => {
+                while self.peek().is_some_and(|ch| ch.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+            }
+            // Special parameters.
+            Some('@' | '*' | '#' | '?' | '-' | '$' | '!') => self.pos += 1,
+            _ => {
+                while let Some(ch) = self.peek() {
+                    if !ch.is_alphanumeric() && ch != '_' {
+                        break;
+                    }
+                    self.pos += ch.len_utf8();
+                }
+            }
+        }
+        &self.s[start..self.pos]
+    }
+
+    /// Processes a word in a substitution until `stop`, without word
+    /// splitting.
+    fn word(&mut self, stop: &[char], raw_escapes: bool) -> Result<(String, Option<char>)> {
+        let mut word = String::new();
+        let options = self.options;
+        self.options.raw_escapes |= raw_escapes;
+        let res = self.process(&mut word, stop);
+        self.options = options;
+        let found = res?;
+        if found.is_none() {
+            return Err(error::without_location("syntax error: missing '}'".into()));
+        }
+        Ok((word, found))
+    }
+
+    /// Processes `$...`. The `$` has already been consumed.
+    fn dollar<S: Sink<'s>>(&mut self, sink: &mut S, quoted: bool) -> Result<()> {
+        let start = self.pos - 1;
+        if self.peek() != Some('{') {
+            let name = self.name();
+            if name.is_empty() {
+                sink.push("$", quoted);
+                return Ok(());
+            }
+            match self.env.get(name) {
+                Some(value) => sink.substitution(name, start..self.pos, value, quoted),
+                None if self.options.skip_unset => sink.push(&self.s[start..self.pos], true),
+                None => sink.substitution(name, start..self.pos, "", quoted),
+            }
+            return Ok(());
+        }
+        self.pos += 1;
+        let name = self.name();
+        let Some(modifier) = self.next() else {
+            return Err(error::without_location("syntax error: missing '}'".into()));
+        };
+        if name.is_empty() {
+            return Err(error::without_location("bad substitution".into()));
+        }
+        let value = self.env.get(name);
+        let result: Cow<'_, str> = match modifier {
+            '}' => match value {
+                Some(value) => value.into(),
+                None if self.options.skip_unset => {
+                    sink.push(&self.s[start..self.pos], true);
+                    return Ok(());
+                }
+                None => "".into(),
+            },
+            ':' => {
+                let modifier = self.next();
+                let (word, _) = self.word(&['}'], false)?;
+                let value = value.filter(|v| !v.is_empty());
+                match modifier {
+                    Some('-') => value.map_or(word.into(), Cow::Borrowed),
+                    Some('+') => {
+                        if value.is_some() {
+                            word.into()
+                        } else {
+                            "".into()
+                        }
+                    }
+                    Some('?') => match value {
+                        Some(value) => value.into(),
+                        None => {
+                            let msg =
+                                if word.is_empty() { "is not allowed to be empty" } else { &word };
+                            return Err(error::without_location(format!("{name}: {msg}").into()));
+                        }
+                    },
+                    _ => {
+                        return Err(error::without_location(
+                            format!(
+                                "unsupported modifier (:{}) in substitution",
+                                modifier.map(|m| m.to_string()).unwrap_or_default()
+                            )
+                            .into(),
+                        ));
+                    }
+                }
+            }
+            '-' => {
+                let (word, _) = self.word(&['}'], false)?;
+                value.map_or(word.into(), Cow::Borrowed)
+            }
+            '+' => {
+                let (word, _) = self.word(&['}'], false)?;
+                if value.is_some() { word.into() } else { "".into() }
+            }
+            '?' => {
+                let (word, _) = self.word(&['}'], false)?;
+                match value {
+                    Some(value) => value.into(),
+                    None => {
+                        let msg =
+                            if word.is_empty() { "is not allowed to be unset" } else { &word };
+                        return Err(error::without_location(format!("{name}: {msg}").into()));
+                    }
+                }
+            }
+            '#' | '%' => {
+                let longest = self.peek() == Some(modifier);
+                if longest {
+                    self.pos += 1;
+                }
+                let (pattern, _) = self.word(&['}'], true)?;
+                let value = value.unwrap_or_default();
+                let pattern = Pattern::new(&pattern, self.escape());
+                if modifier == '#' {
+                    pattern.trim_prefix(value, longest).into()
+                } else {
+                    pattern.trim_suffix(value, longest).into()
+                }
+            }
+            '/' => {
+                let all = self.peek() == Some('/');
+                if all {
+                    self.pos += 1;
+                }
+                let (pattern, found) = self.word(&['/', '}'], true)?;
+                let replacement =
+                    if found == Some('/') { self.word(&['}'], false)?.0 } else { String::new() };
+                let value = value.unwrap_or_default();
+                Pattern::new(&pattern, self.escape()).replace(value, &replacement, all).into()
+            }
+            _ => {
+                return Err(error::without_location(
+                    format!("unsupported modifier ({modifier}) in substitution").into(),
+                ));
+            }
+        };
+        sink.substitution(name, start..self.pos, &result, quoted);
+        Ok(())
+    }
+}
+
+/// A shell pattern used in `${name#pattern}`, etc.
+struct Pattern {
+    tokens: Vec<Token>,
+}
+
+enum Token {
+    Char(char),
+    AnyChar,
+    AnyString,
+    /// `[...]`: ranges and whether it is negated.
+    Class(Vec<(char, char)>, bool),
+}
+
+impl Token {
+    /// Returns whether this matches `ch`. `*` is handled by [`Pattern::matches`].
+    fn matches(&self, ch: char) -> bool {
+        match self {
+            Self::Char(c) => *c == ch,
+            Self::AnyChar => true,
+            Self::Class(ranges, negated) => {
+                ranges.iter().any(|&(lo, hi)| lo <= ch && ch <= hi) != *negated
+            }
+            Self::AnyString => false,
+        }
+    }
+}
+
+impl Pattern {
+    fn new(pattern: &str, escape: char) -> Self {
+        let chars: Vec<char> = pattern.chars().collect();
+        let mut tokens = Vec::with_capacity(chars.len());
+        let mut i = 0;
+        while i < chars.len() {
+            let ch = chars[i];
+            i += 1;
+            match ch {
+                '*' => tokens.push(Token::AnyString),
+                '?' => tokens.push(Token::AnyChar),
+                '[' => match Self::class(&chars[i..], escape) {
+                    Some((class, len)) => {
+                        tokens.push(class);
+                        i += len;
+                    }
+                    None => tokens.push(Token::Char('[')),
+                },
+                _ if ch == escape && i < chars.len() => {
+                    tokens.push(Token::Char(chars[i]));
+                    i += 1;
+                }
+                _ => tokens.push(Token::Char(ch)),
+            }
+        }
+        Self { tokens }
+    }
+    /// Parses `[...]` after `[`. Returns `None` if it is not terminated.
+    fn class(chars: &[char], escape: char) -> Option<(Token, usize)> {
+        let mut i = 0;
+        let negated = matches!(chars.first(), Some('!' | '^'));
+        if negated {
+            i += 1;
+        }
+        let mut ranges = Vec::new();
+        let mut first = true;
+        loop {
+            let mut ch = *chars.get(i)?;
+            i += 1;
+            if ch == ']' && !first {
+                return Some((Token::Class(ranges, negated), i));
+            }
+            first = false;
+            if ch == escape {
+                ch = *chars.get(i)?;
+                i += 1;
+            }
+            if chars.get(i) == Some(&'-') && chars.get(i + 1).is_some_and(|&c| c != ']') {
+                ranges.push((ch, chars[i + 1]));
+                i += 2;
+            } else {
+                ranges.push((ch, ch));
+            }
+        }
+    }
+
+    fn matches(&self, s: &[char]) -> bool {
+        // Backtracking only to the last `*` is enough, since any match of an
+        // earlier `*` can be extended by the later one.
+        let tokens = &self.tokens;
+        let (mut t, mut i) = (0, 0);
+        let mut star: Option<(usize, usize)> = None;
+        while i < s.len() {
+            match tokens.get(t) {
+                Some(Token::AnyString) => {
+                    star = Some((t, i));
+                    t += 1;
+                }
+                Some(token) if token.matches(s[i]) => {
+                    t += 1;
+                    i += 1;
+                }
+                _ => match &mut star {
+                    // Let the last `*` consume one more character.
+                    Some((star_t, star_i)) => {
+                        *star_i += 1;
+                        t = *star_t + 1;
+                        i = *star_i;
+                    }
+                    None => return false,
+                },
+            }
+        }
+        tokens[t..].iter().all(|token| matches!(token, Token::AnyString))
+    }
+
+    fn trim_prefix(&self, value: &str, longest: bool) -> String {
+        let chars: Vec<char> = value.chars().collect();
+        let mut lens: Vec<usize> = (0..=chars.len()).collect();
+        if longest {
+            lens.reverse();
+        }
+        match lens.into_iter().find(|&i| self.matches(&chars[..i])) {
+            Some(i) => chars[i..].iter().collect(),
+            None => String::from(value),
+        }
+    }
+    fn trim_suffix(&self, value: &str, longest: bool) -> String {
+        let chars: Vec<char> = value.chars().collect();
+        let mut starts: Vec<usize> = (0..=chars.len()).collect();
+        if !longest {
+            starts.reverse();
+        }
+        match starts.into_iter().find(|&i| self.matches(&chars[i..])) {
+            Some(i) => chars[..i].iter().collect(),
+            None => String::from(value),
+        }
+    }
+    fn replace(&self, value: &str, replacement: &str, all: bool) -> String {
+        let chars: Vec<char> = value.chars().collect();
+        let mut out = String::with_capacity(value.len());
+        let mut i = 0;
+        let mut replaced = false;
+        while i < chars.len() {
+            if !replaced || all {
+                // The longest non-empty match at this position.
+                if let Some(end) = (i + 1..=chars.len()).rev().find(|&j| self.matches(&chars[i..j]))
+                {
+                    out.push_str(replacement);
+                    i = end;
+                    replaced = true;
+                    continue;
+                }
+            }
+            out.push(chars[i]);
+            i += 1;
+        }
+        out
+    }
+}
+
+/// A reference to a variable, returned by [`Dockerfile::variable_references`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VariableReference<'t> {
+    /// The name of the variable.
+    pub name: &'t str,
+    /// The operator of the substitution, or `None` for `$name` and `${name}`.
+    pub operator: Option<Operator>,
+    /// The span of the substitution (e.g., `${name:-word}`) in the text.
+    pub span: Span,
+    /// The index of the instruction in [`Dockerfile::instructions`].
+    pub instruction: usize,
+}
+
+/// The operator of a substitution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operator {
+    /// `${name:-word}`
+    DefaultIfEmpty,
+    /// `${name-word}`
+    DefaultIfUnset,
+    /// `${name:+word}`
+    AlternativeIfNonEmpty,
+    /// `${name+word}`
+    AlternativeIfSet,
+    /// `${name:?message}`
+    ErrorIfEmpty,
+    /// `${name?message}`
+    ErrorIfUnset,
+    /// `${name#pattern}`
+    RemoveShortestPrefix,
+    /// `${name##pattern}`
+    RemoveLongestPrefix,
+    /// `${name%pattern}`
+    RemoveShortestSuffix,
+    /// `${name%%pattern}`
+    RemoveLongestSuffix,
+    /// `${name/pattern/replacement}`
+    ReplaceFirst,
+    /// `${name//pattern/replacement}`
+    ReplaceAll,
+}
+
+impl Dockerfile<'_> {
+    /// Returns references to variables in the instructions, in order of
+    /// appearance.
+    ///
+    /// `text` must be the text from which this dockerfile was parsed.
+    ///
+    /// Only the parts that BuildKit expands are scanned: the image and flags
+    /// of `FROM`, flags, sources, and destination of `ADD` and `COPY`
+    /// (including here-documents whose delimiter is not quoted), arguments of
+    /// `ARG`, `ENV`, `LABEL`, `EXPOSE`, `USER`, `WORKDIR`, `VOLUME`, and
+    /// `STOPSIGNAL`, flags of `RUN`, and instructions in `ONBUILD`. Commands
+    /// of `RUN`, `CMD`, `ENTRYPOINT`, etc. are expanded by the shell at
+    /// runtime, so they are not scanned.
+    ///
+    /// Variables are not evaluated, and references in single quotes or
+    /// escaped with the escape character are ignored.
+    ///
+    /// ```
+    /// use parse_dockerfile::Operator;
+    ///
+    /// let text = "FROM alpine\nWORKDIR ${HOME:-/root}/$APP\nRUN echo $SHELL\n";
+    /// let dockerfile = parse_dockerfile::parse(text).unwrap();
+    /// let references = dockerfile.variable_references(text);
+    /// let references: Vec<_> =
+    ///     references.iter().map(|r| (r.name, r.operator, &text[r.span.clone()])).collect();
+    /// assert_eq!(references, [
+    ///     ("HOME", Some(Operator::DefaultIfEmpty), "${HOME:-/root}"),
+    ///     ("APP", None, "$APP"),
+    /// ]);
+    /// ```
+    #[must_use]
+    pub fn variable_references<'t>(&self, text: &'t str) -> Vec<VariableReference<'t>> {
+        let text =
+            if text.as_bytes().starts_with(UTF8_BOM) { &text[UTF8_BOM.len()..] } else { text };
+        let mut scanner =
+            Scanner { text, pos: 0, escape: self.escape_byte, instruction: 0, references: vec![] };
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            scanner.instruction = i;
+            scanner.instruction(instruction);
+        }
+        let mut references = scanner.references;
+        references.sort_by_key(|r| r.span.start);
+        references
+    }
+}
+
+/// Returns references to variables in `s`. Spans are relative to `s`.
+pub(crate) fn references_in(s: &str, escape: u8) -> Vec<VariableReference<'_>> {
+    let mut scanner = Scanner { text: s, pos: 0, escape, instruction: 0, references: vec![] };
+    scanner.span(&(0..s.len()), true);
+    scanner.references
+}
+
+/// Returns references in `s` to variables that are not set in `env`, i.e.,
+/// references whose values cannot be determined. Spans are relative to `s`.
+///
+/// References with operators (e.g., `${name:-word}`) and references nested
+/// in them are ignored, since they are evaluated only when needed.
+pub(crate) fn unset_references<'s, E: ?Sized + Env>(
+    s: &'s str,
+    escape: u8,
+    env: &E,
+) -> Vec<VariableReference<'s>> {
+    let mut references = references_in(s, escape);
+    let mut end = 0;
+    references.retain(|r| {
+        let nested = r.span.start < end;
+        end = end.max(r.span.end);
+        !nested && r.operator.is_none() && env.get(r.name).is_none()
+    });
+    references
+}
+
+/// Scans references in raw text.
+struct Scanner<'t> {
+    text: &'t str,
+    pos: usize,
+    escape: u8,
+    instruction: usize,
+    references: Vec<VariableReference<'t>>,
+}
+
+impl<'t> Scanner<'t> {
+    fn instruction(&mut self, instruction: &Instruction<'_>) {
+        match instruction {
+            Instruction::From(from) => {
+                self.flags(&from.options);
+                self.span(&from.image.span, true);
+            }
+            Instruction::Add(AddInstruction { options, src, dest, .. })
+            | Instruction::Copy(CopyInstruction { options, src, dest, .. }) => {
+                self.flags(options);
+                for src in src {
+                    match src {
+                        Source::Path(path) => self.span(&path.span, true),
+                        // Quotes in here-documents are not special.
+                        Source::HereDoc(here_doc) if here_doc.expand => {
+                            self.span(&here_doc.span, false);
+                        }
+                        Source::HereDoc(..) => {}
+                    }
+                }
+                self.span(&dest.span, true);
+            }
+            Instruction::Arg(ArgInstruction { arguments, .. })
+            | Instruction::Env(EnvInstruction { arguments, .. })
+            | Instruction::Label(LabelInstruction { arguments, .. })
+            | Instruction::User(UserInstruction { arguments, .. })
+            | Instruction::Workdir(WorkdirInstruction { arguments, .. })
+            | Instruction::Stopsignal(StopsignalInstruction { arguments, .. }) => {
+                self.span(&arguments.span, true);
+            }
+            Instruction::Expose(expose) => {
+                for argument in &expose.arguments {
+                    self.span(&argument.span, true);
+                }
+            }
+            Instruction::Volume(volume) => {
+                let arguments = match &volume.arguments {
+                    JsonOrStringArray::Json(arguments) => &arguments.value,
+                    JsonOrStringArray::String(arguments) => arguments,
+                };
+                for argument in arguments {
+                    self.span(&argument.span, true);
+                }
+            }
+            Instruction::Run(run) => self.flags(&run.options),
+            Instruction::Onbuild(onbuild) => self.instruction(&onbuild.instruction),
+            _ => {}
+        }
+    }
+    fn flags(&mut self, flags: &[Flag<'_>]) {
+        for flag in flags {
+            if let Some(value) = &flag.value {
+                self.span(&value.span, true);
+            }
+        }
+    }
+    fn span(&mut self, span: &Span, quotes: bool) {
+        let text = self.text;
+        self.text = &text[..span.end];
+        self.pos = span.start;
+        self.scan(&[], quotes);
+        self.text = text;
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+    /// Scans until one of `stop` characters (which is consumed) outside
+    /// quotes, or the end of the text.
+    fn scan(&mut self, stop: &[u8], quotes: bool) -> Option<u8> {
+        let mut in_double_quote = false;
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                _ if !in_double_quote && stop.contains(&b) => return Some(b),
+                _ if b == self.escape => {
+                    // Skips the escaped character (or newline of line continuation).
+                    if let Some(ch) = self.text[self.pos..].chars().next() {
+                        self.pos += ch.len_utf8();
+                    }
+                }
+                b'\'' if quotes && !in_double_quote => match self.text[self.pos..].find('\'') {
+                    Some(len) => self.pos += len + 1,
+                    None => self.pos = self.text.len(),
+                },
+                b'"' if quotes => in_double_quote = !in_double_quote,
+                b'$' => self.dollar(quotes),
+                _ => {}
+            }
+        }
+        None
+    }
+    /// Scans `$...`. The `$` has already been consumed.
+    fn dollar(&mut self, quotes: bool) {
+        let start = self.pos - 1;
+        let braced = self.peek() == Some(b'{');
+        if braced {
+            self.pos += 1;
+        }
+        let name = self.name();
+        if name.is_empty() {
+            return;
+        }
+        let index = self.references.len();
+        self.references.push(VariableReference {
+            name,
+            operator: None,
+            span: start..self.pos,
+            instruction: self.instruction,
+        });
+        if !braced {
+            return;
+        }
+        let Some(b) = self.peek() else { return };
+        self.pos += 1;
+        let double = |this: &mut Self, single: Operator, double: Operator| {
+            if this.peek() == Some(b) {
+                this.pos += 1;
+                double
+            } else {
+                single
+            }
+        };
+        let operator = match b {
+            b'}' => None,
+            b':' => {
+                let operator = match self.peek() {
+                    Some(b'-') => Operator::DefaultIfEmpty,
+                    Some(b'+') => Operator::AlternativeIfNonEmpty,
+                    Some(b'?') => Operator::ErrorIfEmpty,
+                    _ => return,
+                };
+                self.pos += 1;
+                Some(operator)
+            }
+            b'-' => Some(Operator::DefaultIfUnset),
+            b'+' => Some(Operator::AlternativeIfSet),
+            b'?' => Some(Operator::ErrorIfUnset),
+            b'#' => {
+                Some(double(self, Operator::RemoveShortestPrefix, Operator::RemoveLongestPrefix))
+            }
+            b'%' => {
+                Some(double(self, Operator::RemoveShortestSuffix, Operator::RemoveLongestSuffix))
+            }
+            b'/' => Some(double(self, Operator::ReplaceFirst, Operator::ReplaceAll)),
+            _ => return,
+        };
+        if operator.is_some() {
+            let stop: &[u8] =
+                if matches!(operator, Some(Operator::ReplaceFirst | Operator::ReplaceAll)) {
+                    b"/}"
+                } else {
+                    b"}"
+                };
+            if self.scan(stop, quotes) == Some(b'/') {
+                self.scan(b"}", quotes);
+            }
+        }
+        self.references[index].operator = operator;
+        self.references[index].span.end = self.pos;
+    }
+    /// Reads a variable name, like `Lexer::name`.
+    fn name(&mut self) -> &'t str {
+        let start = self.pos;
+        let rest = &self.text[start..];
+        let len = match rest.as_bytes().first() {
+            Some(b) if b.is_ascii_digit() => rest.bytes().take_while(u8::is_ascii_digit).count(),
+            Some(b'@' | b'*' | b'#' | b'?' | b'-' | b'$' | b'!') => 1,
+            _ => rest
+                .char_indices()
+                .find(|&(_, ch)| !ch.is_alphanumeric() && ch != '_')
+                .map_or(rest.len(), |(i, _)| i),
+        };
+        self.pos += len;
+        &self.text[start..self.pos]
+    }
+}
@@ -0,0 +1,307 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{string::String, vec, vec::Vec};
+use std::collections::HashSet;
+
+use crate::{
+    Dockerfile, Instruction, Span, StageBase, TextEdit, UTF8_BOM,
+    expand::{NO_BUILD_ARGS, references_in, unset_references},
+    scope::Scopes,
+    words,
+};
+
+/// The result of [`Dockerfile::flatten_stages`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StageFlattening {
+    /// Edits that merge stages.
+    ///
+    /// Apply them with [`apply_edits`](crate::apply_edits).
+    pub edits: Vec<TextEdit>,
+    /// Chains of indices of merged stages.
+    ///
+    /// Each chain is merged into the position of its last stage, which keeps
+    /// its name.
+    pub merged: Vec<Vec<usize>>,
+}
+
+impl Dockerfile<'_> {
+    /// Returns edits that inline chains of intermediate stages that are only
+    /// used as the base of the next stage.
+    ///
+    /// `text` must be the text from which this dockerfile was parsed.
+    ///
+    /// For example, `FROM alpine AS a` + `FROM a AS b` is merged into a stage
+    /// that starts with `FROM alpine AS b` and contains instructions of both
+    /// stages.
+    ///
+    /// A stage is not merged into the next stage if:
+    ///
+    /// - it is referenced by other than the `FROM` of the next stage (e.g.,
+    ///   `COPY --from`, `RUN --mount=from=`)
+    /// - it or a stage merged into it declares an `ARG` and the next stage
+    ///   uses or declares the `ARG`, or has `RUN` instructions, because the
+    ///   scope of the `ARG` would change (`ARG` is also visible to `RUN` as
+    ///   an environment variable)
+    /// - it has `ONBUILD` instructions, which are triggered only when used as
+    ///   a base
+    /// - the `FROM` of the next stage has flags such as `--platform`
+    ///
+    /// Also, no stages are merged if the dockerfile has stage references that
+    /// cannot be resolved statically (i.e., refer to variables that are not
+    /// set without build args) or that refer to stages by index, since
+    /// merging stages changes indices of stages.
+    ///
+    /// Comment lines directly preceding the `FROM` of a merged stage other
+    /// than the last stage of the chain are removed along with the stage.
+    ///
+    /// ```
+    /// let text = "FROM alpine AS base\nRUN apk add make\nFROM base AS build\nRUN make\nFROM build\nCMD [\"app\"]\n";
+    /// let dockerfile = parse_dockerfile::parse(text).unwrap();
+    /// let flattening = dockerfile.flatten_stages(text);
+    /// assert_eq!(flattening.merged, [[0, 1, 2]]);
+    /// let new = parse_dockerfile::apply_edits(text, &flattening.edits).unwrap();
+    /// assert_eq!(new, "FROM alpine\nRUN apk add make\nRUN make\nCMD [\"app\"]\n");
+    /// ```
+    #[must_use]
+    pub fn flatten_stages(&self, text: &str) -> StageFlattening {
+        let mut flattening = StageFlattening { edits: vec![], merged: vec![] };
+        let text =
+            if text.as_bytes().starts_with(UTF8_BOM) { &text[UTF8_BOM.len()..] } else { text };
+        let Ok(scopes) = self.scopes(NO_BUILD_ARGS) else { return flattening };
+        let n = self.stages.len();
+
+        // Count references to each stage.
+        let mut from_refs = vec![0_usize; n];
+        let mut other_refs = vec![0_usize; n];
+        for stage in self.stages() {
+            let start = self.stages[stage.index()].start;
+            match stage.base() {
+                StageBase::Stage(base) => from_refs[base.index()] += 1,
+                StageBase::Image(image)
+                    if !references_in(&image.value, self.escape_byte).is_empty() =>
+                {
+                    // A stage referenced via variables cannot be merged since
+                    // the reference would no longer refer to the stage.
+                    match self.resolve_flattening_ref(&image.value, &scopes, start) {
+                        Ok(Some(base)) => other_refs[base] += 1,
+                        Ok(None) => {}
+                        Err(()) => return flattening,
+                    }
+                }
+                _ => {}
+            }
+            for (i, instruction) in stage.instructions.iter().enumerate() {
+                for from in stage_refs(instruction) {
+                    match self.resolve_flattening_ref(&from, &scopes, start + 1 + i) {
+                        Ok(Some(index)) => other_refs[index] += 1,
+                        Ok(None) => {}
+                        Err(()) => return flattening,
+                    }
+                }
+            }
+        }
+
+        // next[i] is the stage into which stage i is merged.
+        let mut next = vec![None; n];
+        // carried[i] is the set of ARGs declared in stages merged into stage i.
+        let mut carried: Vec<HashSet<&str>> = vec![HashSet::new(); n];
+        for stage in self.stages() {
+            let StageBase::Stage(base) = stage.base() else { continue };
+            let i = base.index();
+            if from_refs[i] != 1 || other_refs[i] != 0 || !stage.from.options.is_empty() {
+                continue;
+            }
+            if base.instructions.iter().any(|i| matches!(i, Instruction::Onbuild(..))) {
+                continue;
+            }
+            let mut declared: HashSet<&str> = base
+                .instructions
+                .iter()
+                .filter_map(|i| match i {
+                    Instruction::Arg(arg) => {
+                        Some(words::key_values(&arg.arguments, self.escape_byte, false))
+                    }
+                    _ => None,
+                })
+                .flatten()
+                .map(|kv| kv.key)
+                .collect();
+            declared.extend(&carried[i]);
+            let conflict = stage.instructions.iter().any(|i| {
+                let raw = &text[i.span()];
+                let redeclared = match i {
+                    Instruction::Arg(arg) => {
+                        words::key_values(&arg.arguments, self.escape_byte, false)
+                            .iter()
+                            .any(|kv| declared.contains(kv.key))
+                    }
+                    Instruction::Run(..) => !declared.is_empty(),
+                    _ => false,
+                };
+                redeclared
+                    || references_in(raw, self.escape_byte)
+                        .iter()
+                        .any(|r| declared.contains(r.name))
+            });
+            if !conflict {
+                next[i] = Some(stage.index());
+                carried[stage.index()] = declared;
+            }
+        }
+
+        let mut has_prev = vec![false; n];
+        for &j in next.iter().flatten() {
+            has_prev[j] = true;
+        }
+        for start in (0..n).filter(|&i| !has_prev[i] && next[i].is_some()) {
+            let mut chain = vec![start];
+            let mut i = start;
+            while let Some(j) = next[i] {
+                chain.push(j);
+                i = j;
+            }
+            flattening.edits.extend(self.merge_chain(text, &chain));
+            flattening.merged.push(chain);
+        }
+        flattening
+    }
+
+    fn merge_chain(&self, text: &str, chain: &[usize]) -> Vec<TextEdit> {
+        let (&last, merged) = chain.split_last().unwrap();
+        let first = self.stage_at(chain[0]);
+        let last = self.stage_at(last);
+        let from = first.from;
+
+        // The FROM of the first stage, with the name of the last stage.
+        let from_span = self.instructions[self.stages[chain[0]].start].span();
+        let mut replacement = String::with_capacity(text.len() / 4);
+        let (as_, name) = from.as_.as_ref().unwrap();
+        match last.name() {
+            Some(last_name) => {
+                replacement.push_str(&text[from_span.start..name.span.start]);
+                replacement.push_str(&text[last_name.span.clone()]);
+            }
+            None => {
+                let end = text[..as_.span.start].trim_end_matches([' ', '\t']).len();
+                replacement.push_str(&text[from_span.start..end]);
+            }
+        }
+        replacement.push_str(&text[name.span.end..from_span.end]);
+
+        let mut edits = Vec::with_capacity(chain.len());
+        for &i in merged {
+            let stage = self.stage_at(i);
+            let from_end = self.instructions[self.stages[i].start].span().end;
+            replacement.push_str(&text[from_end..stage.span().end]);
+            let start = self.leading_comments_start(text, self.stages[i].start);
+            edits.push(stage_removal(text, start..stage.span().end));
+        }
+        let last_from_span = self.instructions[self.stages[last.index()].start].span();
+        edits.push(TextEdit::replace(last_from_span, replacement));
+        edits
+    }
+
+    /// Returns the start of the comment lines directly preceding the
+    /// instruction at `index`, or the start of the instruction if there are
+    /// none.
+    fn leading_comments_start(&self, text: &str, index: usize) -> usize {
+        let mut start = self.instructions[index].span().start;
+        // Comments cannot precede the previous instruction or parser directives.
+        let limit = index.checked_sub(1).map_or_else(
+            || {
+                let directives = &self.parser_directives;
+                [
+                    directives.syntax.as_ref().map(|d| d.span().end),
+                    directives.escape.as_ref().map(|d| d.span().end),
+                    directives.check.as_ref().map(|d| d.span().end),
+                ]
+                .into_iter()
+                .flatten()
+                .max()
+                .unwrap_or(0)
+            },
+            |prev| self.instructions[prev].span().end,
+        );
+        while let Some(line_end) = text[..start].rfind('\n') {
+            let line_start = text[..line_end].rfind('\n').map_or(0, |i| i + 1);
+            if line_start < limit
+                || !text[line_start..line_end].trim_start_matches([' ', '\t']).starts_with('#')
+            {
+                break;
+            }
+            start = line_start;
+        }
+        start
+    }
+
+    /// Resolves a stage reference in the instruction at `index` for
+    /// [`flatten_stages`](Self::flatten_stages).
+    ///
+    /// Returns `Err` if the reference cannot be resolved statically or refers
+    /// to a stage by index.
+    fn resolve_flattening_ref(
+        &self,
+        from: &str,
+        scopes: &Scopes<'_>,
+        index: usize,
+    ) -> Result<Option<usize>, ()> {
+        let scope = scopes.at(index);
+        if !unset_references(from, self.escape_byte, scope).is_empty() {
+            return Err(());
+        }
+        let from = self.expander().expand(from, scope).map_err(|_| ())?.value;
+        if !from.is_empty() && from.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(());
+        }
+        Ok(self.stage(&from).map(|s| s.index()))
+    }
+}
+
+/// Returns an edit that removes the stage at `span`.
+///
+/// If the stage is preceded by a blank line, the following blank lines are
+/// also removed so that blank lines between stages don't accumulate.
+fn stage_removal(text: &str, span: Span) -> TextEdit {
+    let start = text[..span.start].trim_end_matches([' ', '\t']).len();
+    if start != 0 && !text[..start].ends_with('\n') {
+        return TextEdit::delete_lines(span);
+    }
+    let skip_line = |pos: usize| {
+        let rest = &text[pos..];
+        let trimmed = rest.trim_start_matches([' ', '\t', '\r']);
+        trimmed.starts_with('\n').then(|| pos + (rest.len() - trimmed.len()) + 1)
+    };
+    let Some(mut end) = skip_line(span.end).or_else(|| {
+        (text[span.end..].trim_matches([' ', '\t', '\r']).is_empty()).then_some(text.len())
+    }) else {
+        return TextEdit::delete_lines(span);
+    };
+    // Whether the previous line is blank (or there is no previous line).
+    let blank_before = start == 0 || {
+        let prev = text[..start - 1].trim_end_matches([' ', '\t', '\r']);
+        prev.is_empty() || prev.ends_with('\n')
+    };
+    if blank_before {
+        while let Some(next) = skip_line(end) {
+            end = next;
+        }
+    }
+    TextEdit::delete(start..end)
+}
+
+/// Returns the values of `COPY --from` and `RUN --mount=from=` in `instruction`.
+fn stage_refs(instruction: &Instruction<'_>) -> Vec<String> {
+    match instruction {
+        Instruction::Copy(copy) => copy
+            .options
+            .iter()
+            .filter(|f| f.name.value == "from")
+            .filter_map(|f| Some(String::from(&*f.value.as_ref()?.value)))
+            .collect(),
+        Instruction::Run(run) => {
+            run.options.iter().filter_map(|f| Some(words::mount_from(f)?.value)).collect()
+        }
+        _ => vec![],
+    }
+}
@@ -0,0 +1,595 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{string::String, vec::Vec};
+use core::mem;
+
+use crate::{
+    Command, HealthcheckArguments, Instruction, Span,
+    syntax::{InstructionNode, SyntaxElement, SyntaxKind, SyntaxToken, SyntaxTree},
+};
+
+/// Style of the text formatted by [`SyntaxTree::format`].
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct FormatStyle {
+    indent_width: usize,
+    blank_line_between_stages: bool,
+    split_commands: bool,
+}
+impl FormatStyle {
+    /// Creates a new `FormatStyle` with the default style.
+    pub const fn new() -> Self {
+        Self { indent_width: 4, blank_line_between_stages: true, split_commands: true }
+    }
+    /// Sets the number of spaces used to indent continuation lines.
+    ///
+    /// Default is `4`.
+    pub const fn indent_width(mut self, indent_width: usize) -> Self {
+        self.indent_width = indent_width;
+        self
+    }
+    /// Sets whether to insert a blank line between stages.
+    ///
+    /// Default is `true`.
+    pub const fn blank_line_between_stages(mut self, blank_line_between_stages: bool) -> Self {
+        self.blank_line_between_stages = blank_line_between_stages;
+        self
+    }
+    /// Sets whether to put each `&&`-chained command in shell form `RUN` on
+    /// its own line.
+    ///
+    /// Default is `true`.
+    pub const fn split_commands(mut self, split_commands: bool) -> Self {
+        self.split_commands = split_commands;
+        self
+    }
+}
+impl Default for FormatStyle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SyntaxTree<'_> {
+    /// Formats this dockerfile.
+    ///
+    /// The formatter:
+    ///
+    /// - prints keywords in upper case.
+    /// - prints flags in the order of their names, separated by a space.
+    /// - indents continuation lines by [`FormatStyle::indent_width`] spaces,
+    ///   and removes empty continuation lines.
+    /// - puts each `&&`-chained command in shell form `RUN` on its own line,
+    ///   unless disabled by [`FormatStyle::split_commands`].
+    /// - collapses consecutive blank lines into one, and inserts a blank line
+    ///   after parser directives and between stages.
+    ///
+    /// Comments, here-documents, and whitespace in quoted strings are left
+    /// untouched. Line endings follow the first line ending in the input.
+    #[must_use]
+    pub fn format(&self, style: &FormatStyle) -> String {
+        let text = self.root().text();
+        let cx = Context {
+            escape: self.escape(),
+            newline: match text.find('\n') {
+                Some(pos) if text[..pos].ends_with('\r') => "\r\n",
+                _ => "\n",
+            },
+            indent_width: style.indent_width,
+            split_commands: style.split_commands,
+        };
+
+        let items = collect_items(self);
+        let items = normalize_items(items, style.blank_line_between_stages);
+        let mut out = String::with_capacity(text.len());
+        out.push_str(&text[..self.bom_len()]);
+        for item in items {
+            match item {
+                Item::Directive(s) | Item::Comment(s) => out.push_str(s),
+                Item::Blank => {}
+                Item::Instruction(node) => format_instruction(&mut out, node, &cx),
+            }
+            out.push_str(cx.newline);
+        }
+        out
+    }
+}
+
+struct Context {
+    escape: char,
+    newline: &'static str,
+    indent_width: usize,
+    split_commands: bool,
+}
+
+enum Item<'a, 't> {
+    Directive(&'a str),
+    Comment(&'a str),
+    Blank,
+    Instruction(InstructionNode<'a, 't>),
+}
+
+fn collect_items<'a, 't>(tree: &'t SyntaxTree<'a>) -> Vec<Item<'a, 't>> {
+    let mut instructions = tree.instructions();
+    let mut items = Vec::new();
+    let mut line_has_content = false;
+    for e in tree.root().children() {
+        match e {
+            SyntaxElement::Node(node) => {
+                line_has_content = true;
+                match node.kind() {
+                    SyntaxKind::ParserDirective => items.push(Item::Directive(node.text())),
+                    _ => items.push(Item::Instruction(instructions.next().unwrap())),
+                }
+            }
+            SyntaxElement::Token(token) => match token.kind() {
+                SyntaxKind::Newline => {
+                    if !line_has_content {
+                        items.push(Item::Blank);
+                    }
+                    line_has_content = false;
+                }
+                SyntaxKind::LineContinuation => line_has_content = false,
+                SyntaxKind::Bom | SyntaxKind::Whitespace => {}
+                _ => {
+                    line_has_content = true;
+                    items.push(Item::Comment(token.text()));
+                }
+            },
+        }
+    }
+    items
+}
+
+fn normalize_items<'a, 't>(
+    items: Vec<Item<'a, 't>>,
+    blank_line_between_stages: bool,
+) -> Vec<Item<'a, 't>> {
+    let mut out = Vec::with_capacity(items.len());
+    let mut seen_from = false;
+    for item in items {
+        match &item {
+            Item::Blank => {
+                if out.is_empty() || matches!(out.last(), Some(Item::Blank)) {
+                    continue;
+                }
+            }
+            Item::Directive(..) => {}
+            _ => {
+                if matches!(out.last(), Some(Item::Directive(..))) {
+                    out.push(Item::Blank);
+                }
+                let is_from = match &item {
+                    Item::Instruction(node) => matches!(node.instruction(), Instruction::From(..)),
+                    _ => false,
+                };
+                if is_from && mem::replace(&mut seen_from, true) && blank_line_between_stages {
+                    // Insert a blank line before the comments attached to FROM.
+                    let mut i = out.len();
+                    while i != 0 && matches!(out[i - 1], Item::Comment(..)) {
+                        i -= 1;
+                    }
+                    if i != 0 && !matches!(out[i - 1], Item::Blank) {
+                        out.insert(i, Item::Blank);
+                    }
+                }
+            }
+        }
+        out.push(item);
+    }
+    if matches!(out.last(), Some(Item::Blank)) {
+        out.pop();
+    }
+    out
+}
+
+fn format_instruction(out: &mut String, node: InstructionNode<'_, '_>, cx: &Context) {
+    let syntax = node.syntax();
+    let keyword = node.keyword();
+    let bom_len = keyword.range().start - node.instruction().keyword().span.start;
+    let tokens: Vec<&SyntaxToken<'_>> = syntax
+        .children()
+        .iter()
+        .filter_map(|e| match e {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(..) => None,
+        })
+        .collect();
+
+    if let Some(trigger) = node.onbuild_trigger() {
+        if tokens.iter().any(|t| t.kind() == SyntaxKind::Comment) {
+            format_verbatim(out, node);
+        } else {
+            out.push_str("ONBUILD ");
+            format_instruction(out, trigger, cx);
+        }
+        return;
+    }
+    if matches!(node.instruction(), Instruction::Unknown(..)) {
+        format_verbatim(out, node);
+        return;
+    }
+
+    // Tokens of here-document bodies and delimiters, and the newline before
+    // them are left untouched.
+    let mut header_end = tokens
+        .iter()
+        .position(|t| matches!(t.kind(), SyntaxKind::HereDocBody | SyntaxKind::HereDocDelimiter))
+        .unwrap_or(tokens.len());
+    let tail_start = if header_end == tokens.len() {
+        syntax.range().end
+    } else {
+        if header_end != 0 && tokens[header_end - 1].kind() == SyntaxKind::Newline {
+            header_end -= 1;
+        }
+        tokens[header_end].range().start
+    };
+    let header = &tokens[..header_end];
+
+    let mut options: Vec<_> = node.instruction().options().iter().collect();
+    let mut args_start = options.iter().map(|f| f.span().end + bom_len).max().unwrap_or(0);
+    args_start = args_start.max(keyword.range().end);
+    if header.iter().any(|t| t.kind() == SyntaxKind::Comment && t.range().start < args_start) {
+        // Keep the flags as written to keep comments between them.
+        options.clear();
+        args_start = keyword.range().end;
+    }
+    options.sort_by(|a, b| a.name.value.cmp(&b.name.value));
+
+    out.push_str(node.instruction().kind().as_str());
+    for flag in options {
+        out.push_str(" --");
+        out.push_str(&flag.name.value);
+        if let Some(value) = &flag.value {
+            out.push('=');
+            out.push_str(&value.value);
+        }
+    }
+
+    let keywords = arguments_keywords(node.instruction(), bom_len);
+    let segments =
+        split_segments(header.iter().filter(|t| t.range().start >= args_start), &keywords);
+    let split_commands = cx.split_commands
+        && match node.instruction() {
+            Instruction::Run(run) => {
+                run.here_docs.is_empty() && matches!(run.arguments, Command::Shell(..))
+            }
+            _ => false,
+        };
+    let words = match node.instruction() {
+        Instruction::Env(env) => is_key_value_form(&env.arguments.value),
+        Instruction::Label(label) => is_key_value_form(&label.arguments.value),
+        // The rest of the line is the argument.
+        Instruction::Maintainer(..) | Instruction::User(..) | Instruction::Workdir(..) => false,
+        _ => true,
+    };
+    let lines = build_lines(&segments, split_commands, words);
+
+    let mut prev_comment = false;
+    for (i, line) in lines.iter().enumerate() {
+        let (text, is_comment, glued) = match line {
+            Line::Text { text, glued } => (text, false, *glued),
+            Line::Comment(comment) => {
+                // Comment lines are removed by the parser, so the whitespace
+                // around them depends on whether the next line is glued.
+                let glued = lines[i + 1..].iter().find_map(|l| match l {
+                    Line::Text { glued, .. } => Some(*glued),
+                    Line::Comment(..) => None,
+                });
+                (comment, true, glued == Some(true))
+            }
+        };
+        if i == 0 {
+            out.push(' ');
+            if is_comment {
+                out.push(cx.escape);
+                out.push_str(cx.newline);
+            }
+        } else {
+            if !prev_comment {
+                if !glued {
+                    out.push(' ');
+                }
+                out.push(cx.escape);
+            }
+            out.push_str(cx.newline);
+        }
+        if (i != 0 || is_comment) && (!glued || is_comment) {
+            push_indent(out, cx.indent_width);
+        }
+        out.push_str(text);
+        prev_comment = is_comment;
+    }
+    out.push_str(&syntax.text()[tail_start - syntax.range().start..]);
+}
+
+/// Returns `true` if the arguments of `ENV` or `LABEL` are in `key=value` form,
+/// instead of the legacy `key value` form.
+fn is_key_value_form(arguments: &str) -> bool {
+    arguments.split([' ', '\t']).next().is_some_and(|key| key.contains('='))
+}
+
+fn format_verbatim(out: &mut String, node: InstructionNode<'_, '_>) {
+    let keyword = node.keyword();
+    let start = node.syntax().range().start;
+    let text = node.syntax().text();
+    out.push_str(&text[..keyword.range().start - start]);
+    out.push_str(&keyword.text().to_ascii_uppercase());
+    out.push_str(&text[keyword.range().end - start..]);
+}
+
+fn push_indent(out: &mut String, width: usize) {
+    for _ in 0..width {
+        out.push(' ');
+    }
+}
+
+/// Returns spans of keywords in arguments, such as `AS` in `FROM`.
+fn arguments_keywords(instruction: &Instruction<'_>, bom_len: usize) -> Vec<Span> {
+    let mut keywords = Vec::new();
+    match instruction {
+        Instruction::From(from) => {
+            if let Some((as_, _)) = &from.as_ {
+                keywords.push(as_.span.clone());
+            }
+        }
+        Instruction::Healthcheck(healthcheck) => match &healthcheck.arguments {
+            HealthcheckArguments::Cmd { cmd, .. } => keywords.push(cmd.span.clone()),
+            HealthcheckArguments::None { none } => keywords.push(none.span.clone()),
+        },
+        _ => {}
+    }
+    for span in &mut keywords {
+        *span = span.start + bom_len..span.end + bom_len;
+    }
+    keywords
+}
+
+enum Segment<'a> {
+    Text(String),
+    Comment(&'a str),
+}
+
+/// Splits tokens into physical lines.
+fn split_segments<'a, 'b, I: Iterator<Item = &'b &'b SyntaxToken<'a>>>(
+    tokens: I,
+    keywords: &[Span],
+) -> Vec<Segment<'a>>
+where
+    'a: 'b,
+{
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut after_comment = false;
+    for token in tokens {
+        let range = token.range();
+        let in_keyword = keywords.iter().any(|k| k.start < range.start && range.end < k.end);
+        match token.kind() {
+            // Line continuations and indentation of comments in keywords are removed.
+            SyntaxKind::LineContinuation | SyntaxKind::Whitespace if in_keyword => {}
+            SyntaxKind::LineContinuation | SyntaxKind::Newline => {
+                if !mem::take(&mut after_comment) {
+                    segments.push(Segment::Text(mem::take(&mut current)));
+                }
+            }
+            SyntaxKind::Comment => {
+                // Only whitespace can precede a comment in the same line, unless
+                // the comment is in a keyword.
+                if current.trim_start_matches([' ', '\t']).is_empty() {
+                    current.clear();
+                } else {
+                    segments.push(Segment::Text(mem::take(&mut current)));
+                }
+                segments.push(Segment::Comment(token.text()));
+                after_comment = true;
+            }
+            _ => {
+                let mut pos = range.start;
+                for keyword in keywords {
+                    let start = keyword.start.max(range.start);
+                    let end = keyword.end.min(range.end);
+                    if start < end {
+                        current.push_str(&token.text()[pos - range.start..start - range.start]);
+                        current.push_str(
+                            &token.text()[start - range.start..end - range.start]
+                                .to_ascii_uppercase(),
+                        );
+                        pos = end;
+                    }
+                }
+                current.push_str(&token.text()[pos - range.start..]);
+            }
+        }
+    }
+    if !after_comment {
+        segments.push(Segment::Text(current));
+    }
+    segments
+}
+
+enum Line {
+    Text {
+        text: String,
+        /// `true` if this line must be joined with the previous line without
+        /// changing whitespace, e.g., the line break is inside a quoted string.
+        glued: bool,
+    },
+    Comment(String),
+}
+
+/// Builds lines from physical lines.
+///
+/// If `words` is `true`, whitespace outside of quoted strings is considered
+/// insignificant.
+fn build_lines(segments: &[Segment<'_>], split_commands: bool, words: bool) -> Vec<Line> {
+    let mut state = QuoteState::default();
+    let mut pieces = Vec::new();
+    let mut has_shell_comment = false;
+    let mut prev_ends_with_space = true;
+    for segment in segments {
+        match segment {
+            Segment::Comment(comment) => pieces.push(Piece::Comment(comment)),
+            Segment::Text(text) => {
+                if text.trim_matches([' ', '\t']).is_empty() {
+                    // Empty continuation lines are removed by the parser.
+                    continue;
+                }
+                let starts_with_space = text.starts_with([' ', '\t']);
+                let start_quoted = if words {
+                    state.quoted() || !prev_ends_with_space && !starts_with_space
+                } else {
+                    !pieces.iter().all(|p| matches!(p, Piece::Comment(..)))
+                };
+                let mut ands = Vec::new();
+                let normalized = scan(text, &mut state, &mut ands, &mut has_shell_comment);
+                let text = if words { normalized } else { text.clone() };
+                let end_quoted = state.quoted();
+                prev_ends_with_space = text.ends_with([' ', '\t']);
+                pieces.push(Piece::Text { text, start_quoted, end_quoted, ands });
+            }
+        }
+    }
+    let split_commands = split_commands && !has_shell_comment;
+
+    let mut lines = Vec::new();
+    let mut after_and = false;
+    for (i, piece) in pieces.iter().enumerate() {
+        let (text, start_quoted, end_quoted, ands) = match piece {
+            Piece::Comment(comment) => {
+                lines.push(Line::Comment((*comment).into()));
+                continue;
+            }
+            Piece::Text { text, start_quoted, end_quoted, ands } => {
+                (&**text, *start_quoted, *end_quoted, ands)
+            }
+        };
+        // The line break after this line is inside a word or quoted string.
+        let next_glued = pieces[i + 1..].iter().find_map(|p| match p {
+            Piece::Text { start_quoted, .. } => Some(*start_quoted),
+            Piece::Comment(..) => None,
+        });
+        let end_quoted = end_quoted || next_glued == Some(true);
+        let mut parts = Vec::new();
+        let mut pos = 0;
+        if split_commands {
+            for &and in ands {
+                parts.push((pos..and, pos == 0 && start_quoted, false));
+                pos = and + 2;
+            }
+        }
+        parts.push((pos..text.len(), pos == 0 && start_quoted, end_quoted));
+        for (j, (range, start_quoted, end_quoted)) in parts.into_iter().enumerate() {
+            let mut part = &text[range];
+            if !start_quoted {
+                part = part.trim_start_matches([' ', '\t']);
+            }
+            if !end_quoted {
+                part = part.trim_end_matches([' ', '\t']);
+            }
+            if j != 0 {
+                after_and = true;
+            }
+            if part.is_empty() {
+                continue;
+            }
+            let mut line = String::with_capacity(part.len() + 3);
+            if mem::take(&mut after_and) {
+                line.push_str("&& ");
+            }
+            line.push_str(part);
+            lines.push(Line::Text { text: line, glued: start_quoted });
+        }
+    }
+    if after_and {
+        // Keep trailing `&&`, although it is a syntax error in shell.
+        match lines.iter_mut().rev().find_map(|l| match l {
+            Line::Text { text, .. } => Some(text),
+            Line::Comment(..) => None,
+        }) {
+            Some(text) => text.push_str(" &&"),
+            None => lines.push(Line::Text { text: "&&".into(), glued: false }),
+        }
+    }
+    lines
+}
+
+enum Piece<'a> {
+    Text { text: String, start_quoted: bool, end_quoted: bool, ands: Vec<usize> },
+    Comment(&'a str),
+}
+
+#[derive(Default)]
+struct QuoteState {
+    single: bool,
+    double: bool,
+    backslash: bool,
+    /// Depth of unquoted `${...}`, whose contents are kept as is.
+    braces: usize,
+}
+impl QuoteState {
+    fn quoted(&self) -> bool {
+        self.single || self.double || self.backslash || self.braces != 0
+    }
+}
+
+/// Scans shell-like quotes in `text`, and returns `text` with whitespace
+/// outside of quoted strings and `${...}` collapsed into a single space.
+///
+/// This also collects positions of unquoted `&&` in the returned string.
+fn scan(
+    text: &str,
+    state: &mut QuoteState,
+    ands: &mut Vec<usize>,
+    has_shell_comment: &mut bool,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut prev_space = false;
+    while let Some(ch) = chars.next() {
+        if mem::take(&mut state.backslash) {
+            out.push(ch);
+            prev_space = false;
+            continue;
+        }
+        if state.single {
+            state.single = ch != '\'';
+            out.push(ch);
+            prev_space = false;
+            continue;
+        }
+        match ch {
+            ' ' | '\t' if !state.double && state.braces == 0 => {
+                if !prev_space {
+                    out.push(' ');
+                }
+                prev_space = true;
+                continue;
+            }
+            '\\' => state.backslash = true,
+            '"' => state.double = !state.double,
+            _ if state.double => {}
+            '\'' => state.single = true,
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                state.braces += 1;
+                out.push_str("${");
+                prev_space = false;
+                continue;
+            }
+            '}' if state.braces != 0 => state.braces -= 1,
+            _ if state.braces != 0 => {}
+            '&' if chars.peek() == Some(&'&') => {
+                chars.next();
+                ands.push(out.len());
+                out.push_str("&&");
+                prev_space = false;
+                continue;
+            }
+            '#' if out.is_empty() || prev_space => *has_shell_comment = true,
+            _ => {}
+        }
+        out.push(ch);
+        prev_space = false;
+    }
+    out
+}
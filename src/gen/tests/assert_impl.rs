@@ -58,6 +58,11 @@ const _: fn() = || {
     assert_unpin::<crate::error::Error>();
     assert_not_unwind_safe!(crate::error::Error);
     assert_not_ref_unwind_safe!(crate::error::Error);
+    assert_send::<crate::error::ParseInstructionKindError>();
+    assert_sync::<crate::error::ParseInstructionKindError>();
+    assert_unpin::<crate::error::ParseInstructionKindError>();
+    assert_unwind_safe::<crate::error::ParseInstructionKindError>();
+    assert_ref_unwind_safe::<crate::error::ParseInstructionKindError>();
     assert_send::<crate::Dockerfile<'_>>();
     assert_sync::<crate::Dockerfile<'_>>();
     assert_unpin::<crate::Dockerfile<'_>>();
@@ -68,6 +73,11 @@ const _: fn() = || {
     assert_unpin::<crate::Stage<'_, '_>>();
     assert_unwind_safe::<crate::Stage<'_, '_>>();
     assert_ref_unwind_safe::<crate::Stage<'_, '_>>();
+    assert_send::<crate::StageBase<'_, '_>>();
+    assert_sync::<crate::StageBase<'_, '_>>();
+    assert_unpin::<crate::StageBase<'_, '_>>();
+    assert_unwind_safe::<crate::StageBase<'_, '_>>();
+    assert_ref_unwind_safe::<crate::StageBase<'_, '_>>();
     assert_send::<crate::ParserDirectives<'_>>();
     assert_sync::<crate::ParserDirectives<'_>>();
     assert_unpin::<crate::ParserDirectives<'_>>();
@@ -90,6 +100,11 @@ const _: fn() = || {
     assert_unpin::<crate::Instruction<'_>>();
     assert_unwind_safe::<crate::Instruction<'_>>();
     assert_ref_unwind_safe::<crate::Instruction<'_>>();
+    assert_send::<crate::InstructionKind>();
+    assert_sync::<crate::InstructionKind>();
+    assert_unpin::<crate::InstructionKind>();
+    assert_unwind_safe::<crate::InstructionKind>();
+    assert_ref_unwind_safe::<crate::InstructionKind>();
     assert_send::<crate::AddInstruction<'_>>();
     assert_sync::<crate::AddInstruction<'_>>();
     assert_unpin::<crate::AddInstruction<'_>>();
@@ -190,6 +205,11 @@ const _: fn() = || {
     assert_unpin::<crate::WorkdirInstruction<'_>>();
     assert_unwind_safe::<crate::WorkdirInstruction<'_>>();
     assert_ref_unwind_safe::<crate::WorkdirInstruction<'_>>();
+    assert_send::<crate::UnknownInstruction<'_>>();
+    assert_sync::<crate::UnknownInstruction<'_>>();
+    assert_unpin::<crate::UnknownInstruction<'_>>();
+    assert_unwind_safe::<crate::UnknownInstruction<'_>>();
+    assert_ref_unwind_safe::<crate::UnknownInstruction<'_>>();
     assert_send::<crate::Keyword>();
     assert_sync::<crate::Keyword>();
     assert_unpin::<crate::Keyword>();
@@ -237,4 +257,224 @@ const _: fn() = || {
     assert_unpin::<crate::ParseIter<'_>>();
     assert_unwind_safe::<crate::ParseIter<'_>>();
     assert_ref_unwind_safe::<crate::ParseIter<'_>>();
+    assert_send::<crate::ParseOptions>();
+    assert_sync::<crate::ParseOptions>();
+    assert_unpin::<crate::ParseOptions>();
+    assert_unwind_safe::<crate::ParseOptions>();
+    assert_ref_unwind_safe::<crate::ParseOptions>();
+    assert_send::<crate::PrintStyle>();
+    assert_sync::<crate::PrintStyle>();
+    assert_unpin::<crate::PrintStyle>();
+    assert_unwind_safe::<crate::PrintStyle>();
+    assert_ref_unwind_safe::<crate::PrintStyle>();
+    assert_send::<crate::DockerfileBuilder>();
+    assert_sync::<crate::DockerfileBuilder>();
+    assert_unpin::<crate::DockerfileBuilder>();
+    assert_unwind_safe::<crate::DockerfileBuilder>();
+    assert_ref_unwind_safe::<crate::DockerfileBuilder>();
+    assert_send::<crate::ConvertOptions>();
+    assert_sync::<crate::ConvertOptions>();
+    assert_unpin::<crate::ConvertOptions>();
+    assert_unwind_safe::<crate::ConvertOptions>();
+    assert_ref_unwind_safe::<crate::ConvertOptions>();
+    assert_send::<crate::StageRename>();
+    assert_sync::<crate::StageRename>();
+    assert_unpin::<crate::StageRename>();
+    assert_unwind_safe::<crate::StageRename>();
+    assert_ref_unwind_safe::<crate::StageRename>();
+    assert_send::<crate::ImageUpdate>();
+    assert_sync::<crate::ImageUpdate>();
+    assert_unpin::<crate::ImageUpdate>();
+    assert_unwind_safe::<crate::ImageUpdate>();
+    assert_ref_unwind_safe::<crate::ImageUpdate>();
+    assert_send::<crate::StageFlattening>();
+    assert_sync::<crate::StageFlattening>();
+    assert_unpin::<crate::StageFlattening>();
+    assert_unwind_safe::<crate::StageFlattening>();
+    assert_ref_unwind_safe::<crate::StageFlattening>();
+    assert_send::<crate::FormatStyle>();
+    assert_sync::<crate::FormatStyle>();
+    assert_unpin::<crate::FormatStyle>();
+    assert_unwind_safe::<crate::FormatStyle>();
+    assert_ref_unwind_safe::<crate::FormatStyle>();
+    assert_send::<crate::TextEdit>();
+    assert_sync::<crate::TextEdit>();
+    assert_unpin::<crate::TextEdit>();
+    assert_unwind_safe::<crate::TextEdit>();
+    assert_ref_unwind_safe::<crate::TextEdit>();
+    assert_send::<crate::ImageConfig>();
+    assert_sync::<crate::ImageConfig>();
+    assert_unpin::<crate::ImageConfig>();
+    assert_unwind_safe::<crate::ImageConfig>();
+    assert_ref_unwind_safe::<crate::ImageConfig>();
+    assert_send::<crate::Healthcheck>();
+    assert_sync::<crate::Healthcheck>();
+    assert_unpin::<crate::Healthcheck>();
+    assert_unwind_safe::<crate::Healthcheck>();
+    assert_ref_unwind_safe::<crate::Healthcheck>();
+    assert_send::<crate::ContextInput>();
+    assert_sync::<crate::ContextInput>();
+    assert_unpin::<crate::ContextInput>();
+    assert_unwind_safe::<crate::ContextInput>();
+    assert_ref_unwind_safe::<crate::ContextInput>();
+    assert_send::<crate::ContextInputKind>();
+    assert_sync::<crate::ContextInputKind>();
+    assert_unpin::<crate::ContextInputKind>();
+    assert_unwind_safe::<crate::ContextInputKind>();
+    assert_ref_unwind_safe::<crate::ContextInputKind>();
+    assert_send::<crate::Expander>();
+    assert_sync::<crate::Expander>();
+    assert_unpin::<crate::Expander>();
+    assert_unwind_safe::<crate::Expander>();
+    assert_ref_unwind_safe::<crate::Expander>();
+    assert_send::<crate::Expanded<'_>>();
+    assert_sync::<crate::Expanded<'_>>();
+    assert_unpin::<crate::Expanded<'_>>();
+    assert_unwind_safe::<crate::Expanded<'_>>();
+    assert_ref_unwind_safe::<crate::Expanded<'_>>();
+    assert_send::<crate::Substitution<'_>>();
+    assert_sync::<crate::Substitution<'_>>();
+    assert_unpin::<crate::Substitution<'_>>();
+    assert_unwind_safe::<crate::Substitution<'_>>();
+    assert_ref_unwind_safe::<crate::Substitution<'_>>();
+    assert_send::<crate::VariableReference<'_>>();
+    assert_sync::<crate::VariableReference<'_>>();
+    assert_unpin::<crate::VariableReference<'_>>();
+    assert_unwind_safe::<crate::VariableReference<'_>>();
+    assert_ref_unwind_safe::<crate::VariableReference<'_>>();
+    assert_send::<crate::Operator>();
+    assert_sync::<crate::Operator>();
+    assert_unpin::<crate::Operator>();
+    assert_unwind_safe::<crate::Operator>();
+    assert_ref_unwind_safe::<crate::Operator>();
+    assert_send::<crate::StageGraph<'_, '_>>();
+    assert_sync::<crate::StageGraph<'_, '_>>();
+    assert_unpin::<crate::StageGraph<'_, '_>>();
+    assert_unwind_safe::<crate::StageGraph<'_, '_>>();
+    assert_ref_unwind_safe::<crate::StageGraph<'_, '_>>();
+    assert_send::<crate::Node>();
+    assert_sync::<crate::Node>();
+    assert_unpin::<crate::Node>();
+    assert_unwind_safe::<crate::Node>();
+    assert_ref_unwind_safe::<crate::Node>();
+    assert_send::<crate::Edge<'_, '_>>();
+    assert_sync::<crate::Edge<'_, '_>>();
+    assert_unpin::<crate::Edge<'_, '_>>();
+    assert_unwind_safe::<crate::Edge<'_, '_>>();
+    assert_ref_unwind_safe::<crate::Edge<'_, '_>>();
+    assert_send::<crate::EdgeKind>();
+    assert_sync::<crate::EdgeKind>();
+    assert_unpin::<crate::EdgeKind>();
+    assert_unwind_safe::<crate::EdgeKind>();
+    assert_ref_unwind_safe::<crate::EdgeKind>();
+    assert_send::<crate::PlanStep>();
+    assert_sync::<crate::PlanStep>();
+    assert_unpin::<crate::PlanStep>();
+    assert_unwind_safe::<crate::PlanStep>();
+    assert_ref_unwind_safe::<crate::PlanStep>();
+    assert_send::<crate::Platform>();
+    assert_sync::<crate::Platform>();
+    assert_unpin::<crate::Platform>();
+    assert_unwind_safe::<crate::Platform>();
+    assert_ref_unwind_safe::<crate::Platform>();
+    assert_send::<crate::ResolveOptions>();
+    assert_sync::<crate::ResolveOptions>();
+    assert_unpin::<crate::ResolveOptions>();
+    assert_unwind_safe::<crate::ResolveOptions>();
+    assert_ref_unwind_safe::<crate::ResolveOptions>();
+    assert_send::<crate::ResolvedStage>();
+    assert_sync::<crate::ResolvedStage>();
+    assert_unpin::<crate::ResolvedStage>();
+    assert_unwind_safe::<crate::ResolvedStage>();
+    assert_ref_unwind_safe::<crate::ResolvedStage>();
+    assert_send::<crate::ResolvedBase>();
+    assert_sync::<crate::ResolvedBase>();
+    assert_unpin::<crate::ResolvedBase>();
+    assert_unwind_safe::<crate::ResolvedBase>();
+    assert_ref_unwind_safe::<crate::ResolvedBase>();
+    assert_send::<crate::Resolution>();
+    assert_sync::<crate::Resolution>();
+    assert_unpin::<crate::Resolution>();
+    assert_unwind_safe::<crate::Resolution>();
+    assert_ref_unwind_safe::<crate::Resolution>();
+    assert_send::<crate::ResolveProblem>();
+    assert_sync::<crate::ResolveProblem>();
+    assert_unpin::<crate::ResolveProblem>();
+    assert_unwind_safe::<crate::ResolveProblem>();
+    assert_ref_unwind_safe::<crate::ResolveProblem>();
+    assert_send::<crate::ResolveProblemKind>();
+    assert_sync::<crate::ResolveProblemKind>();
+    assert_unpin::<crate::ResolveProblemKind>();
+    assert_unwind_safe::<crate::ResolveProblemKind>();
+    assert_ref_unwind_safe::<crate::ResolveProblemKind>();
+    assert_send::<crate::Scopes<'_>>();
+    assert_sync::<crate::Scopes<'_>>();
+    assert_unpin::<crate::Scopes<'_>>();
+    assert_unwind_safe::<crate::Scopes<'_>>();
+    assert_ref_unwind_safe::<crate::Scopes<'_>>();
+    assert_send::<crate::Scope<'_>>();
+    assert_sync::<crate::Scope<'_>>();
+    assert_unpin::<crate::Scope<'_>>();
+    assert_unwind_safe::<crate::Scope<'_>>();
+    assert_ref_unwind_safe::<crate::Scope<'_>>();
+    assert_send::<crate::Variable<'_>>();
+    assert_sync::<crate::Variable<'_>>();
+    assert_unpin::<crate::Variable<'_>>();
+    assert_unwind_safe::<crate::Variable<'_>>();
+    assert_ref_unwind_safe::<crate::Variable<'_>>();
+    assert_send::<crate::VariableKind>();
+    assert_sync::<crate::VariableKind>();
+    assert_unpin::<crate::VariableKind>();
+    assert_unwind_safe::<crate::VariableKind>();
+    assert_ref_unwind_safe::<crate::VariableKind>();
+    assert_send::<crate::WorkingDirs>();
+    assert_sync::<crate::WorkingDirs>();
+    assert_unpin::<crate::WorkingDirs>();
+    assert_unwind_safe::<crate::WorkingDirs>();
+    assert_ref_unwind_safe::<crate::WorkingDirs>();
+    assert_send::<crate::Destination>();
+    assert_sync::<crate::Destination>();
+    assert_unpin::<crate::Destination>();
+    assert_unwind_safe::<crate::Destination>();
+    assert_ref_unwind_safe::<crate::Destination>();
+    assert_send::<crate::SyntaxKind>();
+    assert_sync::<crate::SyntaxKind>();
+    assert_unpin::<crate::SyntaxKind>();
+    assert_unwind_safe::<crate::SyntaxKind>();
+    assert_ref_unwind_safe::<crate::SyntaxKind>();
+    assert_send::<crate::SyntaxTree<'_>>();
+    assert_sync::<crate::SyntaxTree<'_>>();
+    assert_unpin::<crate::SyntaxTree<'_>>();
+    assert_unwind_safe::<crate::SyntaxTree<'_>>();
+    assert_ref_unwind_safe::<crate::SyntaxTree<'_>>();
+    assert_send::<crate::InstructionNode<'_, '_>>();
+    assert_sync::<crate::InstructionNode<'_, '_>>();
+    assert_unpin::<crate::InstructionNode<'_, '_>>();
+    assert_unwind_safe::<crate::InstructionNode<'_, '_>>();
+    assert_ref_unwind_safe::<crate::InstructionNode<'_, '_>>();
+    assert_send::<crate::SyntaxPart<'_, '_, ()>>();
+    assert_sync::<crate::SyntaxPart<'_, '_, ()>>();
+    assert_unpin::<crate::SyntaxPart<'_, '_, ()>>();
+    assert_unwind_safe::<crate::SyntaxPart<'_, '_, ()>>();
+    assert_ref_unwind_safe::<crate::SyntaxPart<'_, '_, ()>>();
+    assert_send::<crate::SyntaxNode<'_>>();
+    assert_sync::<crate::SyntaxNode<'_>>();
+    assert_unpin::<crate::SyntaxNode<'_>>();
+    assert_unwind_safe::<crate::SyntaxNode<'_>>();
+    assert_ref_unwind_safe::<crate::SyntaxNode<'_>>();
+    assert_send::<crate::SyntaxToken<'_>>();
+    assert_sync::<crate::SyntaxToken<'_>>();
+    assert_unpin::<crate::SyntaxToken<'_>>();
+    assert_unwind_safe::<crate::SyntaxToken<'_>>();
+    assert_ref_unwind_safe::<crate::SyntaxToken<'_>>();
+    assert_send::<crate::SyntaxElement<'_>>();
+    assert_sync::<crate::SyntaxElement<'_>>();
+    assert_unpin::<crate::SyntaxElement<'_>>();
+    assert_unwind_safe::<crate::SyntaxElement<'_>>();
+    assert_ref_unwind_safe::<crate::SyntaxElement<'_>>();
+    assert_send::<crate::Tokens<'_, '_>>();
+    assert_sync::<crate::Tokens<'_, '_>>();
+    assert_unpin::<crate::Tokens<'_, '_>>();
+    assert_unwind_safe::<crate::Tokens<'_, '_>>();
+    assert_ref_unwind_safe::<crate::Tokens<'_, '_>>();
 };
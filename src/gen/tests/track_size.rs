@@ -26,11 +26,14 @@ fn write_size<T>(out: &mut String) {
 fn track_size() {
     let mut out = String::new();
     write_size::<crate::error::Error>(&mut out);
+    write_size::<crate::error::ParseInstructionKindError>(&mut out);
     write_size::<crate::Dockerfile<'_>>(&mut out);
     write_size::<crate::Stage<'_, '_>>(&mut out);
+    write_size::<crate::StageBase<'_, '_>>(&mut out);
     write_size::<crate::ParserDirectives<'_>>(&mut out);
     write_size::<crate::ParserDirective<()>>(&mut out);
     write_size::<crate::Instruction<'_>>(&mut out);
+    write_size::<crate::InstructionKind>(&mut out);
     write_size::<crate::AddInstruction<'_>>(&mut out);
     write_size::<crate::ArgInstruction<'_>>(&mut out);
     write_size::<crate::CmdInstruction<'_>>(&mut out);
@@ -51,6 +54,7 @@ fn track_size() {
     write_size::<crate::UserInstruction<'_>>(&mut out);
     write_size::<crate::VolumeInstruction<'_>>(&mut out);
     write_size::<crate::WorkdirInstruction<'_>>(&mut out);
+    write_size::<crate::UnknownInstruction<'_>>(&mut out);
     write_size::<crate::Keyword>(&mut out);
     write_size::<crate::Flag<'_>>(&mut out);
     write_size::<crate::UnescapedString<'_>>(&mut out);
@@ -58,6 +62,50 @@ fn track_size() {
     write_size::<crate::HereDoc<'_>>(&mut out);
     write_size::<crate::Spanned<()>>(&mut out);
     write_size::<crate::ParseIter<'_>>(&mut out);
+    write_size::<crate::ParseOptions>(&mut out);
+    write_size::<crate::PrintStyle>(&mut out);
+    write_size::<crate::DockerfileBuilder>(&mut out);
+    write_size::<crate::ConvertOptions>(&mut out);
+    write_size::<crate::StageRename>(&mut out);
+    write_size::<crate::ImageUpdate>(&mut out);
+    write_size::<crate::StageFlattening>(&mut out);
+    write_size::<crate::FormatStyle>(&mut out);
+    write_size::<crate::TextEdit>(&mut out);
+    write_size::<crate::ImageConfig>(&mut out);
+    write_size::<crate::Healthcheck>(&mut out);
+    write_size::<crate::ContextInput>(&mut out);
+    write_size::<crate::ContextInputKind>(&mut out);
+    write_size::<crate::Expander>(&mut out);
+    write_size::<crate::Expanded<'_>>(&mut out);
+    write_size::<crate::Substitution<'_>>(&mut out);
+    write_size::<crate::VariableReference<'_>>(&mut out);
+    write_size::<crate::Operator>(&mut out);
+    write_size::<crate::StageGraph<'_, '_>>(&mut out);
+    write_size::<crate::Node>(&mut out);
+    write_size::<crate::Edge<'_, '_>>(&mut out);
+    write_size::<crate::EdgeKind>(&mut out);
+    write_size::<crate::PlanStep>(&mut out);
+    write_size::<crate::Platform>(&mut out);
+    write_size::<crate::ResolveOptions>(&mut out);
+    write_size::<crate::ResolvedStage>(&mut out);
+    write_size::<crate::ResolvedBase>(&mut out);
+    write_size::<crate::Resolution>(&mut out);
+    write_size::<crate::ResolveProblem>(&mut out);
+    write_size::<crate::ResolveProblemKind>(&mut out);
+    write_size::<crate::Scopes<'_>>(&mut out);
+    write_size::<crate::Scope<'_>>(&mut out);
+    write_size::<crate::Variable<'_>>(&mut out);
+    write_size::<crate::VariableKind>(&mut out);
+    write_size::<crate::WorkingDirs>(&mut out);
+    write_size::<crate::Destination>(&mut out);
+    write_size::<crate::SyntaxKind>(&mut out);
+    write_size::<crate::SyntaxTree<'_>>(&mut out);
+    write_size::<crate::InstructionNode<'_, '_>>(&mut out);
+    write_size::<crate::SyntaxPart<'_, '_, ()>>(&mut out);
+    write_size::<crate::SyntaxNode<'_>>(&mut out);
+    write_size::<crate::SyntaxToken<'_>>(&mut out);
+    write_size::<crate::SyntaxElement<'_>>(&mut out);
+    write_size::<crate::Tokens<'_, '_>>(&mut out);
     test_helper::git::assert_diff(
         Path::new(env!("CARGO_MANIFEST_DIR")).join("src/gen/tests/track_size.txt"),
         out,
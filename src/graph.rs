@@ -0,0 +1,384 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{format, string::String, vec, vec::Vec};
+use std::collections::HashMap;
+
+use crate::{
+    Dockerfile, Instruction, Source, Stage, error,
+    error::Result,
+    expand::{Env, Expander, NO_BUILD_ARGS},
+    scope::Scopes,
+    words,
+};
+
+/// A dependency graph of stages.
+///
+/// See [`Dockerfile::graph`].
+#[derive(Debug)]
+pub struct StageGraph<'a, 'b> {
+    dockerfile: &'b Dockerfile<'a>,
+    images: Vec<String>,
+    edges: Vec<Edge<'a, 'b>>,
+}
+
+/// A node of [`StageGraph`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Node {
+    /// A stage, identified by its index.
+    Stage(usize),
+    /// An external image, identified by its index in [`StageGraph::images`].
+    Image(usize),
+    /// The build context.
+    Context,
+}
+
+/// An edge of [`StageGraph`]: a stage depending on a node.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub struct Edge<'a, 'b> {
+    /// The node that the stage depends on.
+    pub from: Node,
+    /// The index of the dependent stage.
+    pub to: usize,
+    /// The kind of this edge.
+    pub kind: EdgeKind,
+    /// The instruction that introduces this edge.
+    pub instruction: &'b Instruction<'a>,
+}
+
+/// The kind of [`Edge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum EdgeKind {
+    /// `FROM <stage|image>`.
+    From,
+    /// `COPY`/`ADD` from the build context, or `COPY --from=<stage|image|index>`.
+    Copy,
+    /// `RUN --mount=from=<stage|image|index>`, or bind mount of the build context.
+    Mount,
+}
+
+impl<'a> Dockerfile<'a> {
+    /// Returns the dependency graph of stages.
+    ///
+    /// Edges come from `FROM <stage|image>`, `COPY --from=<stage|image|index>`,
+    /// `RUN --mount=from=<stage|image|index>`, and `COPY`/`ADD`/bind mounts
+    /// from the build context.
+    ///
+    /// Variables in references are expanded in the same way as BuildKit, with
+    /// the default values of `ARG`s. Use [`graph_with_args`](Self::graph_with_args)
+    /// to pass build args.
+    ///
+    /// ```
+    /// use parse_dockerfile::{Node, parse};
+    ///
+    /// let text = "FROM rust AS build
+    /// COPY . .
+    /// RUN cargo build
+    ///
+    /// FROM node AS docs
+    ///
+    /// FROM debian
+    /// COPY --from=build /app /app
+    /// ";
+    /// let dockerfile = parse(text).unwrap();
+    /// let graph = dockerfile.graph().unwrap();
+    /// assert_eq!(graph.images(), ["rust", "node", "debian"]);
+    /// let deps: Vec<_> = graph.dependencies(2).map(|e| e.from).collect();
+    /// assert_eq!(deps, [Node::Image(2), Node::Stage(0)]);
+    /// assert_eq!(graph.build_order(2), [0, 2]);
+    /// assert_eq!(graph.unreachable_stages(2), [1]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// See [`graph_with_args`](Self::graph_with_args).
+    pub fn graph<'b>(&'b self) -> Result<StageGraph<'a, 'b>> {
+        self.graph_with_args(NO_BUILD_ARGS)
+    }
+
+    /// Returns the dependency graph of stages, with variables in references
+    /// expanded with the given build args.
+    ///
+    /// `build_args` are values passed with `--build-arg`. Variables are
+    /// expanded with the variables visible at the instruction (see
+    /// [`Dockerfile::scopes`]). References to variables that are not set are
+    /// left as is, so such references are treated as images.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    ///
+    /// - expanding variables failed
+    /// - `FROM` refers to a later stage by name (forward reference)
+    /// - `--from` refers to the current or a later stage by index (forward reference),
+    ///   or the index is out of range
+    /// - stages have a circular dependency
+    pub fn graph_with_args<'b, E: ?Sized + Env>(
+        &'b self,
+        build_args: &E,
+    ) -> Result<StageGraph<'a, 'b>> {
+        let graph = collect(self, build_args, true)?;
+        // Check circular dependency.
+        let mut state = vec![0_u8; self.stages.len()]; // 0: unvisited, 1: visiting, 2: done
+        for i in 0..self.stages.len() {
+            if let Some(stage) = graph.find_cycle(i, &mut state) {
+                return Err(error::without_location(
+                    format!("circular dependency detected on stage {}", stage_name(self, stage))
+                        .into(),
+                ));
+            }
+        }
+        Ok(graph)
+    }
+}
+
+/// Collects nodes and edges.
+///
+/// If `strict` is `false`, forward references are treated as images and
+/// out-of-range indices are ignored instead of returning an error.
+pub(crate) fn collect<'a, 'b, E: ?Sized + Env>(
+    dockerfile: &'b Dockerfile<'a>,
+    build_args: &E,
+    strict: bool,
+) -> Result<StageGraph<'a, 'b>> {
+    let scopes = dockerfile.scopes(build_args)?;
+    let mut c = Collector {
+        graph: StageGraph { dockerfile, images: vec![], edges: vec![] },
+        image_indices: HashMap::new(),
+        scopes: &scopes,
+        expander: dockerfile.expander().skip_unset(true),
+        strict,
+    };
+    for stage in dockerfile.stages() {
+        c.stage(stage)?;
+    }
+    Ok(c.graph)
+}
+
+struct Collector<'a, 'b, 's> {
+    graph: StageGraph<'a, 'b>,
+    image_indices: HashMap<String, usize>,
+    scopes: &'s Scopes<'b>,
+    expander: Expander,
+    strict: bool,
+}
+
+impl<'a, 'b> Collector<'a, 'b, '_> {
+    fn image(&mut self, name: &str) -> Node {
+        if let Some(&i) = self.image_indices.get(name) {
+            return Node::Image(i);
+        }
+        let i = self.graph.images.len();
+        self.graph.images.push(String::from(name));
+        self.image_indices.insert(String::from(name), i);
+        Node::Image(i)
+    }
+    fn edge(&mut self, from: Node, to: usize, kind: EdgeKind, instruction: &'b Instruction<'a>) {
+        self.graph.edges.push(Edge { from, to, kind, instruction });
+    }
+    fn stage(&mut self, stage: Stage<'a, 'b>) -> Result<()> {
+        let dockerfile = self.graph.dockerfile;
+        let index = stage.index();
+        let start = dockerfile.stages[index].start;
+        let from = &dockerfile.instructions[start];
+        // Unlike `Stage::base`, variables are expanded.
+        let name = self.expander.expand(&stage.from.image.value, self.scopes.at(start))?.value;
+        match dockerfile.stage(&name) {
+            Some(base) if base.index() < index => {
+                self.edge(Node::Stage(base.index()), index, EdgeKind::From, from);
+            }
+            // `FROM foo AS foo` refers to the image `foo`.
+            Some(base) if base.index() > index && self.strict => {
+                return Err(error::without_location(
+                    format!(
+                        "stage {} refers to later stage {name:?} in FROM",
+                        stage_name(dockerfile, index),
+                    )
+                    .into(),
+                ));
+            }
+            _ if name == "scratch" => {}
+            _ => {
+                let node = self.image(&name);
+                self.edge(node, index, EdgeKind::From, from);
+            }
+        }
+        let scopes = self.scopes;
+        for (i, instruction) in stage.instructions.iter().enumerate() {
+            let scope = scopes.at(start + 1 + i);
+            match instruction {
+                Instruction::Copy(copy) => {
+                    let from = copy.options.iter().find(|f| f.name.value == "from");
+                    match from.and_then(|f| f.value.as_ref()) {
+                        Some(value) => {
+                            let from = self.expander.expand(&value.value, scope)?.value;
+                            if let Some(node) = self.reference(index, &from)? {
+                                self.edge(node, index, EdgeKind::Copy, instruction);
+                            }
+                        }
+                        None => {
+                            if copy.src.iter().any(|s| matches!(s, Source::Path(..))) {
+                                self.edge(Node::Context, index, EdgeKind::Copy, instruction);
+                            }
+                        }
+                    }
+                }
+                Instruction::Add(add) => {
+                    let from_context = add.src.iter().any(|s| match s {
+                        Source::Path(p) => !is_remote(&p.value),
+                        Source::HereDoc(..) => false,
+                    });
+                    if from_context {
+                        self.edge(Node::Context, index, EdgeKind::Copy, instruction);
+                    }
+                }
+                Instruction::Run(run) => {
+                    for flag in &run.options {
+                        let Some(mount) = words::expand_mount(flag, self.expander, scope)? else {
+                            continue;
+                        };
+                        if let Some(from) = &mount.from {
+                            if let Some(node) = self.reference(index, from)? {
+                                self.edge(node, index, EdgeKind::Mount, instruction);
+                            }
+                        } else if mount.is_context_bind() {
+                            self.edge(Node::Context, index, EdgeKind::Mount, instruction);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+    /// Resolves the value of `--from` flag, whose variables have been expanded.
+    fn reference(&mut self, index: usize, from: &str) -> Result<Option<Node>> {
+        let dockerfile = self.graph.dockerfile;
+        if from.is_empty() {
+            return Ok(None);
+        }
+        if from.bytes().all(|b| b.is_ascii_digit()) {
+            return match from.parse::<usize>() {
+                Ok(i) if i < index => Ok(Some(Node::Stage(i))),
+                _ if !self.strict => Ok(None),
+                _ => Err(error::without_location(
+                    format!(
+                        "stage {} refers to current or later stage by index {from}",
+                        stage_name(dockerfile, index),
+                    )
+                    .into(),
+                )),
+            };
+        }
+        match dockerfile.stage(from) {
+            Some(stage) => Ok(Some(Node::Stage(stage.index()))),
+            None if from == "context" => Ok(Some(Node::Context)),
+            None => Ok(Some(self.image(from))),
+        }
+    }
+}
+
+pub(crate) fn is_remote(src: &str) -> bool {
+    src.starts_with("http://")
+        || src.starts_with("https://")
+        || src.starts_with("git@")
+        || src.starts_with("git://")
+}
+
+pub(crate) fn stage_name(dockerfile: &Dockerfile<'_>, index: usize) -> String {
+    match dockerfile.stage_at(index).name() {
+        Some(name) => format!("{:?}", name.value),
+        None => format!("{index}"),
+    }
+}
+
+impl<'a, 'b> StageGraph<'a, 'b> {
+    /// Returns the dockerfile of this graph.
+    #[must_use]
+    pub fn dockerfile(&self) -> &'b Dockerfile<'a> {
+        self.dockerfile
+    }
+    /// Returns external images referenced in the dockerfile.
+    ///
+    /// [`Node::Image`] is an index into this slice.
+    #[must_use]
+    pub fn images(&self) -> &[String] {
+        &self.images
+    }
+    /// Returns all edges, in the order of instructions.
+    #[must_use]
+    pub fn edges(&self) -> &[Edge<'a, 'b>] {
+        &self.edges
+    }
+    /// Returns edges to the given stage, i.e., what the stage depends on.
+    pub fn dependencies<'g>(&'g self, stage: usize) -> impl Iterator<Item = &'g Edge<'a, 'b>> {
+        self.edges.iter().filter(move |e| e.to == stage)
+    }
+    /// Returns indices of all stages in the order in which they can be built,
+    /// i.e., each stage comes after the stages it depends on.
+    ///
+    /// Among independent stages, the order in the dockerfile is kept.
+    #[must_use]
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.dockerfile.stages.len());
+        let mut visited = vec![false; self.dockerfile.stages.len()];
+        for i in 0..self.dockerfile.stages.len() {
+            self.visit(i, &mut visited, &mut order);
+        }
+        order
+    }
+    /// Returns indices of stages needed to build `target` (including `target`
+    /// itself), in the order in which they can be built.
+    #[must_use]
+    pub fn build_order(&self, target: usize) -> Vec<usize> {
+        let mut order = vec![];
+        let mut visited = vec![false; self.dockerfile.stages.len()];
+        if target < visited.len() {
+            self.visit(target, &mut visited, &mut order);
+        }
+        order
+    }
+    /// Returns indices of stages that are not needed to build `target`.
+    ///
+    /// Like BuildKit, these stages are skipped when building `target`.
+    #[must_use]
+    pub fn unreachable_stages(&self, target: usize) -> Vec<usize> {
+        let mut reachable = vec![false; self.dockerfile.stages.len()];
+        for i in self.build_order(target) {
+            reachable[i] = true;
+        }
+        (0..reachable.len()).filter(|&i| !reachable[i]).collect()
+    }
+
+    fn visit(&self, stage: usize, visited: &mut [bool], order: &mut Vec<usize>) {
+        if visited[stage] {
+            return;
+        }
+        visited[stage] = true;
+        for edge in self.dependencies(stage) {
+            if let Node::Stage(dep) = edge.from {
+                self.visit(dep, visited, order);
+            }
+        }
+        order.push(stage);
+    }
+    fn find_cycle(&self, stage: usize, state: &mut [u8]) -> Option<usize> {
+        match state[stage] {
+            1 => return Some(stage),
+            2 => return None,
+            _ => {}
+        }
+        state[stage] = 1;
+        for edge in self.dependencies(stage) {
+            if let Node::Stage(dep) = edge.from {
+                if let Some(s) = self.find_cycle(dep, state) {
+                    return Some(s);
+                }
+            }
+        }
+        state[stage] = 2;
+        None
+    }
+}
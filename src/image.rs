@@ -0,0 +1,216 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{string::String, vec::Vec};
+use std::collections::HashMap;
+
+use crate::{
+    Dockerfile, Instruction, Span, StageBase, TextEdit, UnescapedString,
+    expand::{NO_BUILD_ARGS, references_in, unset_references},
+    scope::{Scope, VariableKind},
+    words,
+};
+
+/// The result of [`Dockerfile::update_images`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ImageUpdate {
+    /// Edits that update images, sorted by span.
+    ///
+    /// Apply them with [`apply_edits`](crate::apply_edits).
+    pub edits: Vec<TextEdit>,
+    /// Spans of image references that contain variables that cannot be
+    /// resolved statically, and therefore may refer to one of the given
+    /// images after expansion.
+    ///
+    /// These references are not updated.
+    pub unresolved: Vec<Span>,
+}
+
+impl Dockerfile<'_> {
+    /// Returns edits that replace images with new ones.
+    ///
+    /// `images` is a list of `(old, new)` pairs of image references, such as
+    /// `("node:18", "node:20.11@sha256:...")`. Images are matched exactly.
+    ///
+    /// The following image references are updated; stage references are left
+    /// as is:
+    ///
+    /// - `FROM <image>`
+    /// - `COPY --from=<image>` (including in `ONBUILD`)
+    /// - `RUN --mount=from=<image>` (including in `ONBUILD`)
+    ///
+    /// If the image in `FROM` contains a variable whose value is given by the
+    /// default value of a global `ARG`, such as `FROM node:${NODE_VERSION}`,
+    /// the default value of `ARG` is updated instead. Variables are expanded in
+    /// the same way as BuildKit (see [`Dockerfile::scopes`]).
+    ///
+    /// ```
+    /// let text =
+    ///     "ARG NODE=node:18\nFROM $NODE AS build\nFROM nginx:1.25\nCOPY --from=build /app /app\n";
+    /// let dockerfile = parse_dockerfile::parse(text).unwrap();
+    /// let update = dockerfile.update_images(&[("node:18", "node:20"), ("nginx:1.25", "nginx:1.27")]);
+    /// let new = parse_dockerfile::apply_edits(text, &update.edits).unwrap();
+    /// assert_eq!(
+    ///     new,
+    ///     "ARG NODE=node:20\nFROM $NODE AS build\nFROM nginx:1.27\nCOPY --from=build /app /app\n"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn update_images(&self, images: &[(&str, &str)]) -> ImageUpdate {
+        let scopes = self.scopes(NO_BUILD_ARGS).ok();
+        let mut u = Updater {
+            images,
+            update: ImageUpdate { edits: Vec::new(), unresolved: Vec::new() },
+            arg_edits: HashMap::new(),
+        };
+        for stage in self.stages() {
+            if let StageBase::Image(image) = stage.base() {
+                if !references_in(&image.value, self.escape_byte).is_empty() {
+                    let scope = scopes.as_ref().map(|s| s.at(self.stages[stage.index()].start));
+                    u.image_with_variables(self, image, scope);
+                } else if let Some(new) = u.find(&image.value) {
+                    u.update.edits.push(image.replace_with(new));
+                }
+            }
+            for instruction in stage.instructions {
+                u.instruction(self, instruction);
+            }
+        }
+        for (span, replacement) in u.arg_edits.into_values().flatten() {
+            u.update.edits.push(TextEdit::replace(span, replacement));
+        }
+        u.update.edits.sort_by_key(|e| e.span.start);
+        u.update.unresolved.sort_by_key(|s| s.start);
+        u.update
+    }
+}
+
+struct Updater<'u> {
+    images: &'u [(&'u str, &'u str)],
+    update: ImageUpdate,
+    /// Edits to the default values of global args, keyed by the start of the
+    /// span. `None` if different values are required by different images.
+    arg_edits: HashMap<usize, Option<(Span, String)>>,
+}
+
+impl<'u> Updater<'u> {
+    fn find(&self, image: &str) -> Option<&'u str> {
+        self.images.iter().find(|(old, _)| *old == image).map(|(_, new)| *new)
+    }
+    fn image_with_variables(
+        &mut self,
+        dockerfile: &Dockerfile<'_>,
+        image: &UnescapedString<'_>,
+        scope: Option<&Scope<'_>>,
+    ) {
+        let escape = dockerfile.escape_byte;
+        // The value is given by --build-arg or other variables.
+        let Some(scope) = scope.filter(|s| unset_references(&image.value, escape, *s).is_empty())
+        else {
+            self.update.unresolved.push(image.span.clone());
+            return;
+        };
+        let Ok(expanded) = dockerfile.expander().expand(&image.value, scope) else {
+            self.update.unresolved.push(image.span.clone());
+            return;
+        };
+        let Some(new) = self.find(&expanded.value) else { return };
+        // Only images with exactly one `$name` or `${name}` referring to a
+        // global arg with a literal default value can be updated by updating
+        // the default value.
+        let references = references_in(&image.value, escape);
+        let ([reference], [substitution]) = (&*references, &*expanded.substitutions) else {
+            self.update.unresolved.push(image.span.clone());
+            return;
+        };
+        let variable = scope.get(reference.name).filter(|v| v.kind == VariableKind::GlobalArg);
+        let default = variable
+            .filter(|_| reference.operator.is_none())
+            .and_then(|v| global_arg_default(dockerfile, v.name, v.span.as_ref()?));
+        let Some(span) = default else {
+            self.update.unresolved.push(image.span.clone());
+            return;
+        };
+        let prefix = &expanded.value[..substitution.range.start];
+        let suffix = &expanded.value[substitution.range.end..];
+        let value = new
+            .strip_prefix(prefix)
+            .and_then(|v| v.strip_suffix(suffix))
+            .filter(|v| !v.contains([' ', '\t', '"', '\'', '$', char::from(escape)]));
+        let Some(value) = value else {
+            self.update.unresolved.push(image.span.clone());
+            return;
+        };
+        let edit = self.arg_edits.entry(span.start).or_insert_with(|| Some((span, value.into())));
+        if edit.as_ref().is_some_and(|(_, v)| v != value) {
+            *edit = None;
+            self.update.unresolved.push(image.span.clone());
+        }
+    }
+    fn instruction(&mut self, dockerfile: &Dockerfile<'_>, instruction: &Instruction<'_>) {
+        match instruction {
+            Instruction::Copy(copy) => {
+                for flag in &copy.options {
+                    if flag.name.value != "from" {
+                        continue;
+                    }
+                    let Some(value) = &flag.value else { continue };
+                    if !is_image(dockerfile, &value.value) {
+                        continue;
+                    }
+                    if !references_in(&value.value, dockerfile.escape_byte).is_empty() {
+                        self.update.unresolved.push(value.span.clone());
+                    } else if let Some(new) = self.find(&value.value) {
+                        self.update.edits.push(value.replace_with(new));
+                    }
+                }
+            }
+            Instruction::Run(run) => {
+                for flag in &run.options {
+                    let Some(from) = words::mount_from(flag) else { continue };
+                    if !is_image(dockerfile, &from.value) {
+                        continue;
+                    }
+                    match (from.span, self.find(&from.value)) {
+                        (Some(span), Some(new)) => {
+                            self.update.edits.push(TextEdit::replace(span, new));
+                        }
+                        (None, Some(..)) => {
+                            self.update.unresolved.push(flag.value.as_ref().unwrap().span.clone());
+                        }
+                        _ if !references_in(&from.value, dockerfile.escape_byte).is_empty() => {
+                            self.update.unresolved.push(flag.value.as_ref().unwrap().span.clone());
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Instruction::Onbuild(onbuild) => self.instruction(dockerfile, &onbuild.instruction),
+            _ => {}
+        }
+    }
+}
+
+/// Returns the span of the default value of the global arg `name` defined at
+/// `span`, or `None` if the default value is not a literal or cannot be mapped
+/// to the text.
+fn global_arg_default(dockerfile: &Dockerfile<'_>, name: &str, span: &Span) -> Option<Span> {
+    let escape = dockerfile.escape_byte;
+    let kv = dockerfile
+        .global_args()
+        .flat_map(|arg| words::key_values(&arg.arguments, escape, false))
+        .find(|kv| kv.key == name && kv.span == *span)?;
+    let value = kv.value?;
+    let start = kv.span.start + kv.key.len() + 1;
+    (kv.span.end - start == value.len() && references_in(value, escape).is_empty())
+        .then_some(start..kv.span.end)
+}
+
+/// Returns `true` if `from` (value of `--from` flag) refers to an image
+/// rather than a stage.
+fn is_image(dockerfile: &Dockerfile<'_>, from: &str) -> bool {
+    !from.is_empty()
+        && dockerfile.stage(from).is_none()
+        && !from.bytes().all(|b| b.is_ascii_digit())
+        && from != "scratch"
+}
@@ -108,20 +108,71 @@ mod assert_impl;
 #[path = "gen/tests/track_size.rs"]
 mod track_size;
 
+mod builder;
+mod config;
+mod context;
+mod convert;
+mod edit;
 mod error;
+mod expand;
+mod flatten;
+mod format;
+mod graph;
+mod image;
+mod plan;
+mod print;
+mod prune;
+mod rename;
+mod resolve;
+mod scope;
+mod syntax;
+pub mod visit;
+pub mod visit_mut;
+mod words;
+mod workdir;
 
 use alloc::{borrow::Cow, boxed::Box, string::String, vec, vec::Vec};
-use core::{mem, ops::Range, str};
+use core::{fmt, mem, ops::Range, str};
 use std::collections::HashMap;
 
 use smallvec::SmallVec;
 
-pub use self::error::Error;
 use self::error::{ErrorKind, InternalResult, Result};
+pub use self::{
+    builder::DockerfileBuilder,
+    config::{Healthcheck, ImageConfig},
+    context::{ContextInput, ContextInputKind},
+    convert::ConvertOptions,
+    edit::{TextEdit, apply_edits, apply_edits_with},
+    error::{Error, ParseInstructionKindError},
+    expand::{Env, Expanded, Expander, Operator, Substitution, VariableReference},
+    flatten::StageFlattening,
+    format::FormatStyle,
+    graph::{Edge, EdgeKind, Node, StageGraph},
+    image::ImageUpdate,
+    plan::PlanStep,
+    print::PrintStyle,
+    rename::StageRename,
+    resolve::{
+        Platform, Resolution, ResolveOptions, ResolveProblem, ResolveProblemKind, ResolvedBase,
+        ResolvedStage,
+    },
+    scope::{Scope, Scopes, Variable, VariableKind},
+    syntax::{
+        InstructionNode, SyntaxElement, SyntaxKind, SyntaxNode, SyntaxPart, SyntaxToken,
+        SyntaxTree, Tokens,
+    },
+    workdir::{Destination, WorkingDirs},
+};
 
 /// Parses dockerfile from the given `text`.
-#[allow(clippy::missing_panics_doc)]
 pub fn parse(text: &str) -> Result<Dockerfile<'_>> {
+    parse_with(text, ParseOptions::new())
+}
+
+/// Parses dockerfile from the given `text` with the given `options`.
+#[allow(clippy::missing_panics_doc)]
+pub fn parse_with(text: &str, options: ParseOptions) -> Result<Dockerfile<'_>> {
     #[cold]
     fn error(
         p: &ParseIter<'_>,
@@ -135,7 +186,7 @@ pub fn parse(text: &str) -> Result<Dockerfile<'_>> {
         e.into_error(p)
     }
 
-    let mut p = ParseIter::new(text)?;
+    let mut p = ParseIter::new(text, options)?;
     let mut s = p.s;
 
     let mut instructions = Vec::with_capacity((p.text.len() / 60).min(1024));
@@ -156,10 +207,10 @@ pub fn parse(text: &str) -> Result<Dockerfile<'_>> {
             }
             arg @ Instruction::Arg(..) => instructions.push(arg),
             instruction => {
-                if current_stage.is_none() {
+                if current_stage.is_none() && !p.options.allow_no_stages {
                     return Err(error(
                         &p,
-                        error::expected("FROM", instruction.instruction_span().start),
+                        error::expected("FROM", instruction.keyword().span.start),
                         &mut instructions,
                         &mut stages,
                     ));
@@ -173,7 +224,7 @@ pub fn parse(text: &str) -> Result<Dockerfile<'_>> {
         stages.push(current_stage..instructions.len());
     }
 
-    if stages.is_empty() {
+    if stages.is_empty() && !p.options.allow_no_stages {
         // https://github.com/moby/buildkit/blob/v0.30/frontend/dockerfile/dockerfile2llb/convert.go#L278
         return Err(error(&p, error::no_stage(), &mut instructions, &mut stages));
     }
@@ -184,7 +235,11 @@ pub fn parse(text: &str) -> Result<Dockerfile<'_>> {
     for (i, stage) in stages.iter().enumerate() {
         let Instruction::From(from) = &instructions[stage.start] else { unreachable!() };
         if let Some((_as, name)) = &from.as_ {
-            if let Some(&first_occurrence) = stages_by_name.get(&name.value) {
+            let key = match to_lowercase(&name.value) {
+                Cow::Borrowed(_) => name.value.clone(),
+                Cow::Owned(lower) => Cow::Owned(lower),
+            };
+            if let Some(&first_occurrence) = stages_by_name.get(&key) {
                 drop(stages_by_name);
                 let second_start = name.span.start;
                 let Instruction::From(from) = &mut instructions[stages[first_occurrence].start]
@@ -199,11 +254,17 @@ pub fn parse(text: &str) -> Result<Dockerfile<'_>> {
                     &mut stages,
                 ));
             }
-            stages_by_name.insert(name.value.clone(), i);
+            stages_by_name.insert(key, i);
         }
     }
 
-    Ok(Dockerfile { parser_directives: p.parser_directives, instructions, stages, stages_by_name })
+    Ok(Dockerfile {
+        parser_directives: p.parser_directives,
+        instructions,
+        stages,
+        stages_by_name,
+        escape_byte: p.escape_byte,
+    })
 }
 
 /// Returns an iterator over instructions in the given `text`.
@@ -216,7 +277,96 @@ pub fn parse(text: &str) -> Result<Dockerfile<'_>> {
 /// When `.next()` on the returned iterator has returned an `Some(Err(..))`
 /// once, the subsequent `.next()` calls return `None`.
 pub fn parse_iter(text: &str) -> Result<ParseIter<'_>> {
-    ParseIter::new(text)
+    ParseIter::new(text, ParseOptions::new())
+}
+
+/// Returns an iterator over instructions in the given `text` with the given `options`.
+///
+/// See [`parse_iter`] for more.
+pub fn parse_iter_with(text: &str, options: ParseOptions) -> Result<ParseIter<'_>> {
+    ParseIter::new(text, options)
+}
+
+/// Options for [`parse_with`] and [`parse_iter_with`].
+///
+/// # Examples
+///
+/// ```
+/// use parse_dockerfile::{ParseOptions, parse_with};
+///
+/// let options = ParseOptions::new().escape('`').allow_no_stages(true);
+/// let dockerfile = parse_with("RUN echo `\n  hello\n", options).unwrap();
+/// assert_eq!(dockerfile.instructions.len(), 1);
+/// assert_eq!(dockerfile.stages().len(), 0);
+/// ```
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct ParseOptions {
+    escape_byte: u8,
+    here_docs: bool,
+    allow_no_stages: bool,
+    unknown_instructions: bool,
+}
+impl ParseOptions {
+    /// Creates a new `ParseOptions` with the default options.
+    ///
+    /// This is equivalent to the options used by [`parse`] and [`parse_iter`].
+    pub const fn new() -> Self {
+        Self {
+            escape_byte: DEFAULT_ESCAPE_BYTE,
+            here_docs: true,
+            allow_no_stages: false,
+            unknown_instructions: false,
+        }
+    }
+    /// Sets the escape character used when the dockerfile doesn't have
+    /// `escape` parser directive.
+    ///
+    /// Default: `\`
+    ///
+    /// # Panics
+    ///
+    /// Panics if `escape` is neither `\` nor `` ` ``.
+    pub const fn escape(mut self, escape: char) -> Self {
+        match escape {
+            '\\' | '`' => self.escape_byte = escape as u8,
+            _ => panic!("escape character must be '\\' or '`'"),
+        }
+        self
+    }
+    /// Sets whether to recognize [here-documents](https://docs.docker.com/reference/dockerfile/#here-documents).
+    ///
+    /// If `false`, `<<EOF` is parsed as a normal argument like in older frontends.
+    ///
+    /// Default: `true`
+    pub const fn here_docs(mut self, here_docs: bool) -> Self {
+        self.here_docs = here_docs;
+        self
+    }
+    /// Sets whether to allow dockerfile without `FROM` instruction.
+    ///
+    /// If `true`, instructions before the first `FROM` instruction and
+    /// dockerfile without stages are allowed. This is useful for parsing
+    /// dockerfile fragments.
+    ///
+    /// Default: `false`
+    pub const fn allow_no_stages(mut self, allow_no_stages: bool) -> Self {
+        self.allow_no_stages = allow_no_stages;
+        self
+    }
+    /// Sets whether to parse unknown instructions as [`Instruction::Unknown`]
+    /// instead of returning an error.
+    ///
+    /// Default: `false`
+    pub const fn unknown_instructions(mut self, unknown_instructions: bool) -> Self {
+        self.unknown_instructions = unknown_instructions;
+        self
+    }
+}
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// A dockerfile.
@@ -232,42 +382,148 @@ pub struct Dockerfile<'a> {
     stages: Vec<Range<usize>>,
     #[cfg_attr(feature = "serde", serde(skip))]
     stages_by_name: HashMap<Cow<'a, str>, usize>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    escape_byte: u8,
 }
 impl<'a> Dockerfile<'a> {
     /// Returns an iterator over global args.
-    #[allow(clippy::missing_panics_doc)] // self.stages is not empty
     #[must_use]
     pub fn global_args<'b>(&'b self) -> impl ExactSizeIterator<Item = &'b ArgInstruction<'a>> {
-        self.instructions[..self.stages.first().unwrap().start].iter().map(|arg| {
+        // Usually this is the start of the first stage, but instructions other than
+        // ARG can precede it when parsed with ParseOptions::allow_no_stages.
+        let end = self
+            .instructions
+            .iter()
+            .position(|i| !matches!(i, Instruction::Arg(..)))
+            .unwrap_or(self.instructions.len());
+        self.instructions[..end].iter().map(|arg| {
             let Instruction::Arg(arg) = arg else { unreachable!() };
             arg
         })
     }
     /// Gets a stage by name.
+    ///
+    /// Like BuildKit, stage names are matched case-insensitively.
     #[must_use]
     pub fn stage<'b>(&'b self, name: &str) -> Option<Stage<'a, 'b>> {
-        let i = *self.stages_by_name.get(name)?;
-        let stage = &self.stages[i];
-        let Instruction::From(from) = &self.instructions[stage.start] else { unreachable!() };
-        Some(Stage { from, instructions: &self.instructions[stage.start + 1..stage.end] })
+        let i = *self.stages_by_name.get(&*to_lowercase(name))?;
+        Some(self.stage_at(i))
+    }
+    /// Gets a stage by index.
+    #[must_use]
+    pub fn stage_by_index<'b>(&'b self, index: usize) -> Option<Stage<'a, 'b>> {
+        if index < self.stages.len() { Some(self.stage_at(index)) } else { None }
+    }
+    /// Returns the stage that is built when no `--target` is specified, i.e., the last stage.
+    ///
+    /// Returns `None` if the dockerfile has no stages.
+    #[must_use]
+    pub fn target<'b>(&'b self) -> Option<Stage<'a, 'b>> {
+        self.stages.len().checked_sub(1).map(|i| self.stage_at(i))
     }
     /// Returns an iterator over stages.
     #[must_use]
     pub fn stages<'b>(&'b self) -> impl ExactSizeIterator<Item = Stage<'a, 'b>> {
-        self.stages.iter().map(move |stage| {
-            let Instruction::From(from) = &self.instructions[stage.start] else { unreachable!() };
-            Stage { from, instructions: &self.instructions[stage.start + 1..stage.end] }
-        })
+        (0..self.stages.len()).map(move |i| self.stage_at(i))
+    }
+    fn stage_at<'b>(&'b self, index: usize) -> Stage<'a, 'b> {
+        let stage = &self.stages[index];
+        let Instruction::From(from) = &self.instructions[stage.start] else { unreachable!() };
+        Stage {
+            from,
+            instructions: &self.instructions[stage.start + 1..stage.end],
+            index,
+            dockerfile: self,
+        }
     }
 }
 /// A stage.
-#[derive(Debug)]
+#[derive(Clone, Copy)]
 #[non_exhaustive]
 pub struct Stage<'a, 'b> {
     /// The `FROM` instruction.
     pub from: &'b FromInstruction<'a>,
     /// The remaining instructions.
     pub instructions: &'b [Instruction<'a>],
+    index: usize,
+    dockerfile: &'b Dockerfile<'a>,
+}
+impl<'a, 'b> Stage<'a, 'b> {
+    /// Returns the name of this stage.
+    ///
+    /// ```text
+    /// FROM [--platform=<platform>] <image> [AS <name>]
+    ///                                          ^^^^^^
+    /// ```
+    #[must_use]
+    pub fn name(&self) -> Option<&'b UnescapedString<'a>> {
+        self.from.as_.as_ref().map(|(_as, name)| name)
+    }
+    /// Returns the index of this stage.
+    #[must_use]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    /// Returns the span from the start of the `FROM` instruction to the end of
+    /// the last instruction in this stage.
+    #[must_use]
+    pub fn span(&self) -> Span {
+        let stage = &self.dockerfile.stages[self.index];
+        self.from.from.span.start..self.dockerfile.instructions[stage.end - 1].end()
+    }
+    /// Returns the value of `--platform` flag.
+    ///
+    /// ```text
+    /// FROM [--platform=<platform>] <image> [AS <name>]
+    ///                  ^^^^^^^^^^
+    /// ```
+    #[must_use]
+    pub fn platform(&self) -> Option<&'b UnescapedString<'a>> {
+        self.from.options.iter().find(|f| f.name.value == "platform")?.value.as_ref()
+    }
+    /// Returns the base of this stage.
+    ///
+    /// An image name that matches the name of this stage or a later stage is
+    /// not treated as a stage reference.
+    ///
+    /// Note that variables in the image name are not expanded, so an image
+    /// name such as `${BASE}` that would refer to a stage or `scratch` after
+    /// expansion is returned as [`StageBase::Image`].
+    #[must_use]
+    pub fn base(&self) -> StageBase<'a, 'b> {
+        let image = &self.from.image;
+        if image.value == "scratch" {
+            return StageBase::Scratch;
+        }
+        match self.dockerfile.stage(&image.value) {
+            Some(stage) if stage.index < self.index => StageBase::Stage(stage),
+            _ => StageBase::Image(image),
+        }
+    }
+}
+impl fmt::Debug for Stage<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Stage")
+            .field("from", &self.from)
+            .field("instructions", &self.instructions)
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+/// The base of a stage.
+#[derive(Debug, Clone, Copy)]
+#[non_exhaustive]
+pub enum StageBase<'a, 'b> {
+    /// `FROM scratch`.
+    Scratch,
+    /// An earlier stage.
+    Stage(Stage<'a, 'b>),
+    /// An image.
+    Image(&'b UnescapedString<'a>),
+}
+
+fn to_lowercase(s: &str) -> Cow<'_, str> {
+    if s.chars().any(char::is_uppercase) { Cow::Owned(s.to_lowercase()) } else { Cow::Borrowed(s) }
 }
 
 /// Parser directives.
@@ -362,30 +618,292 @@ pub enum Instruction<'a> {
     Volume(VolumeInstruction<'a>),
     /// `WORKDIR` instruction.
     Workdir(WorkdirInstruction<'a>),
-}
-impl Instruction<'_> {
-    fn instruction_span(&self) -> Span {
+    /// An instruction not known to this parser.
+    ///
+    /// This is only returned when [`ParseOptions::unknown_instructions`] is enabled.
+    Unknown(UnknownInstruction<'a>),
+}
+/// For `ONBUILD` instruction, [`kind`](Self::kind), [`keyword`](Self::keyword),
+/// [`span`](Self::span), and [`raw_text`](Self::raw_text) describe the whole
+/// `ONBUILD` instruction, and [`options`](Self::options) returns the options
+/// of the trigger instruction, since `ONBUILD` itself has no options.
+impl<'a> Instruction<'a> {
+    /// Returns the kind of this instruction.
+    ///
+    /// For `ONBUILD` instruction, this returns [`InstructionKind::Onbuild`]. Use
+    /// [`OnbuildInstruction::instruction`] to get the kind of the trigger instruction.
+    #[must_use]
+    pub fn kind(&self) -> InstructionKind {
         match self {
-            Instruction::Add(instruction) => instruction.add.span.clone(),
-            Instruction::Arg(instruction) => instruction.arg.span.clone(),
-            Instruction::Cmd(instruction) => instruction.cmd.span.clone(),
-            Instruction::Copy(instruction) => instruction.copy.span.clone(),
-            Instruction::Entrypoint(instruction) => instruction.entrypoint.span.clone(),
-            Instruction::Env(instruction) => instruction.env.span.clone(),
-            Instruction::Expose(instruction) => instruction.expose.span.clone(),
-            Instruction::From(instruction) => instruction.from.span.clone(),
-            Instruction::Healthcheck(instruction) => instruction.healthcheck.span.clone(),
-            Instruction::Label(instruction) => instruction.label.span.clone(),
-            Instruction::Maintainer(instruction) => instruction.maintainer.span.clone(),
-            Instruction::Onbuild(instruction) => instruction.onbuild.span.clone(),
-            Instruction::Run(instruction) => instruction.run.span.clone(),
-            Instruction::Shell(instruction) => instruction.shell.span.clone(),
-            Instruction::Stopsignal(instruction) => instruction.stopsignal.span.clone(),
-            Instruction::User(instruction) => instruction.user.span.clone(),
-            Instruction::Volume(instruction) => instruction.volume.span.clone(),
-            Instruction::Workdir(instruction) => instruction.workdir.span.clone(),
+            Instruction::Add(..) => InstructionKind::Add,
+            Instruction::Arg(..) => InstructionKind::Arg,
+            Instruction::Cmd(..) => InstructionKind::Cmd,
+            Instruction::Copy(..) => InstructionKind::Copy,
+            Instruction::Entrypoint(..) => InstructionKind::Entrypoint,
+            Instruction::Env(..) => InstructionKind::Env,
+            Instruction::Expose(..) => InstructionKind::Expose,
+            Instruction::From(..) => InstructionKind::From,
+            Instruction::Healthcheck(..) => InstructionKind::Healthcheck,
+            Instruction::Label(..) => InstructionKind::Label,
+            Instruction::Maintainer(..) => InstructionKind::Maintainer,
+            Instruction::Onbuild(..) => InstructionKind::Onbuild,
+            Instruction::Run(..) => InstructionKind::Run,
+            Instruction::Shell(..) => InstructionKind::Shell,
+            Instruction::Stopsignal(..) => InstructionKind::Stopsignal,
+            Instruction::User(..) => InstructionKind::User,
+            Instruction::Volume(..) => InstructionKind::Volume,
+            Instruction::Workdir(..) => InstructionKind::Workdir,
+            Instruction::Unknown(..) => InstructionKind::Unknown,
+        }
+    }
+    /// Returns the keyword of this instruction.
+    ///
+    /// ```text
+    /// RUN [options] <command> ...
+    /// ^^^
+    /// ```
+    #[must_use]
+    pub fn keyword(&self) -> &Keyword {
+        match self {
+            Instruction::Add(instruction) => &instruction.add,
+            Instruction::Arg(instruction) => &instruction.arg,
+            Instruction::Cmd(instruction) => &instruction.cmd,
+            Instruction::Copy(instruction) => &instruction.copy,
+            Instruction::Entrypoint(instruction) => &instruction.entrypoint,
+            Instruction::Env(instruction) => &instruction.env,
+            Instruction::Expose(instruction) => &instruction.expose,
+            Instruction::From(instruction) => &instruction.from,
+            Instruction::Healthcheck(instruction) => &instruction.healthcheck,
+            Instruction::Label(instruction) => &instruction.label,
+            Instruction::Maintainer(instruction) => &instruction.maintainer,
+            Instruction::Onbuild(instruction) => &instruction.onbuild,
+            Instruction::Run(instruction) => &instruction.run,
+            Instruction::Shell(instruction) => &instruction.shell,
+            Instruction::Stopsignal(instruction) => &instruction.stopsignal,
+            Instruction::User(instruction) => &instruction.user,
+            Instruction::Volume(instruction) => &instruction.volume,
+            Instruction::Workdir(instruction) => &instruction.workdir,
+            Instruction::Unknown(instruction) => &instruction.keyword,
+        }
+    }
+    /// Returns the span of the whole instruction.
+    ///
+    /// This includes continuation lines and here-documents (including their
+    /// end delimiters), and does not include the trailing newline.
+    ///
+    /// ```text
+    ///   RUN [options] <<EOF
+    /// / ^^^^^^^^^^^^^^^^^^^
+    /// | ...
+    /// | EOF
+    /// |_^^^
+    /// ```
+    #[must_use]
+    pub fn span(&self) -> Span {
+        self.keyword().span.start..self.end()
+    }
+    fn end(&self) -> usize {
+        fn here_docs_end(last_here_doc: Option<&HereDoc<'_>>, end: usize) -> usize {
+            last_here_doc.map_or(end, |here_doc| here_doc.end.max(end))
+        }
+        fn command_end(command: &Command<'_>) -> usize {
+            match command {
+                Command::Exec(array) => array.span.end,
+                Command::Shell(s) => s.span.end,
+            }
+        }
+        fn sources_end(src: &[Source<'_>], dest: &UnescapedString<'_>) -> usize {
+            let last_here_doc = src.iter().rev().find_map(|src| match src {
+                Source::HereDoc(here_doc) => Some(here_doc),
+                Source::Path(..) => None,
+            });
+            here_docs_end(last_here_doc, dest.span.end)
+        }
+        match self {
+            Instruction::Add(instruction) => sources_end(&instruction.src, &instruction.dest),
+            Instruction::Copy(instruction) => sources_end(&instruction.src, &instruction.dest),
+            Instruction::Arg(instruction) => instruction.arguments.span.end,
+            Instruction::Env(instruction) => instruction.arguments.span.end,
+            Instruction::Label(instruction) => instruction.arguments.span.end,
+            Instruction::Maintainer(instruction) => instruction.name.span.end,
+            Instruction::Stopsignal(instruction) => instruction.arguments.span.end,
+            Instruction::User(instruction) => instruction.arguments.span.end,
+            Instruction::Workdir(instruction) => instruction.arguments.span.end,
+            Instruction::Cmd(instruction) => command_end(&instruction.arguments),
+            Instruction::Entrypoint(instruction) => command_end(&instruction.arguments),
+            Instruction::Expose(instruction) => {
+                instruction.arguments.last().map_or(instruction.expose.span.end, |a| a.span.end)
+            }
+            Instruction::From(instruction) => match &instruction.as_ {
+                Some((_as, name)) => name.span.end,
+                None => instruction.image.span.end,
+            },
+            Instruction::Healthcheck(instruction) => match &instruction.arguments {
+                HealthcheckArguments::Cmd { arguments, .. } => command_end(arguments),
+                HealthcheckArguments::None { none } => none.span.end,
+            },
+            Instruction::Onbuild(instruction) => instruction.instruction.end(),
+            Instruction::Run(instruction) => {
+                here_docs_end(instruction.here_docs.last(), command_end(&instruction.arguments))
+            }
+            Instruction::Shell(instruction) => instruction.end,
+            Instruction::Unknown(instruction) => {
+                let end = instruction.arguments.span.end.max(instruction.keyword.span.end);
+                here_docs_end(instruction.here_docs.last(), end)
+            }
+            Instruction::Volume(instruction) => match &instruction.arguments {
+                JsonOrStringArray::Json(array) => array.span.end,
+                JsonOrStringArray::String(arguments) => {
+                    arguments.last().map_or(instruction.volume.span.end, |a| a.span.end)
+                }
+            },
+        }
+    }
+    /// Returns the option flags of this instruction.
+    ///
+    /// This returns an empty slice for instructions that do not accept options.
+    /// For `ONBUILD` instruction, this returns the options of the trigger instruction.
+    #[must_use]
+    pub fn options(&self) -> &[Flag<'a>] {
+        match self {
+            Instruction::Add(instruction) => &instruction.options,
+            Instruction::Copy(instruction) => &instruction.options,
+            Instruction::From(instruction) => &instruction.options,
+            Instruction::Healthcheck(instruction) => &instruction.options,
+            Instruction::Run(instruction) => &instruction.options,
+            Instruction::Onbuild(instruction) => instruction.instruction.options(),
+            _ => &[],
         }
     }
+    /// Returns the source text of the whole instruction.
+    ///
+    /// `text` must be the text passed to the parsing function that returned this
+    /// instruction. A leading UTF-8 BOM in `text` is ignored, as in parsing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the [span](Self::span) of this instruction is out of bounds of `text`.
+    #[must_use]
+    pub fn raw_text<'t>(&self, text: &'t str) -> &'t str {
+        let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+        &text[self.span()]
+    }
+}
+/// The kind of an [instruction](Instruction).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "SCREAMING_SNAKE_CASE"))]
+#[non_exhaustive]
+pub enum InstructionKind {
+    /// `ADD` instruction.
+    Add,
+    /// `ARG` instruction.
+    Arg,
+    /// `CMD` instruction.
+    Cmd,
+    /// `COPY` instruction.
+    Copy,
+    /// `ENTRYPOINT` instruction.
+    Entrypoint,
+    /// `ENV` instruction.
+    Env,
+    /// `EXPOSE` instruction.
+    Expose,
+    /// `FROM` instruction.
+    From,
+    /// `HEALTHCHECK` instruction.
+    Healthcheck,
+    /// `LABEL` instruction.
+    Label,
+    /// `MAINTAINER` instruction (deprecated).
+    Maintainer,
+    /// `ONBUILD` instruction.
+    Onbuild,
+    /// `RUN` instruction.
+    Run,
+    /// `SHELL` instruction.
+    Shell,
+    /// `STOPSIGNAL` instruction.
+    Stopsignal,
+    /// `USER` instruction.
+    User,
+    /// `VOLUME` instruction.
+    Volume,
+    /// `WORKDIR` instruction.
+    Workdir,
+    /// An [instruction not known to this parser](Instruction::Unknown).
+    ///
+    /// This is displayed as `"UNKNOWN"`, but parsing `"UNKNOWN"` doesn't return
+    /// this variant since it is not a keyword.
+    Unknown,
+}
+impl InstructionKind {
+    const ALL: [Self; 18] = [
+        Self::Add,
+        Self::Arg,
+        Self::Cmd,
+        Self::Copy,
+        Self::Entrypoint,
+        Self::Env,
+        Self::Expose,
+        Self::From,
+        Self::Healthcheck,
+        Self::Label,
+        Self::Maintainer,
+        Self::Onbuild,
+        Self::Run,
+        Self::Shell,
+        Self::Stopsignal,
+        Self::User,
+        Self::Volume,
+        Self::Workdir,
+    ];
+    /// Returns the keyword of this instruction kind in upper case, e.g., `"RUN"`.
+    ///
+    /// For [`InstructionKind::Unknown`], this returns `"UNKNOWN"`, which is not
+    /// accepted by [`FromStr`](str::FromStr). Use [`UnknownInstruction::name`]
+    /// to get the actual keyword.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Add => "ADD",
+            Self::Arg => "ARG",
+            Self::Cmd => "CMD",
+            Self::Copy => "COPY",
+            Self::Entrypoint => "ENTRYPOINT",
+            Self::Env => "ENV",
+            Self::Expose => "EXPOSE",
+            Self::From => "FROM",
+            Self::Healthcheck => "HEALTHCHECK",
+            Self::Label => "LABEL",
+            Self::Maintainer => "MAINTAINER",
+            Self::Onbuild => "ONBUILD",
+            Self::Run => "RUN",
+            Self::Shell => "SHELL",
+            Self::Stopsignal => "STOPSIGNAL",
+            Self::User => "USER",
+            Self::Volume => "VOLUME",
+            Self::Workdir => "WORKDIR",
+            Self::Unknown => "UNKNOWN",
+        }
+    }
+}
+impl fmt::Display for InstructionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl str::FromStr for InstructionKind {
+    type Err = ParseInstructionKindError;
+    /// Parses an instruction keyword. The keyword is case-insensitive as in dockerfile.
+    ///
+    /// This never returns [`InstructionKind::Unknown`].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.as_str().eq_ignore_ascii_case(s))
+            .ok_or(ParseInstructionKindError(()))
+    }
 }
 /// An `ADD` instruction.
 ///
@@ -750,6 +1268,12 @@ pub struct ShellInstruction<'a> {
     // Usually at least 2, e.g., ["/bin/sh", "-c"]
     // Common cases are 4, e.g., ["/bin/bash", "-o", "pipefail", "-c"]
     pub arguments: SmallVec<[UnescapedString<'a>; 4]>,
+    /// ```text
+    /// SHELL ["executable", "param"]
+    ///                              ^
+    /// ```
+    #[cfg_attr(feature = "serde", serde(skip))]
+    end: usize,
 }
 /// A `STOPSIGNAL` instruction.
 ///
@@ -827,6 +1351,32 @@ pub struct WorkdirInstruction<'a> {
     /// ```
     pub arguments: UnescapedString<'a>,
 }
+/// An instruction not known to this parser, such as extension instructions
+/// handled by preprocessors.
+///
+/// This is only returned when [`ParseOptions::unknown_instructions`] is enabled.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub struct UnknownInstruction<'a> {
+    /// ```text
+    /// INCLUDE ./common.Dockerfile
+    /// ^^^^^^^
+    /// ```
+    pub keyword: Keyword,
+    /// The keyword as written, e.g., `INCLUDE`.
+    pub name: Cow<'a, str>,
+    /// ```text
+    /// INCLUDE ./common.Dockerfile
+    ///         ^^^^^^^^^^^^^^^^^^^
+    /// ```
+    ///
+    /// Escape and line continuations are preserved as in shell-form commands.
+    pub arguments: Spanned<&'a str>,
+    /// Here-documents referenced by words starting with `<<` in the arguments.
+    pub here_docs: Vec<HereDoc<'a>>,
+}
 
 /// A keyword.
 #[derive(Debug)]
@@ -964,6 +1514,22 @@ pub struct HereDoc<'a> {
     pub expand: bool,
     #[allow(missing_docs)]
     pub value: Cow<'a, str>,
+    /// ```text
+    /// <<EOF
+    /// ...
+    /// EOF
+    ///    ^
+    /// ```
+    #[cfg_attr(feature = "serde", serde(skip))]
+    end: usize,
+    /// ```text
+    /// <<EOF
+    /// ...
+    /// EOF
+    /// ^^^
+    /// ```
+    #[cfg_attr(feature = "serde", serde(skip))]
+    delim: Cow<'a, str>,
 }
 
 /// A spanned value.
@@ -996,9 +1562,10 @@ pub struct ParseIter<'a> {
     has_stage: bool,
     in_onbuild: bool,
     parser_directives: ParserDirectives<'a>,
+    options: ParseOptions,
 }
 impl<'a> ParseIter<'a> {
-    fn new(mut text: &'a str) -> Result<Self> {
+    fn new(mut text: &'a str, options: ParseOptions) -> Result<Self> {
         // https://github.com/moby/moby/pull/23234
         if text.as_bytes().starts_with(UTF8_BOM) {
             text = &text[UTF8_BOM.len()..];
@@ -1006,8 +1573,8 @@ impl<'a> ParseIter<'a> {
         let mut p = Self {
             text,
             s: text.as_bytes(),
-            escape_byte: DEFAULT_ESCAPE_BYTE,
-            has_stage: false,
+            escape_byte: options.escape_byte,
+            has_stage: options.allow_no_stages,
             in_onbuild: false,
             parser_directives: ParserDirectives {
                 // https://docs.docker.com/reference/dockerfile/#parser-directives
@@ -1015,6 +1582,7 @@ impl<'a> ParseIter<'a> {
                 escape: None,
                 check: None,
             },
+            options,
         };
 
         parse_parser_directives(&mut p).map_err(|e| e.into_error(&p))?;
@@ -1055,7 +1623,7 @@ impl<'a> Iterator for ParseIter<'a> {
                     if !p.has_stage {
                         return Some(Err(error(
                             p,
-                            error::expected("FROM", instruction.instruction_span().start),
+                            error::expected("FROM", instruction.keyword().span.start),
                         )));
                     }
                 }
@@ -1090,7 +1658,7 @@ fn parse_parser_directives(p: &mut ParseIter<'_>) -> InternalResult<'static, ()>
                     p.parser_directives.syntax = None;
                     p.parser_directives.escape = None;
                     p.parser_directives.check = None;
-                    p.escape_byte = DEFAULT_ESCAPE_BYTE;
+                    p.escape_byte = p.options.escape_byte;
                     consume_current_line_no_line_continuation(&mut p.s);
                     break;
                 }
@@ -1115,7 +1683,7 @@ fn parse_parser_directives(p: &mut ParseIter<'_>) -> InternalResult<'static, ()>
                     p.parser_directives.syntax = None;
                     p.parser_directives.escape = None;
                     p.parser_directives.check = None;
-                    p.escape_byte = DEFAULT_ESCAPE_BYTE;
+                    p.escape_byte = p.options.escape_byte;
                     consume_current_line_no_line_continuation(&mut p.s);
                     break;
                 }
@@ -1140,7 +1708,7 @@ fn parse_parser_directives(p: &mut ParseIter<'_>) -> InternalResult<'static, ()>
                     p.parser_directives.syntax = None;
                     p.parser_directives.escape = None;
                     p.parser_directives.check = None;
-                    p.escape_byte = DEFAULT_ESCAPE_BYTE;
+                    p.escape_byte = p.options.escape_byte;
                     consume_current_line_no_line_continuation(&mut p.s);
                     break;
                 }
@@ -1151,7 +1719,7 @@ fn parse_parser_directives(p: &mut ParseIter<'_>) -> InternalResult<'static, ()>
                 let value = trim_end(p.text, value_start, end);
                 match value {
                     "`" => p.escape_byte = b'`',
-                    "\\" => {}
+                    "\\" => p.escape_byte = b'\\',
                     _ => return Err(error::invalid_escape(value_start)),
                 }
                 p.parser_directives.escape = Some(ParserDirective {
@@ -1376,6 +1944,10 @@ fn parse_instruction<'a>(
         }
         _ => {}
     }
+    if p.options.unknown_instructions {
+        *s = &p.text.as_bytes()[instruction_start..];
+        return parse_unknown(p, s);
+    }
     Err(error::unknown_instruction(instruction_start))
 }
 
@@ -1439,7 +2011,7 @@ fn parse_add_or_copy<'a>(
         let Source::Path(path) = src else { unreachable!() };
         let full = mem::take(&mut path.value);
         let mut tmp = full.as_bytes();
-        if let Some(tmp_next) = tmp.strip_prefix(b"<<") {
+        if let Some(tmp_next) = tmp.strip_prefix(b"<<").filter(|_| p.options.here_docs) {
             if let Some((delim, strip_tab, expand)) =
                 collect_here_doc_delim(&mut tmp, tmp_next, &full)?
             {
@@ -1461,8 +2033,7 @@ fn parse_add_or_copy<'a>(
                     },
                     Cow::Owned(v) => Cow::Owned(v),
                 };
-                let (here_doc, span) = collect_here_doc(s, p.text, delim, strip_tab)?;
-                *src = Source::HereDoc(HereDoc { span, expand, value: here_doc });
+                *src = Source::HereDoc(collect_here_doc(s, p.text, delim, strip_tab, expand)?);
                 continue;
             }
         }
@@ -1857,7 +2428,7 @@ fn parse_run<'a>(
 
     // https://docs.docker.com/reference/dockerfile/#here-documents
     // At least 5, <<E\nE
-    if s.len() >= 5 {
+    if p.options.here_docs && s.len() >= 5 {
         if let Some(s_next) = s.strip_prefix(b"<<") {
             if let Some((delim, strip_tab, expand)) = collect_here_doc_delim(s, s_next, p.text)? {
                 // TODO: skip space
@@ -1865,8 +2436,7 @@ fn parse_run<'a>(
                 consume_current_line(s, p.escape_byte);
                 let end = p.text.len() - s.len();
                 let arguments = trim_end(p.text, arguments_start, end);
-                let (here_doc, span) = collect_here_doc(s, p.text, delim, strip_tab)?;
-                let here_doc = HereDoc { span, expand, value: here_doc };
+                let here_doc = collect_here_doc(s, p.text, delim, strip_tab, expand)?;
                 return Ok(Instruction::Run(RunInstruction {
                     run: instruction,
                     options,
@@ -1910,7 +2480,7 @@ fn parse_shell<'a>(
     if !is_maybe_json(s) {
         return Err(error::expected("JSON array", p.text.len() - s.len()));
     }
-    let (arguments, _array_span) =
+    let (arguments, array_span) =
         parse_json_array::<SmallVec<[_; 4]>>(s, p.text, p.escape_byte).map_err(error::json)?;
     if let Some((&b, s_next)) = s.split_first() {
         let consumed = consume_newline(b, s, s_next);
@@ -1919,7 +2489,7 @@ fn parse_shell<'a>(
     if arguments.is_empty() {
         return Err(error::at_least_one_argument(instruction.span.start));
     }
-    Ok(Instruction::Shell(ShellInstruction { shell: instruction, arguments }))
+    Ok(Instruction::Shell(ShellInstruction { shell: instruction, arguments, end: array_span.end }))
 }
 
 #[inline]
@@ -2021,6 +2591,51 @@ fn parse_workdir<'a>(
     Ok(Instruction::Workdir(WorkdirInstruction { workdir: instruction, arguments }))
 }
 
+#[cold]
+fn parse_unknown<'a>(
+    p: &mut ParseIter<'a>,
+    s: &mut &'a [u8],
+) -> InternalResult<'a, Instruction<'a>> {
+    let name = collect_non_whitespace(s, p.text, p.escape_byte);
+    let keyword = Keyword { span: name.span };
+    consume_whitespaces_or_is_empty_line(s, p.escape_byte);
+    let arguments_start = p.text.len() - s.len();
+    consume_current_line(s, p.escape_byte);
+    let end = p.text.len() - s.len();
+    let arguments = trim_end(p.text, arguments_start, end);
+    let arguments_end = arguments_start + arguments.len();
+
+    // https://docs.docker.com/reference/dockerfile/#here-documents
+    let mut here_docs = vec![];
+    if p.options.here_docs {
+        let mut tmp = &p.text.as_bytes()[arguments_start..arguments_end];
+        let mut prev = b' ';
+        while let Some((&b, tmp_next)) = tmp.split_first() {
+            let word_start = TABLE[prev as usize] & (WHITESPACE | POSSIBLE_LINE) != 0;
+            if word_start && b == b'<' {
+                if let Some(tmp_next) = tmp.strip_prefix(b"<<") {
+                    if let Some((delim, strip_tab, expand)) =
+                        collect_here_doc_delim(&mut tmp, tmp_next, &p.text[..arguments_end])?
+                    {
+                        here_docs.push(collect_here_doc(s, p.text, delim, strip_tab, expand)?);
+                        prev = b' ';
+                        continue;
+                    }
+                }
+            }
+            prev = b;
+            tmp = tmp_next;
+        }
+    }
+
+    Ok(Instruction::Unknown(UnknownInstruction {
+        keyword,
+        name: name.value,
+        arguments: Spanned { span: arguments_start..arguments_end, value: arguments },
+        here_docs,
+    }))
+}
+
 // -----------------------------------------------------------------------------
 // Parsing Helpers
 
@@ -2532,7 +3147,8 @@ fn collect_here_doc<'a>(
     start: &'a str,
     delim_cow: Cow<'a, [u8]>,
     strip_tab: bool,
-) -> InternalResult<'a, (Cow<'a, str>, Span)> {
+    expand: bool,
+) -> InternalResult<'a, HereDoc<'a>> {
     let delim: &[u8] = &delim_cow;
     let here_doc_start = start.len() - s.len();
     let mut current_start = here_doc_start;
@@ -2569,12 +3185,19 @@ fn collect_here_doc<'a>(
         consume_current_line_no_line_continuation(s);
     }
     let span = here_doc_start..end;
-    if here_doc_start == current_start {
-        Ok((Cow::Borrowed(&start[span.clone()]), span))
+    let delim_end = end + delim.len();
+    let value = if here_doc_start == current_start {
+        Cow::Borrowed(&start[span.clone()])
     } else {
         buf.push_str(&start[current_start..end]);
-        Ok((Cow::Owned(buf), span))
-    }
+        Cow::Owned(buf)
+    };
+    // The delimiter is a part of valid UTF-8 text split at ASCII characters.
+    let delim = match delim_cow {
+        Cow::Borrowed(delim) => Cow::Borrowed(str::from_utf8(delim).unwrap()),
+        Cow::Owned(delim) => Cow::Owned(String::from_utf8(delim).unwrap()),
+    };
+    Ok(HereDoc { span, expand, value, end: delim_end, delim })
 }
 
 // TODO: escaped/quoted space?
@@ -3,6 +3,7 @@
 #![forbid(unsafe_code)]
 
 use std::{
+    fmt::Write as _,
     fs,
     io::{self, BufWriter, Read as _, Write as _},
     path::{Path, PathBuf},
@@ -10,7 +11,10 @@ use std::{
 };
 
 use lexopt::Arg::{Long, Short, Value};
-use parse_dockerfile::parse;
+use parse_dockerfile::{
+    Edge, EdgeKind, FormatStyle, Instruction, Node, Source, StageGraph, SyntaxTree, apply_edits,
+    parse,
+};
 
 type Result<T, E = Box<dyn std::error::Error + Send + Sync>> = std::result::Result<T, E>;
 
@@ -26,51 +30,278 @@ Parse a dockerfile and output a JSON representation of all instructions in docke
 
 USAGE:
     parse-dockerfile [OPTIONS] <PATH>
+    parse-dockerfile fmt [FMT_OPTIONS] <PATH>...
+    parse-dockerfile set-image [SET_IMAGE_OPTIONS] <PATH>
+    parse-dockerfile prune --target <NAME> <PATH>
+    parse-dockerfile graph [GRAPH_OPTIONS] <PATH>
+    parse-dockerfile config [CONFIG_OPTIONS] <PATH>
+    parse-dockerfile inputs [INPUTS_OPTIONS] <PATH>
+    parse-dockerfile plan [PLAN_OPTIONS] <PATH>
 
 ARGS:
-    <PATH>       Path to the dockerfile (use '-' for standard input)
+    <PATH>       Path to the dockerfile (use '-' for standard input, and add a directory prefix
+                 such as './fmt' to a path that is also the name of a subcommand)
+
+SUBCOMMANDS:
+    fmt          Format dockerfiles and output the result
+    set-image    Replace images in a dockerfile and output the result
+    prune        Output a dockerfile that contains only the stages needed to build the target
+    graph        Output the dependency graph of stages in Graphviz DOT or Mermaid format
+    config       Output the image config of a stage in JSON
+    inputs       Output the files in the build context read by stages in JSON
+    plan         Output the layers and the inputs affecting cache keys of instructions in JSON
 
 OPTIONS:
     -h, --help                        Print help information
     -V, --version                     Print version information
+
+FMT_OPTIONS:
+        --check                       Exit with failure if any dockerfile is not formatted
+        --write                       Overwrite dockerfiles with the formatted text
+        --indent-width <WIDTH>        Number of spaces used to indent continuation lines [default: 4]
+        --no-split-commands           Do not put each `&&`-chained command in RUN on its own line
+
+SET_IMAGE_OPTIONS:
+        --image <OLD>=<NEW>           Replace image OLD with NEW (can be specified multiple times)
+        --write                       Overwrite the dockerfile with the result
+
+PRUNE_OPTIONS:
+        --target <NAME>               Name of the target stage
+
+GRAPH_OPTIONS:
+        --build-arg <NAME>=<VALUE>    Set a build arg (can be specified multiple times)
+        --format <FORMAT>             Output format [default: dot] [possible values: dot, mermaid]
+        --target <NAME>               Highlight the stages needed to build the target stage
+
+CONFIG_OPTIONS:
+        --build-arg <NAME>=<VALUE>    Set a build arg (can be specified multiple times)
+        --target <NAME>               Name of the stage [default: the last stage]
+
+INPUTS_OPTIONS:
+        --build-arg <NAME>=<VALUE>    Set a build arg (can be specified multiple times)
+        --target <NAME>               Output only the inputs of the stages needed to build the target stage
+
+PLAN_OPTIONS:
+        --build-arg <NAME>=<VALUE>    Set a build arg (can be specified multiple times)
+        --target <NAME>               Output only the steps of the stages needed to build the target stage
 ";
 
+enum Subcommand {
+    Parse,
+    Fmt(FmtOptions),
+    SetImage(SetImageOptions),
+    Prune { target: Option<String> },
+    Graph(GraphOptions),
+    Config(BuildOptions),
+    Inputs(BuildOptions),
+    Plan(BuildOptions),
+}
+
+#[derive(Default)]
+struct FmtOptions {
+    check: bool,
+    write: bool,
+    indent_width: Option<usize>,
+    no_split_commands: bool,
+}
+
+#[derive(Default)]
+struct SetImageOptions {
+    images: Vec<(String, String)>,
+    write: bool,
+}
+
+#[derive(Default)]
+struct GraphOptions {
+    build_args: Vec<(String, String)>,
+    format: GraphFormat,
+    target: Option<String>,
+}
+
+#[derive(Default)]
+struct BuildOptions {
+    build_args: Vec<(String, String)>,
+    target: Option<String>,
+}
+
+#[derive(Clone, Copy, Default)]
+enum GraphFormat {
+    #[default]
+    Dot,
+    Mermaid,
+}
+
 struct Args {
-    path: PathBuf,
+    subcommand: Subcommand,
+    paths: Vec<PathBuf>,
 }
 
 impl Args {
     fn parse() -> Result<Option<Self>> {
-        let mut path = None;
+        let mut subcommand = None;
+        let mut paths: Vec<PathBuf> = vec![];
 
         let mut parser = lexopt::Parser::from_env();
         while let Some(arg) = parser.next()? {
-            match arg {
-                Short('h') | Long("help") => {
+            match (&mut subcommand, arg) {
+                (_, Short('h') | Long("help")) => {
                     print!("{USAGE}");
                     return Ok(None);
                 }
-                Short('V') | Long("version") => {
+                (_, Short('V') | Long("version")) => {
                     println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
                     return Ok(None);
                 }
-                Value(val) if path.is_none() => path = Some(val.into()),
-                _ => return Err(arg.unexpected().into()),
+                (None, Value(val)) if paths.is_empty() && val == "fmt" => {
+                    subcommand = Some(Subcommand::Fmt(FmtOptions::default()));
+                }
+                (Some(Subcommand::Fmt(options)), Long("check")) => options.check = true,
+                (Some(Subcommand::Fmt(options)), Long("write")) => options.write = true,
+                (Some(Subcommand::Fmt(options)), Long("indent-width")) => {
+                    let val = parser.value()?;
+                    let val = val.to_str().and_then(|v| v.parse().ok()).ok_or_else(|| {
+                        format!("invalid value {val:?} for '--indent-width <WIDTH>'")
+                    })?;
+                    options.indent_width = Some(val);
+                }
+                (Some(Subcommand::Fmt(options)), Long("no-split-commands")) => {
+                    options.no_split_commands = true;
+                }
+                (Some(Subcommand::Fmt(..)), Value(val)) => paths.push(val.into()),
+                (None, Value(val)) if paths.is_empty() && val == "set-image" => {
+                    subcommand = Some(Subcommand::SetImage(SetImageOptions::default()));
+                }
+                (Some(Subcommand::SetImage(options)), Long("image")) => {
+                    let val = parser.value()?;
+                    let (old, new) = val
+                        .to_str()
+                        .and_then(|v| v.split_once('='))
+                        .filter(|(old, new)| !old.is_empty() && !new.is_empty())
+                        .ok_or_else(|| {
+                            format!("invalid value {val:?} for '--image <OLD>=<NEW>'")
+                        })?;
+                    options.images.push((old.to_owned(), new.to_owned()));
+                }
+                (Some(Subcommand::SetImage(options)), Long("write")) => options.write = true,
+                (None, Value(val)) if paths.is_empty() && val == "prune" => {
+                    subcommand = Some(Subcommand::Prune { target: None });
+                }
+                (Some(Subcommand::Prune { target }), Long("target")) => {
+                    *target =
+                        Some(parser.value()?.into_string().map_err(|val| {
+                            format!("invalid value {val:?} for '--target <NAME>'")
+                        })?);
+                }
+                (None, Value(val)) if paths.is_empty() && val == "graph" => {
+                    subcommand = Some(Subcommand::Graph(GraphOptions::default()));
+                }
+                (Some(Subcommand::Graph(options)), Long("build-arg")) => {
+                    options.build_args.push(parse_build_arg(&mut parser)?);
+                }
+                (Some(Subcommand::Graph(options)), Long("format")) => {
+                    let val = parser.value()?;
+                    options.format = match val.to_str() {
+                        Some("dot") => GraphFormat::Dot,
+                        Some("mermaid") => GraphFormat::Mermaid,
+                        _ => bail!("invalid value {val:?} for '--format <FORMAT>'"),
+                    };
+                }
+                (Some(Subcommand::Graph(options)), Long("target")) => {
+                    options.target =
+                        Some(parser.value()?.into_string().map_err(|val| {
+                            format!("invalid value {val:?} for '--target <NAME>'")
+                        })?);
+                }
+                (None, Value(val)) if paths.is_empty() && val == "config" => {
+                    subcommand = Some(Subcommand::Config(BuildOptions::default()));
+                }
+                (None, Value(val)) if paths.is_empty() && val == "plan" => {
+                    subcommand = Some(Subcommand::Plan(BuildOptions::default()));
+                }
+                (None, Value(val)) if paths.is_empty() && val == "inputs" => {
+                    subcommand = Some(Subcommand::Inputs(BuildOptions::default()));
+                }
+                (
+                    Some(
+                        Subcommand::Config(options)
+                        | Subcommand::Inputs(options)
+                        | Subcommand::Plan(options),
+                    ),
+                    Long("build-arg"),
+                ) => {
+                    options.build_args.push(parse_build_arg(&mut parser)?);
+                }
+                (
+                    Some(
+                        Subcommand::Config(options)
+                        | Subcommand::Inputs(options)
+                        | Subcommand::Plan(options),
+                    ),
+                    Long("target"),
+                ) => {
+                    options.target =
+                        Some(parser.value()?.into_string().map_err(|val| {
+                            format!("invalid value {val:?} for '--target <NAME>'")
+                        })?);
+                }
+                (_, Value(val)) if paths.is_empty() => paths.push(val.into()),
+                (_, arg) => return Err(arg.unexpected().into()),
             }
         }
 
-        let Some(path) = path else { bail!("no dockerfile path specified") };
+        if paths.is_empty() {
+            bail!("no dockerfile path specified");
+        }
+        let subcommand = subcommand.unwrap_or(Subcommand::Parse);
+        match &subcommand {
+            Subcommand::Parse
+            | Subcommand::Graph(..)
+            | Subcommand::Config(..)
+            | Subcommand::Inputs(..)
+            | Subcommand::Plan(..) => {}
+            Subcommand::Fmt(options) => {
+                if options.check && options.write {
+                    bail!("--check and --write may not be used together");
+                }
+                if options.write && paths.iter().any(|p| is_stdin(p)) {
+                    bail!("--write may not be used with standard input");
+                }
+            }
+            Subcommand::SetImage(options) => {
+                if options.images.is_empty() {
+                    bail!("no image specified; use --image <OLD>=<NEW>");
+                }
+                if options.write && is_stdin(&paths[0]) {
+                    bail!("--write may not be used with standard input");
+                }
+            }
+            Subcommand::Prune { target } => {
+                if target.is_none() {
+                    bail!("no target specified; use --target <NAME>");
+                }
+            }
+        }
 
-        Ok(Some(Self { path }))
+        Ok(Some(Self { subcommand, paths }))
     }
+}
 
-    fn path_for_msg(&self) -> &Path {
-        if self.path.as_os_str() == "-" {
-            Path::new("dockerfile (standard input)")
-        } else {
-            &self.path
-        }
-    }
+fn parse_build_arg(parser: &mut lexopt::Parser) -> Result<(String, String)> {
+    let val = parser.value()?;
+    let (name, value) = val
+        .to_str()
+        .and_then(|v| v.split_once('='))
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| format!("invalid value {val:?} for '--build-arg <NAME>=<VALUE>'"))?;
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+fn is_stdin(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+fn path_for_msg(path: &Path) -> &Path {
+    if is_stdin(path) { Path::new("dockerfile (standard input)") } else { path }
 }
 
 fn main() -> ExitCode {
@@ -85,30 +316,346 @@ fn main() -> ExitCode {
 fn try_main() -> Result<()> {
     let Some(args) = Args::parse()? else { return Ok(()) };
 
-    let text = if args.path.as_os_str() == "-" {
-        let mut buf = String::with_capacity(128);
-        io::stdin()
-            .read_to_string(&mut buf)
-            .map_err(|e| format!("failed to read from standard input: {e}"))?;
-        buf
+    match &args.subcommand {
+        Subcommand::Parse => {
+            let path = &args.paths[0];
+            let text = read(path)?;
+            let dockerfile = parse(&text).map_err(|e| parse_error(path, &e))?;
+
+            let mut stdout = BufWriter::new(io::stdout().lock()); // Buffered because it is written many times.
+            serde_json::to_writer(&mut stdout, &dockerfile)?;
+            stdout.flush()?;
+        }
+        Subcommand::Fmt(options) => fmt(&args.paths, options)?,
+        Subcommand::SetImage(options) => set_image(&args.paths[0], options)?,
+        Subcommand::Prune { target } => {
+            let path = &args.paths[0];
+            let text = read(path)?;
+            let dockerfile = parse(&text).map_err(|e| parse_error(path, &e))?;
+            let pruned = dockerfile.prune(&text, target.as_deref().unwrap())?;
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(pruned.as_bytes())?;
+            stdout.flush()?;
+        }
+        Subcommand::Graph(options) => graph(&args.paths[0], options)?,
+        Subcommand::Config(options) => config(&args.paths[0], options)?,
+        Subcommand::Plan(options) => plan(&args.paths[0], options)?,
+        Subcommand::Inputs(options) => {
+            let path = &args.paths[0];
+            let text = read(path)?;
+            let dockerfile = parse(&text).map_err(|e| parse_error(path, &e))?;
+            let inputs = match &options.target {
+                Some(target) => dockerfile.context_inputs_for(target, &options.build_args)?,
+                None => dockerfile.context_inputs(&options.build_args)?,
+            };
+            let mut stdout = io::stdout().lock();
+            serde_json::to_writer(&mut stdout, &inputs)?;
+            stdout.flush()?;
+        }
+    }
+
+    Ok(())
+}
+
+fn fmt(paths: &[PathBuf], options: &FmtOptions) -> Result<()> {
+    let mut style = FormatStyle::new().split_commands(!options.no_split_commands);
+    if let Some(indent_width) = options.indent_width {
+        style = style.indent_width(indent_width);
+    }
+    let mut unformatted = 0;
+    let mut stdout = io::stdout().lock();
+    for path in paths {
+        let text = read(path)?;
+        let tree = SyntaxTree::parse(&text).map_err(|e| parse_error(path, &e))?;
+        let formatted = tree.format(&style);
+        if options.check {
+            if formatted != text {
+                eprintln!("{} is not formatted", path_for_msg(path).display());
+                unformatted += 1;
+            }
+        } else if options.write {
+            if formatted != text {
+                fs::write(path, formatted)
+                    .map_err(|e| format!("failed to write to file `{}`: {e}", path.display()))?;
+            }
+        } else {
+            stdout.write_all(formatted.as_bytes())?;
+        }
+    }
+    stdout.flush()?;
+    if unformatted != 0 {
+        bail!("{unformatted} of {} dockerfile(s) are not formatted", paths.len());
+    }
+    Ok(())
+}
+
+fn set_image(path: &Path, options: &SetImageOptions) -> Result<()> {
+    let text = read(path)?;
+    let dockerfile = parse(&text).map_err(|e| parse_error(path, &e))?;
+    let images: Vec<_> =
+        options.images.iter().map(|(old, new)| (old.as_str(), new.as_str())).collect();
+    let update = dockerfile.update_images(&images);
+    let new_text = apply_edits(&text, &update.edits).map_err(|e| parse_error(path, &e))?;
+    // Spans are relative to the text without BOM.
+    let body = text.strip_prefix('\u{FEFF}').unwrap_or(&text);
+    for edit in &update.edits {
+        let (line, column) = location(body, edit.span.start);
+        eprintln!(
+            "{}:{line}:{column}: `{}` -> `{}`",
+            path_for_msg(path).display(),
+            &body[edit.span.clone()],
+            edit.replacement
+        );
+    }
+    for span in &update.unresolved {
+        let (line, column) = location(body, span.start);
+        eprintln!(
+            "warning: {}:{line}:{column}: `{}` contains variables that cannot be resolved statically",
+            path_for_msg(path).display(),
+            &body[span.clone()]
+        );
+    }
+    if options.write {
+        if new_text != text {
+            fs::write(path, new_text)
+                .map_err(|e| format!("failed to write to file `{}`: {e}", path.display()))?;
+        }
     } else {
-        fs::read_to_string(&args.path)
-            .map_err(|e| format!("failed to read from file `{}`: {e}", args.path.display()))?
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(new_text.as_bytes())?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn graph(path: &Path, options: &GraphOptions) -> Result<()> {
+    let text = read(path)?;
+    let dockerfile = parse(&text).map_err(|e| parse_error(path, &e))?;
+    let graph = dockerfile.graph_with_args(&options.build_args)?;
+    let target = match &options.target {
+        Some(name) => match dockerfile.stage(name) {
+            Some(stage) => Some(stage.index()),
+            None => bail!("stage {name:?} not found"),
+        },
+        None => None,
+    };
+    let mut used_stages = vec![target.is_none(); dockerfile.stages().len()];
+    if let Some(target) = target {
+        for i in graph.build_order(target) {
+            used_stages[i] = true;
+        }
+    }
+    let mut out = String::with_capacity(text.len());
+    match options.format {
+        GraphFormat::Dot => write_dot(&mut out, &graph, &used_stages, target),
+        GraphFormat::Mermaid => write_mermaid(&mut out, &graph, &used_stages, target),
+    }
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(out.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Returns nodes of `graph` with their labels and whether they are needed to
+/// build the target.
+fn graph_nodes(graph: &StageGraph<'_, '_>, used_stages: &[bool]) -> Vec<(Node, String, bool)> {
+    let mut nodes = vec![];
+    for stage in graph.dockerfile().stages() {
+        let label = match stage.name() {
+            Some(name) => name.value.to_string(),
+            None => format!("stage {}", stage.index()),
+        };
+        nodes.push((Node::Stage(stage.index()), label, used_stages[stage.index()]));
+    }
+    let used = |node: Node| graph.edges().iter().any(|e| e.from == node && used_stages[e.to]);
+    for (i, image) in graph.images().iter().enumerate() {
+        nodes.push((Node::Image(i), image.clone(), used(Node::Image(i))));
+    }
+    if graph.edges().iter().any(|e| e.from == Node::Context) {
+        nodes.push((Node::Context, "build context".to_owned(), used(Node::Context)));
+    }
+    nodes
+}
+
+fn node_id(node: Node) -> String {
+    match node {
+        Node::Stage(i) => format!("stage_{i}"),
+        Node::Image(i) => format!("image_{i}"),
+        _ => "context".to_owned(),
+    }
+}
+
+/// Returns the label of `edge`: the copied paths for `COPY`/`ADD`.
+fn edge_label(edge: &Edge<'_, '_>) -> Option<String> {
+    let src = match edge.instruction {
+        Instruction::Copy(copy) => &copy.src,
+        Instruction::Add(add) => &add.src,
+        _ if edge.kind == EdgeKind::Mount => return Some("mount".to_owned()),
+        _ => return None,
     };
+    let paths: Vec<_> = src
+        .iter()
+        .filter_map(|src| match src {
+            Source::Path(path) => Some(&*path.value),
+            _ => None,
+        })
+        .collect();
+    Some(paths.join(", "))
+}
 
-    let dockerfile = match parse(&text) {
-        Ok(dockerfile) => dockerfile,
-        Err(e) => {
-            if args.path.as_os_str() == "-" {
-                bail!("{e} in {}", args.path_for_msg().display())
-            }
-            bail!("{e:#} at {}:{}:{}", args.path_for_msg().display(), e.line(), e.column())
+fn write_dot(out: &mut String, graph: &StageGraph<'_, '_>, used: &[bool], target: Option<usize>) {
+    fn quote(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+    out.push_str("digraph {\n");
+    for (node, label, used) in graph_nodes(graph, used) {
+        let shape = match node {
+            Node::Stage(..) => "box",
+            Node::Image(..) => "ellipse",
+            _ => "folder",
+        };
+        let _ = write!(out, "    {} [label={}, shape={shape}", node_id(node), quote(&label));
+        if !used {
+            out.push_str(", color=gray, fontcolor=gray");
+        } else if target.is_some_and(|t| node == Node::Stage(t)) {
+            out.push_str(", penwidth=2");
+        }
+        out.push_str("];\n");
+    }
+    for edge in graph.edges() {
+        let _ = write!(out, "    {} -> {} [", node_id(edge.from), node_id(Node::Stage(edge.to)));
+        let mut attrs = vec![];
+        if edge.kind == EdgeKind::From {
+            attrs.push("style=bold".to_owned());
+        } else if edge.kind == EdgeKind::Mount {
+            attrs.push("style=dashed".to_owned());
+        }
+        if let Some(label) = edge_label(edge) {
+            attrs.push(format!("label={}", quote(&label)));
         }
+        if !used[edge.to] {
+            attrs.push("color=gray, fontcolor=gray".to_owned());
+        }
+        out.push_str(&attrs.join(", "));
+        out.push_str("];\n");
+    }
+    out.push_str("}\n");
+}
+
+fn write_mermaid(
+    out: &mut String,
+    graph: &StageGraph<'_, '_>,
+    used: &[bool],
+    target: Option<usize>,
+) {
+    fn quote(s: &str) -> String {
+        format!("\"{}\"", s.replace('"', "#quot;"))
+    }
+    out.push_str("flowchart TD\n");
+    let mut unused_nodes = vec![];
+    for (node, label, used) in graph_nodes(graph, used) {
+        let id = node_id(node);
+        let label = quote(&label);
+        let _ = match node {
+            Node::Stage(..) => writeln!(out, "    {id}[{label}]"),
+            Node::Image(..) => writeln!(out, "    {id}([{label}])"),
+            _ => writeln!(out, "    {id}[({label})]"),
+        };
+        if !used {
+            unused_nodes.push(id);
+        }
+    }
+    let mut unused_edges = vec![];
+    for (i, edge) in graph.edges().iter().enumerate() {
+        let arrow = if edge.kind == EdgeKind::From {
+            "==>"
+        } else if edge.kind == EdgeKind::Mount {
+            "-.->"
+        } else {
+            "-->"
+        };
+        let _ = write!(out, "    {} {arrow}", node_id(edge.from));
+        if let Some(label) = edge_label(edge) {
+            let _ = write!(out, "|{}|", quote(&label));
+        }
+        let _ = writeln!(out, " {}", node_id(Node::Stage(edge.to)));
+        if !used[edge.to] {
+            unused_edges.push(i.to_string());
+        }
+    }
+    if !unused_nodes.is_empty() {
+        out.push_str("    classDef unused color:gray,stroke:gray\n");
+        let _ = writeln!(out, "    class {} unused", unused_nodes.join(","));
+    }
+    if !unused_edges.is_empty() {
+        let _ = writeln!(out, "    linkStyle {} stroke:gray", unused_edges.join(","));
+    }
+    if let Some(target) = target {
+        out.push_str("    classDef target stroke-width:3px\n");
+        let _ = writeln!(out, "    class {} target", node_id(Node::Stage(target)));
+    }
+}
+
+/// Returns 1-based line and column of `pos` in `text`.
+fn location(text: &str, pos: usize) -> (usize, usize) {
+    let before = &text[..pos];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+fn config(path: &Path, options: &BuildOptions) -> Result<()> {
+    let text = read(path)?;
+    let dockerfile = parse(&text).map_err(|e| parse_error(path, &e))?;
+    let stage = match &options.target {
+        Some(name) => match dockerfile.stage(name) {
+            Some(stage) => stage,
+            None => bail!("stage {name:?} not found"),
+        },
+        None => dockerfile.target().unwrap(),
     };
+    let mut configs = dockerfile.image_configs(&text, &options.build_args)?;
+    let config = configs.swap_remove(stage.index());
+    let mut stdout = io::stdout().lock();
+    serde_json::to_writer(&mut stdout, &config)?;
+    stdout.flush()?;
+    Ok(())
+}
 
+fn plan(path: &Path, options: &BuildOptions) -> Result<()> {
+    let text = read(path)?;
+    let dockerfile = parse(&text).map_err(|e| parse_error(path, &e))?;
+    let mut plan = dockerfile.build_plan(&text, &options.build_args)?;
+    if let Some(name) = &options.target {
+        let Some(target) = dockerfile.stage(name) else { bail!("stage {name:?} not found") };
+        let mut needed = vec![false; dockerfile.stages().len()];
+        for i in dockerfile.graph_with_args(&options.build_args)?.build_order(target.index()) {
+            needed[i] = true;
+        }
+        plan.retain(|step| needed[step.stage]);
+    }
     let mut stdout = BufWriter::new(io::stdout().lock()); // Buffered because it is written many times.
-    serde_json::to_writer(&mut stdout, &dockerfile)?;
+    serde_json::to_writer(&mut stdout, &plan)?;
     stdout.flush()?;
-
     Ok(())
 }
+
+fn read(path: &Path) -> Result<String> {
+    if is_stdin(path) {
+        let mut buf = String::with_capacity(128);
+        io::stdin()
+            .read_to_string(&mut buf)
+            .map_err(|e| format!("failed to read from standard input: {e}"))?;
+        Ok(buf)
+    } else {
+        Ok(fs::read_to_string(path)
+            .map_err(|e| format!("failed to read from file `{}`: {e}", path.display()))?)
+    }
+}
+
+fn parse_error(path: &Path, e: &parse_dockerfile::Error) -> String {
+    if is_stdin(path) {
+        format!("{e} in {}", path_for_msg(path).display())
+    } else {
+        format!("{e:#} at {}:{}:{}", path_for_msg(path).display(), e.line(), e.column())
+    }
+}
@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{collections::BTreeMap, string::String, vec, vec::Vec};
+use core::ptr;
+
+use crate::{
+    Dockerfile, Instruction, InstructionKind, Source,
+    config::join_lines,
+    error::Result,
+    expand::Env,
+    graph::{self, EdgeKind, Node},
+    scope::{Scope, VariableKind},
+};
+
+/// A step of the build plan, corresponding to an instruction in a stage.
+///
+/// See [`Dockerfile::build_plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde_derive::Serialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+#[non_exhaustive]
+pub struct PlanStep {
+    /// The index of the stage.
+    pub stage: usize,
+    /// The index of the instruction in [`Dockerfile::instructions`].
+    pub instruction: usize,
+    /// The kind of the instruction.
+    pub kind: InstructionKind,
+    /// Whether the instruction creates a filesystem layer (`RUN`, `COPY`, and
+    /// `ADD`). Other instructions change only the metadata of the image.
+    pub layer: bool,
+    /// The text of the instruction, with variables expanded in the parts that
+    /// BuildKit expands (see [`Dockerfile::variable_references`]) and line
+    /// continuations removed.
+    pub text: String,
+    /// The files in the build context read by the instruction (see
+    /// [`Dockerfile::context_inputs`]).
+    pub context_files: Vec<String>,
+    /// The stages referenced by `FROM`, `COPY --from`, or `RUN --mount=from=`.
+    pub from_stages: Vec<usize>,
+    /// The external images referenced by `FROM`, `COPY --from`, or
+    /// `RUN --mount=from=`.
+    pub from_images: Vec<String>,
+    /// The values of `--mount` flags of `RUN`, with variables expanded.
+    pub mounts: Vec<String>,
+    /// The build args (`ARG`s) consumed by the instruction and their values.
+    pub build_args: BTreeMap<String, String>,
+}
+
+impl Dockerfile<'_> {
+    /// Returns the build plan, i.e., the steps for instructions in all stages
+    /// with the inputs that affect their cache keys, in the order of instructions.
+    ///
+    /// `text` must be the text passed to the parsing function that returned
+    /// this dockerfile, and `build_args` are values passed with `--build-arg`.
+    ///
+    /// Like BuildKit, `RUN` consumes all `ARG`s of the stage that have values
+    /// (since they are set as environment variables of the command), and other
+    /// instructions consume only `ARG`s referenced in the expanded parts.
+    ///
+    /// ```
+    /// use parse_dockerfile::parse;
+    ///
+    /// let text = "ARG VERSION=1.80
+    /// FROM rust:$VERSION AS build
+    /// ARG PROFILE=release
+    /// COPY Cargo.toml src/ /app/
+    /// RUN cargo build --profile $PROFILE
+    ///
+    /// FROM debian
+    /// COPY --from=build /app/target/release/app /usr/local/bin/
+    /// CMD [\"app\"]
+    /// ";
+    /// let dockerfile = parse(text).unwrap();
+    /// let plan = dockerfile.build_plan(text, &[("VERSION", "1.81")]).unwrap();
+    ///
+    /// assert_eq!(plan[0].text, "FROM rust:1.81 AS build");
+    /// assert_eq!(plan[0].from_images, ["rust:1.81"]);
+    /// assert!(plan[2].layer);
+    /// assert_eq!(plan[2].context_files, ["Cargo.toml", "src/"]);
+    /// // `RUN` consumes all build args declared in the stage.
+    /// assert_eq!(plan[3].build_args["PROFILE"], "release");
+    /// assert_eq!(plan[5].from_stages, [0]);
+    /// assert!(!plan[6].layer);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if expanding variables failed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the span of an instruction is out of bounds of `text`.
+    pub fn build_plan<E: ?Sized + Env>(&self, text: &str, build_args: &E) -> Result<Vec<PlanStep>> {
+        let scopes = self.scopes(build_args)?;
+        let graph = graph::collect(self, build_args, false)?;
+        let expander = self.expander();
+        let references = self.variable_references(text);
+        let text = text.strip_prefix('\u{FEFF}').unwrap_or(text);
+        let mut context_inputs = self.context_inputs(build_args)?.into_iter().peekable();
+        let mut plan = Vec::with_capacity(self.instructions.len());
+        for stage in self.stages() {
+            let start = self.stages[stage.index()].start;
+            let end = start + 1 + stage.instructions.len();
+            for (index, instruction) in (start..end).zip(&self.instructions[start..end]) {
+                let scope = scopes.at(index);
+                let mut step = PlanStep {
+                    stage: stage.index(),
+                    instruction: index,
+                    kind: instruction.kind(),
+                    layer: matches!(
+                        instruction,
+                        Instruction::Run(..) | Instruction::Copy(..) | Instruction::Add(..)
+                    ),
+                    text: String::new(),
+                    context_files: vec![],
+                    from_stages: vec![],
+                    from_images: vec![],
+                    mounts: vec![],
+                    build_args: BTreeMap::new(),
+                };
+
+                // Expand the parts that BuildKit expands. Triggers of `ONBUILD`
+                // are expanded when they are executed in the child image.
+                let span = instruction.span();
+                let mut expanded = String::with_capacity(span.len());
+                let mut pos = span.start;
+                if !matches!(instruction, Instruction::Onbuild(..)) {
+                    for reference in references.iter().filter(|r| r.instruction == index) {
+                        consume_arg(&mut step.build_args, scope, reference.name, index == start);
+                        // Skip references nested in another reference.
+                        if reference.span.start < pos {
+                            continue;
+                        }
+                        expanded.push_str(&text[pos..reference.span.start]);
+                        expanded.push_str(
+                            &expander.expand(&text[reference.span.clone()], scope)?.value,
+                        );
+                        pos = reference.span.end;
+                    }
+                }
+                expanded.push_str(&text[pos..span.end]);
+                step.text = if has_here_docs(instruction) {
+                    expanded
+                } else {
+                    join_lines(&expanded, self.escape_byte)
+                };
+
+                while let Some(input) = context_inputs.next_if(|i| i.instruction == index) {
+                    if !step.context_files.contains(&input.source) {
+                        step.context_files.push(input.source);
+                    }
+                }
+                if index == start {
+                    // Unlike the stage graph, `FROM` is resolved with build args.
+                    let image = expander.expand(&stage.from.image.value, scope)?.value;
+                    match self.stage(&image).filter(|b| b.index() < stage.index()) {
+                        Some(base) => step.from_stages.push(base.index()),
+                        None if image == "scratch" => {}
+                        None => step.from_images.push(image),
+                    }
+                }
+                let edges = graph.edges().iter().filter(|e| ptr::eq(e.instruction, instruction));
+                for edge in edges.filter(|e| e.kind != EdgeKind::From) {
+                    match edge.from {
+                        Node::Stage(i) if !step.from_stages.contains(&i) => {
+                            step.from_stages.push(i);
+                        }
+                        Node::Image(i) => {
+                            let image = &graph.images()[i];
+                            if !step.from_images.contains(image) {
+                                step.from_images.push(image.clone());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                if let Instruction::Run(run) = instruction {
+                    for flag in run.options.iter().filter(|f| f.name.value == "mount") {
+                        if let Some(value) = &flag.value {
+                            step.mounts.push(expander.expand(&value.value, scope)?.value);
+                        }
+                    }
+                    for variable in scope {
+                        if variable.kind == VariableKind::StageArg {
+                            consume_arg(&mut step.build_args, scope, variable.name, false);
+                        }
+                    }
+                }
+                plan.push(step);
+            }
+        }
+        Ok(plan)
+    }
+}
+
+/// Records `name` as consumed if it is an `ARG` with value.
+///
+/// Predefined args are platform args in the global scope (i.e., `FROM`) and
+/// proxy args in stages. The latter are excluded from cache keys by BuildKit.
+fn consume_arg(
+    build_args: &mut BTreeMap<String, String>,
+    scope: &Scope<'_>,
+    name: &str,
+    from: bool,
+) {
+    let Some(variable) = scope.get(name) else { return };
+    let consumed = match variable.kind {
+        VariableKind::GlobalArg | VariableKind::StageArg => true,
+        VariableKind::Predefined => from,
+        _ => false,
+    };
+    if let (true, Some(value)) = (consumed, &variable.value) {
+        build_args.insert(String::from(name), value.clone());
+    }
+}
+
+fn has_here_docs(instruction: &Instruction<'_>) -> bool {
+    match instruction {
+        Instruction::Run(run) => !run.here_docs.is_empty(),
+        Instruction::Add(add) => add.src.iter().any(|s| matches!(s, Source::HereDoc(..))),
+        Instruction::Copy(copy) => copy.src.iter().any(|s| matches!(s, Source::HereDoc(..))),
+        _ => false,
+    }
+}
@@ -0,0 +1,380 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{string::String, vec::Vec};
+use core::fmt;
+
+use crate::{
+    Command, Dockerfile, Flag, HealthcheckArguments, HereDoc, Instruction, JsonOrStringArray,
+    ParserDirectives, Source, UnescapedString,
+};
+
+/// Style of the text rendered by [`Dockerfile::to_string_with`].
+///
+/// Regardless of the style, keywords are printed in upper case, comments and
+/// empty lines are not preserved, and shell-form commands are printed as
+/// written, including line continuations and comments in them.
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct PrintStyle {
+    blank_line_between_stages: bool,
+}
+impl PrintStyle {
+    /// Creates a new `PrintStyle` with the default style.
+    ///
+    /// This is equivalent to the style used by the [`Display`](fmt::Display)
+    /// implementation of [`Dockerfile`].
+    pub const fn new() -> Self {
+        Self { blank_line_between_stages: true }
+    }
+    /// Sets whether to insert a blank line before each `FROM` instruction
+    /// except for the first instruction.
+    ///
+    /// Default: `true`
+    pub const fn blank_line_between_stages(mut self, blank_line_between_stages: bool) -> Self {
+        self.blank_line_between_stages = blank_line_between_stages;
+        self
+    }
+}
+impl Default for PrintStyle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dockerfile<'_> {
+    /// Renders this dockerfile as text with the given style.
+    ///
+    /// Parsing the returned text produces a dockerfile with the same
+    /// instructions and parser directives as this dockerfile, except for
+    /// spans.
+    #[must_use]
+    pub fn to_string_with(&self, style: PrintStyle) -> String {
+        let mut out = String::new();
+        // Writing to String never fails.
+        let _ = print_dockerfile(&mut out, self, style);
+        out
+    }
+}
+impl fmt::Display for Dockerfile<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        print_dockerfile(f, self, PrintStyle::new())
+    }
+}
+/// Renders this instruction as dockerfile text, without a trailing newline.
+///
+/// Shell-form commands are printed as written, so if they contain line
+/// continuations, the result is valid only under the escape character used
+/// when parsing.
+impl fmt::Display for Instruction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        print_instruction(f, self)
+    }
+}
+
+fn print_dockerfile<W: fmt::Write>(
+    w: &mut W,
+    dockerfile: &Dockerfile<'_>,
+    style: PrintStyle,
+) -> fmt::Result {
+    let has_parser_directives = print_parser_directives(w, &dockerfile.parser_directives)?;
+    for (i, instruction) in dockerfile.instructions.iter().enumerate() {
+        if i == 0 {
+            if has_parser_directives {
+                w.write_char('\n')?;
+            }
+        } else if style.blank_line_between_stages && matches!(instruction, Instruction::From(..)) {
+            w.write_char('\n')?;
+        }
+        print_instruction(w, instruction)?;
+        w.write_char('\n')?;
+    }
+    Ok(())
+}
+
+fn print_parser_directives<W: fmt::Write>(
+    w: &mut W,
+    parser_directives: &ParserDirectives<'_>,
+) -> Result<bool, fmt::Error> {
+    enum Directive<'a> {
+        Syntax(&'a str),
+        Escape(char),
+        Check(&'a str),
+    }
+    let mut directives = Vec::with_capacity(3);
+    if let Some(syntax) = &parser_directives.syntax {
+        directives.push((syntax.start, Directive::Syntax(syntax.value.value)));
+    }
+    if let Some(escape) = &parser_directives.escape {
+        directives.push((escape.start, Directive::Escape(escape.value.value)));
+    }
+    if let Some(check) = &parser_directives.check {
+        directives.push((check.start, Directive::Check(check.value.value)));
+    }
+    // Keep the original order.
+    directives.sort_by_key(|&(start, _)| start);
+    for (_, directive) in &directives {
+        match directive {
+            Directive::Syntax(value) => writeln!(w, "# syntax={value}")?,
+            Directive::Escape(value) => writeln!(w, "# escape={value}")?,
+            Directive::Check(value) => writeln!(w, "# check={value}")?,
+        }
+    }
+    Ok(!directives.is_empty())
+}
+
+fn print_instruction<W: fmt::Write>(w: &mut W, instruction: &Instruction<'_>) -> fmt::Result {
+    match instruction {
+        Instruction::Add(instruction) => {
+            w.write_str("ADD")?;
+            print_options(w, &instruction.options)?;
+            print_sources(w, &instruction.src, &instruction.dest)
+        }
+        Instruction::Arg(instruction) => print_argument(w, "ARG", &instruction.arguments),
+        Instruction::Cmd(instruction) => {
+            w.write_str("CMD")?;
+            print_command(w, &instruction.arguments)
+        }
+        Instruction::Copy(instruction) => {
+            w.write_str("COPY")?;
+            print_options(w, &instruction.options)?;
+            print_sources(w, &instruction.src, &instruction.dest)
+        }
+        Instruction::Entrypoint(instruction) => {
+            w.write_str("ENTRYPOINT")?;
+            print_command(w, &instruction.arguments)
+        }
+        Instruction::Env(instruction) => print_argument(w, "ENV", &instruction.arguments),
+        Instruction::Expose(instruction) => {
+            w.write_str("EXPOSE")?;
+            for argument in &instruction.arguments {
+                write!(w, " {}", argument.value)?;
+            }
+            Ok(())
+        }
+        Instruction::From(instruction) => {
+            w.write_str("FROM")?;
+            print_options(w, &instruction.options)?;
+            write!(w, " {}", instruction.image.value)?;
+            if let Some((_as, name)) = &instruction.as_ {
+                write!(w, " AS {}", name.value)?;
+            }
+            Ok(())
+        }
+        Instruction::Healthcheck(instruction) => {
+            w.write_str("HEALTHCHECK")?;
+            print_options(w, &instruction.options)?;
+            match &instruction.arguments {
+                HealthcheckArguments::Cmd { arguments, .. } => {
+                    w.write_str(" CMD")?;
+                    print_command(w, arguments)
+                }
+                HealthcheckArguments::None { .. } => w.write_str(" NONE"),
+            }
+        }
+        Instruction::Label(instruction) => print_argument(w, "LABEL", &instruction.arguments),
+        Instruction::Maintainer(instruction) => print_argument(w, "MAINTAINER", &instruction.name),
+        Instruction::Onbuild(instruction) => {
+            w.write_str("ONBUILD ")?;
+            print_instruction(w, &instruction.instruction)
+        }
+        Instruction::Run(instruction) => {
+            w.write_str("RUN")?;
+            print_options(w, &instruction.options)?;
+            match (instruction.here_docs.first(), &instruction.arguments) {
+                // The arguments start right after the delimiter of the here-document.
+                (Some(here_doc), Command::Shell(s)) => {
+                    print_here_doc_delim(w, here_doc)?;
+                    w.write_str(s.value)?;
+                }
+                _ => print_command(w, &instruction.arguments)?,
+            }
+            print_here_doc_bodies(w, &instruction.here_docs)
+        }
+        Instruction::Shell(instruction) => {
+            w.write_str("SHELL ")?;
+            print_json_array(w, instruction.arguments.iter().map(|a| &*a.value))
+        }
+        Instruction::Stopsignal(instruction) => {
+            print_argument(w, "STOPSIGNAL", &instruction.arguments)
+        }
+        Instruction::User(instruction) => print_argument(w, "USER", &instruction.arguments),
+        Instruction::Volume(instruction) => {
+            w.write_str("VOLUME")?;
+            match &instruction.arguments {
+                JsonOrStringArray::String(arguments)
+                    if !needs_json_form(arguments.iter().map(|a| &*a.value)) =>
+                {
+                    for argument in arguments {
+                        write!(w, " {}", argument.value)?;
+                    }
+                    Ok(())
+                }
+                JsonOrStringArray::String(arguments) => {
+                    w.write_char(' ')?;
+                    print_json_array(w, arguments.iter().map(|a| &*a.value))
+                }
+                JsonOrStringArray::Json(arguments) => {
+                    w.write_char(' ')?;
+                    print_json_array(w, arguments.value.iter().map(|a| &*a.value))
+                }
+            }
+        }
+        Instruction::Workdir(instruction) => print_argument(w, "WORKDIR", &instruction.arguments),
+        Instruction::Unknown(instruction) => {
+            w.write_str(&instruction.name)?;
+            if !instruction.arguments.value.is_empty() {
+                write!(w, " {}", instruction.arguments.value)?;
+            }
+            print_here_doc_bodies(w, &instruction.here_docs)
+        }
+    }
+}
+
+fn print_argument<W: fmt::Write>(
+    w: &mut W,
+    keyword: &str,
+    argument: &UnescapedString<'_>,
+) -> fmt::Result {
+    write!(w, "{keyword} {}", argument.value)
+}
+
+fn print_options<W: fmt::Write>(w: &mut W, options: &[Flag<'_>]) -> fmt::Result {
+    for flag in options {
+        write!(w, " --{}", flag.name.value)?;
+        if let Some(value) = &flag.value {
+            write!(w, "={}", value.value)?;
+        }
+    }
+    Ok(())
+}
+
+fn print_command<W: fmt::Write>(w: &mut W, command: &Command<'_>) -> fmt::Result {
+    match command {
+        Command::Exec(array) => {
+            w.write_char(' ')?;
+            print_json_array(w, array.value.iter().map(|a| &*a.value))
+        }
+        Command::Shell(s) => {
+            if !s.value.is_empty() {
+                write!(w, " {}", s.value)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn print_sources<W: fmt::Write>(
+    w: &mut W,
+    src: &[Source<'_>],
+    dest: &UnescapedString<'_>,
+) -> fmt::Result {
+    let paths = src.iter().map(|src| match src {
+        Source::Path(path) => Some(&*path.value),
+        Source::HereDoc(..) => None,
+    });
+    if paths.clone().all(|path| path.is_some())
+        && needs_json_form(paths.clone().flatten().chain([&*dest.value]))
+    {
+        w.write_char(' ')?;
+        return print_json_array(w, paths.flatten().chain([&*dest.value]));
+    }
+    let mut here_docs = Vec::new();
+    for src in src {
+        match src {
+            Source::Path(path) => write!(w, " {}", path.value)?,
+            Source::HereDoc(here_doc) => {
+                print_here_doc_delim(w, here_doc)?;
+                here_docs.push(here_doc);
+            }
+        }
+    }
+    write!(w, " {}", dest.value)?;
+    print_here_doc_bodies(w, here_docs)
+}
+
+fn print_here_doc_delim<W: fmt::Write>(w: &mut W, here_doc: &HereDoc<'_>) -> fmt::Result {
+    fn needs_escape(ch: char) -> bool {
+        matches!(ch, '\'' | '"' | '\\' | ' ' | '\t')
+    }
+    let delim = &*here_doc.delim;
+    if !delim.contains(needs_escape) {
+        return if here_doc.expand { write!(w, " <<{delim}") } else { write!(w, " <<'{delim}'") };
+    }
+    w.write_str(" <<")?;
+    if !here_doc.expand {
+        // Any quote disables expansion: <<''EOF is the same as <<'EOF'.
+        w.write_str("''")?;
+    }
+    for ch in delim.chars() {
+        if needs_escape(ch) {
+            w.write_char('\\')?;
+        }
+        w.write_char(ch)?;
+    }
+    Ok(())
+}
+
+fn print_here_doc_bodies<'a, 'b: 'a, W: fmt::Write, I: IntoIterator<Item = &'a HereDoc<'b>>>(
+    w: &mut W,
+    here_docs: I,
+) -> fmt::Result {
+    for here_doc in here_docs {
+        w.write_char('\n')?;
+        w.write_str(&here_doc.value)?;
+        if !here_doc.value.is_empty() && !here_doc.value.ends_with('\n') {
+            w.write_char('\n')?;
+        }
+        w.write_str(&here_doc.delim)?;
+    }
+    Ok(())
+}
+
+/// Returns `true` if the given space-separated arguments cannot be represented
+/// without JSON array.
+pub(crate) fn needs_json_form<'a, I: Iterator<Item = &'a str>>(mut arguments: I) -> bool {
+    let mut first = true;
+    arguments.any(|a| {
+        let is_first = first;
+        first = false;
+        a.is_empty()
+            || a.bytes().any(|b| matches!(b, b' ' | b'\t' | b'\r' | b'\n'))
+            || is_first && a.starts_with('[')
+    })
+}
+
+pub(crate) fn print_json_array<'a, W: fmt::Write, I: Iterator<Item = &'a str>>(
+    w: &mut W,
+    elements: I,
+) -> fmt::Result {
+    w.write_char('[')?;
+    for (i, element) in elements.enumerate() {
+        if i != 0 {
+            w.write_str(", ")?;
+        }
+        print_json_string(w, element)?;
+    }
+    w.write_char(']')
+}
+
+pub(crate) fn print_json_string<W: fmt::Write>(w: &mut W, s: &str) -> fmt::Result {
+    w.write_char('"')?;
+    print_json_string_contents(w, s)?;
+    w.write_char('"')
+}
+
+pub(crate) fn print_json_string_contents<W: fmt::Write>(w: &mut W, s: &str) -> fmt::Result {
+    for ch in s.chars() {
+        match ch {
+            '"' => w.write_str("\\\"")?,
+            '\\' => w.write_str("\\\\")?,
+            '\n' => w.write_str("\\n")?,
+            '\r' => w.write_str("\\r")?,
+            '\t' => w.write_str("\\t")?,
+            '\u{8}' => w.write_str("\\b")?,
+            '\u{c}' => w.write_str("\\f")?,
+            ch if ch < ' ' => write!(w, "\\u{:04x}", ch as u32)?,
+            ch => w.write_char(ch)?,
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,211 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt::Write as _;
+use std::collections::HashSet;
+
+use crate::{
+    Dockerfile, Instruction, Span, UTF8_BOM, error,
+    error::Result,
+    expand::{NO_BUILD_ARGS, references_in},
+    graph, words,
+};
+
+impl Dockerfile<'_> {
+    /// Returns a dockerfile that contains only the stages needed to build
+    /// the `target` stage.
+    ///
+    /// `text` must be the text from which this dockerfile was parsed.
+    ///
+    /// Stages that `target` depends on via `FROM <stage>`, `COPY --from`, and
+    /// `RUN --mount=from=` are followed recursively. Variables in stage
+    /// references are expanded with the default values of `ARG`s, in the same
+    /// way as [`Dockerfile::graph`].
+    ///
+    /// The result contains the original parser directives, the global `ARG`
+    /// instructions used by the kept stages, and the kept stages. The text of
+    /// each kept instruction (and comments inside kept stages) is preserved
+    /// verbatim, except that references to stages by index (e.g.,
+    /// `COPY --from=1`) are rewritten to the new indices.
+    ///
+    /// ```
+    /// let text = "ARG RUST=1\nARG NODE=20\nFROM rust:$RUST AS build\nRUN cargo build\n\
+    ///             FROM node:$NODE AS web\nFROM build AS test\nRUN cargo test\n";
+    /// let dockerfile = parse_dockerfile::parse(text).unwrap();
+    /// assert_eq!(
+    ///     dockerfile.prune(text, "test").unwrap(),
+    ///     "ARG RUST=1\n\nFROM rust:$RUST AS build\nRUN cargo build\n\nFROM build AS test\nRUN cargo test\n",
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no stage named `target`, expanding
+    /// variables failed, or a reference to a stage by index needs to be
+    /// rewritten but contains variables.
+    pub fn prune(&self, text: &str, target: &str) -> Result<String> {
+        let Some(target) = self.stage(target) else {
+            return Err(error::without_location(format!("stage {target:?} not found").into()));
+        };
+        let (bom, text) = if text.as_bytes().starts_with(UTF8_BOM) {
+            text.split_at(UTF8_BOM.len())
+        } else {
+            ("", text)
+        };
+        let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+
+        // Collect stages needed to build the target.
+        let graph = graph::collect(self, NO_BUILD_ARGS, false)?;
+        let mut keep = vec![true; self.stages.len()];
+        for i in graph.unreachable_stages(target.index()) {
+            keep[i] = false;
+        }
+        let index_references = self.index_references(&keep)?;
+
+        // Collect global args used by the kept stages.
+        let mut used_args = HashSet::<&str>::new();
+        let escape = self.escape_byte;
+        for stage in self.stages().filter(|s| keep[s.index()]) {
+            let from = stage.from;
+            used_args.extend(references_in(&from.image.value, escape).iter().map(|r| r.name));
+            for flag in &from.options {
+                if let Some(value) = &flag.value {
+                    used_args.extend(references_in(&value.value, escape).iter().map(|r| r.name));
+                }
+            }
+            // Global args are only visible in stages that redeclare them.
+            for instruction in stage.instructions {
+                if let Instruction::Arg(arg) = instruction {
+                    used_args.extend(
+                        words::key_values(&arg.arguments, escape, false).iter().map(|kv| kv.key),
+                    );
+                }
+            }
+        }
+        let global_args: Vec<_> = self.global_args().collect();
+        let mut keep_args = vec![false; global_args.len()];
+        // Default values of global args can refer to earlier global args.
+        for (i, arg) in global_args.iter().enumerate().rev() {
+            let definitions = words::key_values(&arg.arguments, escape, false);
+            if definitions.iter().any(|kv| used_args.contains(kv.key)) {
+                keep_args[i] = true;
+                for default in definitions.iter().filter_map(|kv| kv.value) {
+                    used_args.extend(references_in(default, escape).iter().map(|r| r.name));
+                }
+            }
+        }
+
+        let mut out = String::with_capacity(text.len());
+        out.push_str(bom);
+        let directives = &self.parser_directives;
+        let directives_end = [
+            directives.syntax.as_ref().map(|d| d.span().end),
+            directives.escape.as_ref().map(|d| d.span().end),
+            directives.check.as_ref().map(|d| d.span().end),
+        ]
+        .into_iter()
+        .flatten()
+        .max();
+        if let Some(end) = directives_end {
+            let end = text[end..].find('\n').map_or(text.len(), |i| end + i + 1);
+            out.push_str(&text[..end]);
+            if !out.ends_with('\n') {
+                out.push_str(newline);
+            }
+        }
+        let mut sections = usize::from(directives_end.is_some());
+        if keep_args.contains(&true) {
+            if sections != 0 {
+                out.push_str(newline);
+            }
+            sections += 1;
+            for (arg, _) in global_args.iter().zip(&keep_args).filter(|(_, &k)| k) {
+                out.push_str(&text[arg.arg.span.start..arg.arguments.span.end]);
+                out.push_str(newline);
+            }
+        }
+        for stage in self.stages().filter(|s| keep[s.index()]) {
+            if sections != 0 {
+                out.push_str(newline);
+            }
+            sections += 1;
+            let span = stage.span();
+            let mut pos = span.start;
+            for (value, new) in index_references.iter().filter(|(v, _)| span.contains(&v.start)) {
+                out.push_str(&text[pos..value.start]);
+                let _ = write!(out, "{new}");
+                pos = value.end;
+            }
+            out.push_str(&text[pos..span.end]);
+            out.push_str(newline);
+        }
+        Ok(out)
+    }
+
+    /// Returns the spans of references to stages by index in the kept
+    /// stages, and the new indices of the referenced stages.
+    ///
+    /// Removing stages renumbers the kept stages, so `COPY --from=<index>`
+    /// and `RUN --mount=from=<index>` need to be rewritten.
+    fn index_references(&self, keep: &[bool]) -> Result<Vec<(Span, usize)>> {
+        let mut new_indices = vec![None; keep.len()];
+        for (new, old) in (0..keep.len()).filter(|&i| keep[i]).enumerate() {
+            new_indices[old] = Some(new);
+        }
+        let scopes = self.scopes(NO_BUILD_ARGS)?;
+        let expander = self.expander().skip_unset(true);
+        let mut references = vec![];
+        for stage in self.stages().filter(|s| keep[s.index()]) {
+            let start = self.stages[stage.index()].start;
+            for (i, instruction) in stage.instructions.iter().enumerate() {
+                // The value and its span, or `None` if the value cannot be mapped to the text.
+                let values: Vec<(String, Option<Span>)> = match instruction {
+                    Instruction::Copy(copy) => copy
+                        .options
+                        .iter()
+                        .find(|f| f.name.value == "from")
+                        .and_then(|f| f.value.as_ref())
+                        .map(|v| {
+                            let mapped = v.span.len() == v.value.len();
+                            (String::from(&*v.value), mapped.then(|| v.span.clone()))
+                        })
+                        .into_iter()
+                        .collect(),
+                    Instruction::Run(run) => run
+                        .options
+                        .iter()
+                        .filter_map(words::mount_from)
+                        .map(|f| (f.value, f.span))
+                        .collect(),
+                    _ => continue,
+                };
+                for (value, span) in values {
+                    let from = expander.expand(&value, scopes.at(start + 1 + i))?.value;
+                    if !from.bytes().all(|b| b.is_ascii_digit()) {
+                        continue;
+                    }
+                    let Ok(old) = from.parse::<usize>() else { continue };
+                    let Some(&Some(new)) = new_indices.get(old) else { continue };
+                    if new == old {
+                        continue;
+                    }
+                    match span {
+                        Some(span) if references_in(&value, self.escape_byte).is_empty() => {
+                            references.push((span, new));
+                        }
+                        _ => {
+                            return Err(error::without_location(
+                                format!(
+                                    "cannot rewrite reference to stage by index {from} in stage {}",
+                                    graph::stage_name(self, stage.index()),
+                                )
+                                .into(),
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(references)
+    }
+}
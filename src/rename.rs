@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{format, vec::Vec};
+
+use crate::{
+    Dockerfile, Flag, Instruction, Span, StageBase, TextEdit, UTF8_BOM, UnescapedString, error,
+    error::Result, to_lowercase, words,
+};
+
+/// The result of [`Dockerfile::rename_stage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StageRename {
+    /// Edits that rename the stage and all references to it.
+    ///
+    /// Apply them with [`apply_edits`](crate::apply_edits).
+    pub edits: Vec<TextEdit>,
+    /// Spans of stage references that contain variables, and therefore may
+    /// refer to the renamed stage after expansion.
+    ///
+    /// These references are not renamed.
+    pub unresolved: Vec<Span>,
+}
+
+impl Dockerfile<'_> {
+    /// Returns edits that rename the stage named `old` to `new`, including all
+    /// references to it.
+    ///
+    /// `text` must be the text from which this dockerfile was parsed.
+    ///
+    /// Like BuildKit, stage names are matched case-insensitively. The following
+    /// references are renamed:
+    ///
+    /// - `FROM <stage>` in later stages
+    /// - `COPY --from=<stage>` (including in `ONBUILD`)
+    /// - `RUN --mount=from=<stage>` (including in `ONBUILD`)
+    /// - The stage description comment, i.e., the comment line that directly
+    ///   precedes the `FROM` instruction and starts with `<stage> `, where
+    ///   `<stage>` is the lowercased stage name (as in BuildKit)
+    ///
+    /// ```
+    /// let text = "FROM rust AS build\nRUN cargo build\nFROM scratch\nCOPY --from=build /app /app\n";
+    /// let dockerfile = parse_dockerfile::parse(text).unwrap();
+    /// let rename = dockerfile.rename_stage(text, "build", "builder").unwrap();
+    /// let new = parse_dockerfile::apply_edits(text, &rename.edits).unwrap();
+    /// assert_eq!(
+    ///     new,
+    ///     "FROM rust AS builder\nRUN cargo build\nFROM scratch\nCOPY --from=builder /app /app\n"
+    /// );
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no stage named `old`, if `new` is not a
+    /// valid stage name, or if `new` is a reserved name (`scratch` or
+    /// `context`) or the name of another stage.
+    pub fn rename_stage(&self, text: &str, old: &str, new: &str) -> Result<StageRename> {
+        let Some(stage) = self.stage(old) else {
+            return Err(error::without_location(format!("stage {old:?} not found").into()));
+        };
+        if !is_valid_stage_name(new) {
+            return Err(error::without_location(format!("invalid stage name {new:?}").into()));
+        }
+        let new_lower = to_lowercase(new);
+        if matches!(&*new_lower, "scratch" | "context") {
+            return Err(error::without_location(format!("stage name {new:?} is reserved").into()));
+        }
+        if self.stage(new).is_some_and(|s| s.index() != stage.index()) {
+            return Err(error::without_location(
+                format!("stage named {new:?} already exists").into(),
+            ));
+        }
+        let text =
+            if text.as_bytes().starts_with(UTF8_BOM) { &text[UTF8_BOM.len()..] } else { text };
+        let Some(name) = stage.name() else { unreachable!() };
+        let old_lower = to_lowercase(&name.value);
+        let mut r = Renamer {
+            old: &old_lower,
+            new,
+            rename: StageRename { edits: Vec::new(), unresolved: Vec::new() },
+        };
+
+        if let Some(edit) = description_comment(text, stage.from.from.span.start, &old_lower, new) {
+            r.rename.edits.push(edit);
+        }
+        r.rename.edits.push(name.replace_with(new));
+        for s in self.stages() {
+            if s.index() > stage.index() {
+                match s.base() {
+                    StageBase::Stage(base) if base.index() == stage.index() => {
+                        r.rename.edits.push(s.from.image.replace_with(new));
+                    }
+                    StageBase::Image(image) if image.value.contains('$') => {
+                        r.rename.unresolved.push(image.span.clone());
+                    }
+                    _ => {}
+                }
+            }
+            for instruction in s.instructions {
+                r.instruction(instruction);
+            }
+        }
+        Ok(r.rename)
+    }
+}
+
+struct Renamer<'r> {
+    old: &'r str,
+    new: &'r str,
+    rename: StageRename,
+}
+
+impl Renamer<'_> {
+    fn instruction(&mut self, instruction: &Instruction<'_>) {
+        match instruction {
+            Instruction::Copy(copy) => {
+                for flag in &copy.options {
+                    if flag.name.value == "from" {
+                        if let Some(value) = &flag.value {
+                            self.reference(value);
+                        }
+                    }
+                }
+            }
+            Instruction::Run(run) => {
+                for flag in &run.options {
+                    self.mount(flag);
+                }
+            }
+            Instruction::Onbuild(onbuild) => self.instruction(&onbuild.instruction),
+            _ => {}
+        }
+    }
+    fn reference(&mut self, value: &UnescapedString<'_>) {
+        if to_lowercase(&value.value) == self.old {
+            self.rename.edits.push(value.replace_with(self.new));
+        } else if value.value.contains('$') {
+            self.rename.unresolved.push(value.span.clone());
+        }
+    }
+    /// Handles `from=<stage>` field in `--mount` flag.
+    fn mount(&mut self, flag: &Flag<'_>) {
+        let Some(from) = words::mount_from(flag) else { return };
+        let matched = to_lowercase(&from.value) == self.old;
+        match from.span {
+            Some(span) if matched => self.rename.edits.push(TextEdit::replace(span, self.new)),
+            _ if matched || from.value.contains('$') => {
+                self.rename.unresolved.push(flag.value.as_ref().unwrap().span.clone());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the edit that renames the stage description comment.
+///
+/// See <https://github.com/moby/buildkit/blob/v0.30/frontend/dockerfile/instructions/parse.go>
+/// for how BuildKit gets the stage description.
+fn description_comment(text: &str, from_start: usize, name: &str, new: &str) -> Option<TextEdit> {
+    let mut line_end = text[..from_start].rfind('\n')?;
+    loop {
+        let line_start = text[..line_end].rfind('\n').map_or(0, |i| i + 1);
+        let line = text[line_start..line_end].trim_end_matches('\r');
+        let trimmed = line.trim_start_matches([' ', '\t']);
+        let comment = trimmed.strip_prefix('#')?;
+        let leading = comment.len() - comment.trim_start_matches([' ', '\t']).len();
+        let comment = &comment[leading..];
+        if comment.strip_prefix(name).is_some_and(|rest| rest.starts_with(' ')) {
+            let start = line_start + (line.len() - trimmed.len()) + 1 + leading;
+            return Some(TextEdit::replace(start..start + name.len(), new));
+        }
+        if line_start == 0 {
+            return None;
+        }
+        line_end = line_start - 1;
+    }
+}
+
+/// See <https://github.com/moby/buildkit/blob/v0.30/frontend/dockerfile/instructions/parse.go>.
+fn is_valid_stage_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
@@ -0,0 +1,381 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{
+    format,
+    string::{String, ToString as _},
+    vec,
+    vec::Vec,
+};
+use core::{fmt, str::FromStr};
+
+use crate::{
+    Dockerfile, Span, UnescapedString, error,
+    error::{Error, Result},
+    expand,
+    graph::{self, EdgeKind, Node},
+    scope::Scope,
+};
+
+/// A platform such as `linux/arm64/v8`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct Platform {
+    /// The operating system, such as `linux`.
+    pub os: String,
+    /// The CPU architecture, such as `amd64`.
+    pub architecture: String,
+    /// The variant of the CPU, such as `v7`.
+    pub variant: Option<String>,
+}
+
+impl Platform {
+    /// Creates a new `Platform`.
+    ///
+    /// Unlike parsing from a string, this doesn't normalize the values.
+    #[must_use]
+    pub fn new(os: &str, architecture: &str, variant: Option<&str>) -> Self {
+        Self { os: os.into(), architecture: architecture.into(), variant: variant.map(Into::into) }
+    }
+}
+
+/// Parses `<os>/<arch>[/<variant>]`, normalizing the values like BuildKit
+/// (e.g., `linux/aarch64` and `linux/arm64/v8` are parsed as `linux/arm64`).
+impl FromStr for Platform {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || error::without_location(format!("invalid platform {s:?}").into());
+        let lower = s.to_ascii_lowercase();
+        let mut parts = lower.split('/');
+        let (Some(os), Some(architecture), variant, None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        if os.is_empty() || architecture.is_empty() || variant == Some("") {
+            return Err(invalid());
+        }
+        let os = if os == "macos" { "darwin" } else { os };
+        let (architecture, variant) = match (architecture, variant) {
+            ("i386", v) => ("386", v),
+            ("x86_64" | "x86-64" | "amd64", Some("v1") | None) => ("amd64", None),
+            ("x86_64" | "x86-64", v) => ("amd64", v),
+            ("aarch64" | "arm64", Some("8" | "v8") | None) => ("arm64", None),
+            ("aarch64", v) => ("arm64", v),
+            ("arm" | "armhf", None) => ("arm", Some("v7")),
+            ("armel", None) => ("arm", Some("v6")),
+            ("arm", Some(v @ ("5" | "6" | "7" | "8"))) => {
+                return Ok(Self::new(os, "arm", Some(&format!("v{v}"))));
+            }
+            (a, v) => (a, v),
+        };
+        Ok(Self::new(os, architecture, variant))
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.os, self.architecture)?;
+        if let Some(variant) = &self.variant {
+            write!(f, "/{variant}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Options for [`Dockerfile::resolve_images`].
+#[derive(Debug, Clone, Default)]
+#[must_use]
+pub struct ResolveOptions {
+    build_args: Vec<(String, String)>,
+    platform: Option<Platform>,
+    build_platform: Option<Platform>,
+    target: Option<String>,
+}
+
+impl ResolveOptions {
+    /// Creates a new `ResolveOptions` with no build args.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds a build arg, like `--build-arg <name>=<value>`.
+    pub fn build_arg(mut self, name: &str, value: &str) -> Self {
+        self.build_args.push((name.into(), value.into()));
+        self
+    }
+    /// Sets the target platform, like `--platform <platform>`.
+    ///
+    /// Default: the build platform
+    pub fn platform(mut self, platform: Platform) -> Self {
+        self.platform = Some(platform);
+        self
+    }
+    /// Sets the platform of the build machine.
+    ///
+    /// Default: `linux/amd64`
+    pub fn build_platform(mut self, platform: Platform) -> Self {
+        self.build_platform = Some(platform);
+        self
+    }
+    /// Sets the target stage, like `--target <name>`.
+    ///
+    /// Default: the last stage
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.into());
+        self
+    }
+}
+
+/// The result of [`Dockerfile::resolve_images`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Resolution {
+    /// The stages that the target depends on (including the target itself),
+    /// in the order in the dockerfile.
+    pub stages: Vec<ResolvedStage>,
+    /// Problems found while resolving the stages, in the order in the
+    /// dockerfile.
+    pub problems: Vec<ResolveProblem>,
+}
+
+/// A problem found by [`Dockerfile::resolve_images`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResolveProblem {
+    /// The index of the stage.
+    pub stage: usize,
+    /// The kind of the problem.
+    pub kind: ResolveProblemKind,
+    /// The span of the problem.
+    ///
+    /// This is the span of the variable reference for
+    /// [`ResolveProblemKind::UnsetVariable`] (or the span of the whole value
+    /// if the value contains line continuations), and the span of the value
+    /// otherwise.
+    pub span: Span,
+}
+
+/// The kind of [`ResolveProblem`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResolveProblemKind {
+    /// `FROM` refers to a variable that is not set (e.g., not declared as a
+    /// global `ARG`, or declared without default value and not given as a
+    /// build arg), identified by its name.
+    ///
+    /// Like BuildKit, the variable is expanded to an empty string.
+    UnsetVariable(String),
+    /// The base name is empty after expansion.
+    ///
+    /// The base is resolved to [`ResolvedBase::Image`] with an empty name.
+    BlankBase,
+    /// The value of `--platform` flag is invalid.
+    ///
+    /// The platform is resolved as if the flag is not present.
+    InvalidPlatform,
+}
+
+/// A stage whose base image and platform are resolved.
+///
+/// See [`Dockerfile::resolve_images`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ResolvedStage {
+    /// The index of the stage.
+    pub index: usize,
+    /// The resolved base.
+    pub base: ResolvedBase,
+    /// The platform of the stage.
+    pub platform: Platform,
+}
+
+/// The resolved base of [`ResolvedStage`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ResolvedBase {
+    /// `FROM scratch`
+    Scratch,
+    /// Another stage, identified by its index.
+    Stage(usize),
+    /// An external image, with variables expanded.
+    Image(String),
+}
+
+impl Dockerfile<'_> {
+    /// Resolves the base images and platforms of the stages needed to build
+    /// the target.
+    ///
+    /// Like BuildKit, variables in `FROM` are expanded with the global `ARG`s,
+    /// whose default values are overridden by the build args, and the
+    /// automatic platform args (`TARGETPLATFORM`, `TARGETOS`, `TARGETARCH`,
+    /// `TARGETVARIANT`, `BUILDPLATFORM`, `BUILDOS`, `BUILDARCH`, and
+    /// `BUILDVARIANT`). The same args are used to expand stage references in
+    /// `COPY --from` and `RUN --mount=from`, so only stages that the target
+    /// depends on with these args are resolved.
+    ///
+    /// The platform of a stage is the value of `--platform` flag if present,
+    /// otherwise the platform of the base stage if based on another stage,
+    /// otherwise the target platform.
+    ///
+    /// Unset variables, blank base names, and invalid platforms don't stop
+    /// the resolution, and are reported in [`Resolution::problems`].
+    ///
+    /// ```
+    /// use parse_dockerfile::{ResolveOptions, ResolvedBase, parse};
+    ///
+    /// let text = "ARG RUST_VERSION=1.80
+    /// FROM --platform=$BUILDPLATFORM rust:$RUST_VERSION AS build
+    /// FROM alpine:3 AS test
+    /// FROM gcr.io/distroless/cc
+    /// COPY --from=build /app /app
+    /// ";
+    /// let dockerfile = parse(text).unwrap();
+    /// let options = ResolveOptions::new()
+    ///     .build_arg("RUST_VERSION", "1.81")
+    ///     .platform("linux/arm64/v8".parse().unwrap());
+    /// let resolution = dockerfile.resolve_images(&options).unwrap();
+    /// assert!(resolution.problems.is_empty());
+    /// let images: Vec<_> = resolution
+    ///     .stages
+    ///     .iter()
+    ///     .filter_map(|s| match &s.base {
+    ///         ResolvedBase::Image(image) => Some((&**image, s.platform.to_string())),
+    ///         _ => None,
+    ///     })
+    ///     .collect();
+    /// assert_eq!(images, [
+    ///     ("rust:1.81", "linux/amd64".to_owned()),
+    ///     ("gcr.io/distroless/cc", "linux/arm64".to_owned()),
+    /// ]);
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    ///
+    /// - the target stage is not found
+    /// - expanding variables fails (e.g., `${name:?message}` with unset
+    ///   `name`)
+    pub fn resolve_images(&self, options: &ResolveOptions) -> Result<Resolution> {
+        let build_platform =
+            options.build_platform.clone().unwrap_or_else(|| Platform::new("linux", "amd64", None));
+        let platform = options.platform.as_ref().unwrap_or(&build_platform);
+        let target = match &options.target {
+            Some(name) => match self.stage(name) {
+                Some(stage) => stage.index(),
+                None => {
+                    return Err(error::without_location(
+                        format!("stage {name:?} not found").into(),
+                    ));
+                }
+            },
+            None => match self.stages.len().checked_sub(1) {
+                Some(target) => target,
+                None => return Ok(Resolution { stages: vec![], problems: vec![] }),
+            },
+        };
+
+        // Automatic platform args override build args.
+        let mut args = options.build_args.clone();
+        for (prefix, p) in [("TARGET", platform), ("BUILD", &build_platform)] {
+            args.push((format!("{prefix}PLATFORM"), p.to_string()));
+            args.push((format!("{prefix}OS"), p.os.clone()));
+            args.push((format!("{prefix}ARCH"), p.architecture.clone()));
+            args.push((format!("{prefix}VARIANT"), p.variant.clone().unwrap_or_default()));
+        }
+        let scopes = self.scopes(&args)?;
+        let graph = graph::collect(self, &args, false)?;
+        let mut problems = vec![];
+
+        // Collect stages needed to build the target, resolving their bases.
+        let mut bases: Vec<Option<ResolvedBase>> = vec![None; self.stages.len()];
+        let mut stack = vec![target];
+        while let Some(index) = stack.pop() {
+            if bases[index].is_some() {
+                continue;
+            }
+            let stage = self.stage_at(index);
+            // Scope of the FROM instruction.
+            let scope = scopes.at(self.stages[index].start);
+            let image = self.expand_from(index, &stage.from.image, scope, &mut problems)?;
+            let base = if image.is_empty() {
+                problems.push(ResolveProblem {
+                    stage: index,
+                    kind: ResolveProblemKind::BlankBase,
+                    span: stage.from.image.span.clone(),
+                });
+                ResolvedBase::Image(image)
+            } else if image == "scratch" {
+                ResolvedBase::Scratch
+            } else {
+                match self.stage(&image) {
+                    Some(base) if base.index() < index => {
+                        stack.push(base.index());
+                        ResolvedBase::Stage(base.index())
+                    }
+                    _ => ResolvedBase::Image(image),
+                }
+            };
+            bases[index] = Some(base);
+            for edge in graph.dependencies(index) {
+                if let (Node::Stage(dep), EdgeKind::Copy | EdgeKind::Mount) = (edge.from, edge.kind)
+                {
+                    stack.push(dep);
+                }
+            }
+        }
+
+        // Resolve platforms in order, since base stages precede stages based on them.
+        let mut stages: Vec<ResolvedStage> = vec![];
+        let mut platforms: Vec<Option<Platform>> = vec![None; bases.len()];
+        for (index, base) in bases.into_iter().enumerate() {
+            let Some(base) = base else { continue };
+            let stage = self.stage_at(index);
+            let scope = scopes.at(self.stages[index].start);
+            let mut resolved = match base {
+                ResolvedBase::Stage(base) => platforms[base].clone(),
+                _ => None,
+            };
+            if let Some(flag) = stage.platform() {
+                match self.expand_from(index, flag, scope, &mut problems)?.parse() {
+                    Ok(platform) => resolved = Some(platform),
+                    Err(_) => problems.push(ResolveProblem {
+                        stage: index,
+                        kind: ResolveProblemKind::InvalidPlatform,
+                        span: flag.span.clone(),
+                    }),
+                }
+            }
+            let platform = resolved.unwrap_or_else(|| platform.clone());
+            platforms[index] = Some(platform.clone());
+            stages.push(ResolvedStage { index, base, platform });
+        }
+        problems.sort_by_key(|p| p.span.start);
+        Ok(Resolution { stages, problems })
+    }
+
+    /// Expands `value` in `FROM` of the stage at `index`, and records
+    /// references to variables that are not set.
+    fn expand_from(
+        &self,
+        index: usize,
+        value: &UnescapedString<'_>,
+        scope: &Scope<'_>,
+        problems: &mut Vec<ResolveProblem>,
+    ) -> Result<String> {
+        for reference in expand::unset_references(&value.value, self.escape_byte, scope) {
+            // Offsets in the value match offsets in the source only if the
+            // value has no line continuations.
+            let span = if value.span.len() == value.value.len() {
+                value.span.start + reference.span.start..value.span.start + reference.span.end
+            } else {
+                value.span.clone()
+            };
+            problems.push(ResolveProblem {
+                stage: index,
+                kind: ResolveProblemKind::UnsetVariable(reference.name.into()),
+                span,
+            });
+        }
+        Ok(self.expander().expand(&value.value, scope)?.value)
+    }
+}
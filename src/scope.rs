@@ -0,0 +1,320 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{string::String, vec, vec::Vec};
+use core::slice;
+
+use crate::{
+    Dockerfile, Instruction, Span,
+    error::Result,
+    expand::{Env, Expander},
+    words,
+    words::KeyValue,
+};
+
+/// Platform args that BuildKit defines automatically in the global scope.
+///
+/// Stages need to declare them with `ARG` to use them.
+pub(crate) const PLATFORM_ARGS: &[&str] = &[
+    "TARGETPLATFORM",
+    "TARGETOS",
+    "TARGETARCH",
+    "TARGETVARIANT",
+    "BUILDPLATFORM",
+    "BUILDOS",
+    "BUILDARCH",
+    "BUILDVARIANT",
+];
+
+/// Proxy args that BuildKit defines automatically in every stage.
+pub(crate) const PROXY_ARGS: &[&str] = &[
+    "HTTP_PROXY",
+    "http_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "FTP_PROXY",
+    "ftp_proxy",
+    "NO_PROXY",
+    "no_proxy",
+    "ALL_PROXY",
+    "all_proxy",
+];
+
+/// Variables visible at each instruction of a dockerfile.
+///
+/// See [`Dockerfile::scopes`].
+#[derive(Debug, Clone)]
+pub struct Scopes<'b> {
+    instructions: Vec<Scope<'b>>,
+    stage_ends: Vec<Scope<'b>>,
+}
+
+impl<'b> Scopes<'b> {
+    /// Returns the variables visible at the instruction at `index` in
+    /// [`Dockerfile::instructions`], i.e., before the instruction is executed.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&Scope<'b>> {
+        self.instructions.get(index)
+    }
+    /// Returns the variables visible at the end of the stage at `index`.
+    ///
+    /// `ENV` variables in this scope are inherited by stages that use this
+    /// stage as their base, and are set in the image config of this stage.
+    #[must_use]
+    pub fn stage_end(&self, index: usize) -> Option<&Scope<'b>> {
+        self.stage_ends.get(index)
+    }
+
+    // Dockerfile::scopes records a scope for every instruction (including
+    // instructions outside stages when parsed with ParseOptions::allow_no_stages)
+    // and every stage, so these never panic for indices from the same dockerfile.
+    pub(crate) fn at(&self, index: usize) -> &Scope<'b> {
+        &self.instructions[index]
+    }
+    pub(crate) fn end_at(&self, index: usize) -> &Scope<'b> {
+        &self.stage_ends[index]
+    }
+}
+
+/// Variables visible at an instruction.
+///
+/// This implements [`Env`], so can be used to expand variables with
+/// [`Expander`].
+#[derive(Debug, Clone, Default)]
+pub struct Scope<'b> {
+    variables: Vec<Variable<'b>>,
+}
+
+impl<'b> Scope<'b> {
+    /// Returns the variable named `name`.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&Variable<'b>> {
+        self.variables.iter().find(|v| v.name == name)
+    }
+    /// Returns an iterator over variables, in the order of definition.
+    pub fn iter(&self) -> slice::Iter<'_, Variable<'b>> {
+        self.variables.iter()
+    }
+
+    fn define(&mut self, variable: Variable<'b>) {
+        match self.variables.iter_mut().find(|v| v.name == variable.name) {
+            // `ENV` always overrides `ARG` of the same name.
+            Some(v) if variable.kind.is_arg() && v.kind.is_env() => {}
+            Some(v) => *v = variable,
+            None => self.variables.push(variable),
+        }
+    }
+}
+
+impl Env for Scope<'_> {
+    fn get(&self, name: &str) -> Option<&str> {
+        Scope::get(self, name)?.value.as_deref()
+    }
+}
+
+impl<'s, 'b> IntoIterator for &'s Scope<'b> {
+    type Item = &'s Variable<'b>;
+    type IntoIter = slice::Iter<'s, Variable<'b>>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A variable visible at an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Variable<'b> {
+    /// The name of the variable.
+    pub name: &'b str,
+    /// The kind of the variable.
+    pub kind: VariableKind,
+    /// The span of the definition (e.g., `NAME=value` of `ARG` or `ENV`), or
+    /// `None` for predefined variables.
+    pub span: Option<Span>,
+    /// The expanded value, or `None` if the variable is declared without value.
+    pub value: Option<String>,
+}
+
+/// The kind of [`Variable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum VariableKind {
+    /// `ARG` before the first `FROM`, which is visible only in `FROM` and
+    /// global `ARG`s.
+    GlobalArg,
+    /// `ARG` in the current stage.
+    StageArg,
+    /// `ENV` in the current stage.
+    Env,
+    /// `ENV` inherited from the base stage.
+    Inherited,
+    /// A variable that BuildKit defines automatically: platform args (e.g.,
+    /// `TARGETPLATFORM`) in the global scope and proxy args (e.g., `HTTP_PROXY`)
+    /// in stages.
+    Predefined,
+}
+
+impl VariableKind {
+    fn is_arg(self) -> bool {
+        matches!(self, Self::GlobalArg | Self::StageArg | Self::Predefined)
+    }
+    fn is_env(self) -> bool {
+        matches!(self, Self::Env | Self::Inherited)
+    }
+}
+
+impl Dockerfile<'_> {
+    /// Returns the variables visible at each instruction.
+    ///
+    /// `build_args` are values passed with `--build-arg`. They override the
+    /// default values of `ARG`s and set the values of predefined args.
+    ///
+    /// The scoping rules are the same as BuildKit:
+    ///
+    /// - Global `ARG`s are visible only in `FROM` and later global `ARG`s.
+    ///   A stage needs to redeclare them with `ARG <name>` to use them, and
+    ///   then their values are inherited.
+    /// - `ENV` variables are inherited by stages that use the stage as their
+    ///   base, and override `ARG`s of the same name.
+    /// - Platform args are predefined in the global scope, and proxy args are
+    ///   predefined in stages.
+    ///
+    /// Values of `ARG` and `ENV` are expanded with the variables visible at
+    /// the instruction, like BuildKit.
+    ///
+    /// ```
+    /// use parse_dockerfile::{VariableKind, parse};
+    ///
+    /// let text = "ARG VERSION=1.0
+    /// FROM alpine AS base
+    /// ENV APP_HOME=/app
+    ///
+    /// FROM base
+    /// ARG VERSION
+    /// RUN echo $VERSION $APP_HOME
+    /// ";
+    /// let dockerfile = parse(text).unwrap();
+    /// let scopes = dockerfile.scopes(&[("VERSION", "2.0")]).unwrap();
+    ///
+    /// // Scope of the last `RUN`.
+    /// let scope = scopes.get(dockerfile.instructions.len() - 1).unwrap();
+    /// let version = scope.get("VERSION").unwrap();
+    /// assert_eq!(version.kind, VariableKind::StageArg);
+    /// assert_eq!(version.value.as_deref(), Some("2.0"));
+    /// let app_home = scope.get("APP_HOME").unwrap();
+    /// assert_eq!(app_home.kind, VariableKind::Inherited);
+    /// assert_eq!(&text[app_home.span.clone().unwrap()], "APP_HOME=/app");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if expanding a value or a `FROM` image failed.
+    pub fn scopes<E: ?Sized + Env>(&self, build_args: &E) -> Result<Scopes<'_>> {
+        let expander = self.expander();
+        let predefined = |name: &'static str| Variable {
+            name,
+            kind: VariableKind::Predefined,
+            span: None,
+            value: build_args.get(name).map(String::from),
+        };
+        let mut scopes = Scopes {
+            instructions: Vec::with_capacity(self.instructions.len()),
+            stage_ends: Vec::with_capacity(self.stages.len()),
+        };
+
+        let mut global = Scope::default();
+        global.variables.extend(PLATFORM_ARGS.iter().map(|&name| predefined(name)));
+        let first_from = self.stages.first().map_or(self.instructions.len(), |s| s.start);
+        for instruction in &self.instructions[..first_from] {
+            scopes.instructions.push(global.clone());
+            if let Instruction::Arg(arg) = instruction {
+                let definitions = words::key_values(&arg.arguments, self.escape_byte, false);
+                define_args(
+                    &mut global,
+                    definitions,
+                    VariableKind::GlobalArg,
+                    build_args,
+                    None,
+                    expander,
+                )?;
+            }
+        }
+
+        for stage in self.stages() {
+            scopes.instructions.push(global.clone());
+            let mut scope = Scope::default();
+            scope.variables.extend(PROXY_ARGS.iter().map(|&name| predefined(name)));
+            let image = expander.expand(&stage.from.image.value, &global)?.value;
+            if let Some(base) = self.stage(&image).filter(|base| base.index() < stage.index()) {
+                for variable in &scopes.stage_ends[base.index()] {
+                    if variable.kind.is_env() {
+                        scope
+                            .define(Variable { kind: VariableKind::Inherited, ..variable.clone() });
+                    }
+                }
+            }
+            for instruction in stage.instructions {
+                scopes.instructions.push(scope.clone());
+                match instruction {
+                    Instruction::Arg(arg) => {
+                        let definitions =
+                            words::key_values(&arg.arguments, self.escape_byte, false);
+                        define_args(
+                            &mut scope,
+                            definitions,
+                            VariableKind::StageArg,
+                            build_args,
+                            Some(&global),
+                            expander,
+                        )?;
+                    }
+                    Instruction::Env(env) => {
+                        // All values are expanded with the variables before this instruction.
+                        let mut variables = vec![];
+                        for KeyValue { key, value, span } in
+                            words::key_values(&env.arguments, self.escape_byte, true)
+                        {
+                            let value = expander.expand(value.unwrap_or_default(), &scope)?.value;
+                            variables.push(Variable {
+                                name: key,
+                                kind: VariableKind::Env,
+                                span: Some(span),
+                                value: Some(value),
+                            });
+                        }
+                        for variable in variables {
+                            scope.define(variable);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            scopes.stage_ends.push(scope);
+        }
+        debug_assert_eq!(scopes.instructions.len(), self.instructions.len());
+        Ok(scopes)
+    }
+}
+
+fn define_args<'b, E: ?Sized + Env>(
+    scope: &mut Scope<'b>,
+    definitions: Vec<KeyValue<'b>>,
+    kind: VariableKind,
+    build_args: &E,
+    global: Option<&Scope<'b>>,
+    expander: Expander,
+) -> Result<()> {
+    let mut variables = Vec::with_capacity(definitions.len());
+    for KeyValue { key, value, span } in definitions {
+        let value = match (build_args.get(key), value) {
+            (Some(build_arg), _) => Some(String::from(build_arg)),
+            (None, Some(default)) => Some(expander.expand(default, &*scope)?.value),
+            // `ARG <name>` in a stage inherits the value of the global arg.
+            (None, None) => global.and_then(|g| Scope::get(g, key)?.value.clone()),
+        };
+        variables.push(Variable { name: key, kind, span: Some(span), value });
+    }
+    for variable in variables {
+        scope.define(variable);
+    }
+    Ok(())
+}
@@ -0,0 +1,573 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    Dockerfile, Flag, HereDoc, Instruction, ParseOptions, Result, Source, Span, UTF8_BOM,
+    parse_with,
+};
+
+/// The kind of a [syntax node](SyntaxNode) or [token](SyntaxToken).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum SyntaxKind {
+    // Nodes
+    /// The root node.
+    Root,
+    /// A parser directive. This node contains a [`Comment`](Self::Comment) token.
+    ParserDirective,
+    /// An instruction.
+    Instruction,
+
+    // Tokens
+    /// UTF-8 BOM.
+    Bom,
+    /// Spaces and tabs.
+    Whitespace,
+    /// `\n` or `\r\n`.
+    Newline,
+    /// A comment line, without the trailing newline.
+    Comment,
+    /// An escape character followed by optional spaces and a newline.
+    LineContinuation,
+    /// The keyword of an instruction.
+    Keyword,
+    /// Other text in an instruction, such as arguments and options.
+    Text,
+    /// The body of a here-document.
+    HereDocBody,
+    /// The closing delimiter of a here-document.
+    HereDocDelimiter,
+}
+
+/// A lossless syntax tree of a dockerfile.
+///
+/// Unlike the [AST](crate::Dockerfile), the syntax tree keeps every byte of
+/// the input, including the UTF-8 BOM, whitespace, comments, line
+/// continuations, and the spelling of keywords, so the text of the root node
+/// is always equal to the input.
+///
+/// ```
+/// use parse_dockerfile::{SyntaxKind, SyntaxTree};
+///
+/// let text = "FROM alpine\r\n# comment\r\nrun echo \\\r\n  hello\r\n";
+/// let tree = SyntaxTree::parse(text).unwrap();
+/// assert_eq!(tree.root().text(), text);
+///
+/// let run = tree.instructions().nth(1).unwrap();
+/// assert_eq!(run.syntax().text(), "run echo \\\r\n  hello");
+/// let kinds: Vec<_> = run.syntax().tokens().map(|t| t.kind()).collect();
+/// assert_eq!(kinds, [
+///     SyntaxKind::Keyword,
+///     SyntaxKind::Whitespace,
+///     SyntaxKind::Text,
+///     SyntaxKind::Whitespace,
+///     SyntaxKind::LineContinuation,
+///     SyntaxKind::Whitespace,
+///     SyntaxKind::Text,
+/// ]);
+/// ```
+#[derive(Debug)]
+pub struct SyntaxTree<'a> {
+    dockerfile: Dockerfile<'a>,
+    root: SyntaxNode<'a>,
+    escape_byte: u8,
+}
+impl<'a> SyntaxTree<'a> {
+    /// Parses dockerfile from the given `text`.
+    pub fn parse(text: &'a str) -> Result<Self> {
+        Self::parse_with(text, ParseOptions::new())
+    }
+    /// Parses dockerfile from the given `text` with the given `options`.
+    pub fn parse_with(text: &'a str, options: ParseOptions) -> Result<Self> {
+        let dockerfile = parse_with(text, options)?;
+        let builder = Builder::new(text, &dockerfile, options.escape_byte);
+        let root = builder.build(&dockerfile);
+        Ok(Self { dockerfile, root, escape_byte: builder.escape_byte })
+    }
+    /// Returns the AST of this dockerfile.
+    #[must_use]
+    pub fn dockerfile(&self) -> &Dockerfile<'a> {
+        &self.dockerfile
+    }
+    /// Returns the root node.
+    #[must_use]
+    pub fn root(&self) -> &SyntaxNode<'a> {
+        &self.root
+    }
+    /// Returns the length of the UTF-8 BOM at the start of the input, or `0`
+    /// if the input doesn't start with BOM.
+    ///
+    /// Ranges in the syntax tree are offsets in the input, while spans in the
+    /// AST are offsets in the input without BOM.
+    #[must_use]
+    pub fn bom_len(&self) -> usize {
+        match self.root.children.first() {
+            Some(SyntaxElement::Token(token)) if token.kind == SyntaxKind::Bom => token.text.len(),
+            _ => 0,
+        }
+    }
+    /// Returns the escape character used in this dockerfile.
+    #[must_use]
+    pub fn escape(&self) -> char {
+        self.escape_byte as char
+    }
+    /// Returns an iterator over top-level instructions and their syntax nodes.
+    pub fn instructions<'t>(&'t self) -> impl Iterator<Item = InstructionNode<'a, 't>> {
+        let nodes = self.root.children.iter().filter_map(|e| match e {
+            SyntaxElement::Node(node) if node.kind == SyntaxKind::Instruction => Some(node),
+            _ => None,
+        });
+        self.dockerfile
+            .instructions
+            .iter()
+            .zip(nodes)
+            .map(|(instruction, syntax)| InstructionNode { syntax, instruction })
+    }
+}
+impl fmt::Display for SyntaxTree<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.root.text)
+    }
+}
+
+/// A typed view of an instruction node.
+///
+/// This maps the syntax node of an instruction to its AST, and the
+/// [flags](Self::flags), [arguments](Self::arguments), and
+/// [here-documents](Self::here_docs) of the instruction to their syntax
+/// elements.
+#[derive(Debug, Clone, Copy)]
+pub struct InstructionNode<'a, 't> {
+    syntax: &'t SyntaxNode<'a>,
+    instruction: &'t Instruction<'a>,
+}
+impl<'a, 't> InstructionNode<'a, 't> {
+    /// Returns the syntax node of this instruction.
+    #[must_use]
+    pub fn syntax(&self) -> &'t SyntaxNode<'a> {
+        self.syntax
+    }
+    /// Returns the AST of this instruction.
+    #[must_use]
+    pub fn instruction(&self) -> &'t Instruction<'a> {
+        self.instruction
+    }
+    /// Returns the keyword token of this instruction.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)] // instruction node always starts with keyword
+    pub fn keyword(&self) -> &'t SyntaxToken<'a> {
+        match self.syntax.children.first() {
+            Some(SyntaxElement::Token(token)) if token.kind == SyntaxKind::Keyword => token,
+            _ => unreachable!(),
+        }
+    }
+    /// Returns the flags of this instruction and their syntax elements.
+    ///
+    /// Unlike [`Instruction::options`], this doesn't include the flags of the
+    /// trigger instruction of `ONBUILD`. Use
+    /// [`onbuild_trigger`](Self::onbuild_trigger) to get them.
+    #[must_use]
+    pub fn flags(&self) -> impl ExactSizeIterator<Item = SyntaxPart<'a, 't, Flag<'a>>> + '_ {
+        let options = match self.instruction {
+            Instruction::Onbuild(..) => &[],
+            instruction => instruction.options(),
+        };
+        options.iter().map(|flag| self.part(flag, flag.span()))
+    }
+    /// Returns the arguments of this instruction and their syntax elements,
+    /// i.e., the elements after the keyword and the flags, including
+    /// here-documents.
+    ///
+    /// For `ONBUILD` instruction, this contains the node of the trigger
+    /// instruction.
+    ///
+    /// Returns `None` if this instruction has no arguments.
+    #[must_use]
+    pub fn arguments(&self) -> Option<SyntaxPart<'a, 't, Instruction<'a>>> {
+        let bom_len = self.bom_len();
+        let start = self.flags().map(|f| f.range.end).fold(self.keyword().range.end, usize::max);
+        let first = self
+            .syntax
+            .children
+            .iter()
+            .map(SyntaxElement::range)
+            .find(|range| range.start >= start && !self.is_trivia_at(range.start))?;
+        let span = first.start - bom_len..self.syntax.range.end - bom_len;
+        Some(self.part(self.instruction, span))
+    }
+    /// Returns the here-documents of this instruction and their syntax
+    /// elements (the body and the closing delimiter).
+    pub fn here_docs(&self) -> impl Iterator<Item = SyntaxPart<'a, 't, HereDoc<'a>>> + '_ {
+        let here_docs = match self.instruction {
+            Instruction::Add(add) => here_docs_in_sources(&add.src),
+            Instruction::Copy(copy) => here_docs_in_sources(&copy.src),
+            Instruction::Run(run) => run.here_docs.iter().collect(),
+            Instruction::Unknown(unknown) => unknown.here_docs.iter().collect(),
+            _ => vec![],
+        };
+        here_docs.into_iter().map(|here_doc| self.part(here_doc, here_doc.span.start..here_doc.end))
+    }
+    /// For `ONBUILD` instruction, returns the trigger instruction.
+    #[must_use]
+    pub fn onbuild_trigger(&self) -> Option<InstructionNode<'a, 't>> {
+        let Instruction::Onbuild(onbuild) = self.instruction else { return None };
+        let syntax = self.syntax.children.iter().find_map(|e| match e {
+            SyntaxElement::Node(node) if node.kind == SyntaxKind::Instruction => Some(node),
+            _ => None,
+        })?;
+        Some(InstructionNode { syntax, instruction: &onbuild.instruction })
+    }
+
+    fn bom_len(&self) -> usize {
+        self.syntax.range.start - self.instruction.span().start
+    }
+    fn is_trivia_at(&self, pos: usize) -> bool {
+        self.syntax.children.iter().any(|e| match e {
+            SyntaxElement::Token(token) => {
+                token.range.start == pos
+                    && matches!(
+                        token.kind,
+                        SyntaxKind::Whitespace
+                            | SyntaxKind::Newline
+                            | SyntaxKind::Comment
+                            | SyntaxKind::LineContinuation
+                    )
+            }
+            SyntaxElement::Node(..) => false,
+        })
+    }
+    /// Returns the part of this node at `span` in the AST.
+    fn part<T: ?Sized>(&self, ast: &'t T, span: Span) -> SyntaxPart<'a, 't, T> {
+        let bom_len = self.bom_len();
+        let range = span.start + bom_len..span.end + bom_len;
+        // Tokens are split at whitespace, so values in the AST consist of whole elements.
+        let children = &self.syntax.children;
+        let start =
+            children.iter().position(|e| e.range().start >= range.start).unwrap_or(children.len());
+        let len = children[start..].iter().take_while(|e| e.range().end <= range.end).count();
+        SyntaxPart { ast, node: self.syntax, range, elements: &children[start..start + len] }
+    }
+}
+
+/// A part of an [instruction node](InstructionNode) that corresponds to a
+/// value in the AST, such as a flag or a here-document.
+#[derive(Debug)]
+pub struct SyntaxPart<'a, 't, T: ?Sized> {
+    ast: &'t T,
+    node: &'t SyntaxNode<'a>,
+    range: Span,
+    elements: &'t [SyntaxElement<'a>],
+}
+impl<T: ?Sized> Clone for SyntaxPart<'_, '_, T> {
+    fn clone(&self) -> Self {
+        Self { range: self.range.clone(), ..*self }
+    }
+}
+impl<'a, 't, T: ?Sized> SyntaxPart<'a, 't, T> {
+    /// Returns the AST of this part.
+    #[must_use]
+    pub fn ast(&self) -> &'t T {
+        self.ast
+    }
+    /// Returns the range of this part in the input.
+    #[must_use]
+    pub fn range(&self) -> Span {
+        self.range.clone()
+    }
+    /// Returns the source text of this part, including line continuations
+    /// and comments in it.
+    #[must_use]
+    pub fn text(&self) -> &'a str {
+        let start = self.node.range.start;
+        &self.node.text[self.range.start - start..self.range.end - start]
+    }
+    /// Returns the syntax elements of this part.
+    #[must_use]
+    pub fn elements(&self) -> &'t [SyntaxElement<'a>] {
+        self.elements
+    }
+}
+
+/// A node in the syntax tree.
+#[derive(Debug)]
+pub struct SyntaxNode<'a> {
+    kind: SyntaxKind,
+    range: Span,
+    text: &'a str,
+    children: Vec<SyntaxElement<'a>>,
+}
+impl<'a> SyntaxNode<'a> {
+    /// Returns the kind of this node.
+    #[must_use]
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+    /// Returns the range of this node in the input.
+    #[must_use]
+    pub fn range(&self) -> Span {
+        self.range.clone()
+    }
+    /// Returns the source text of this node.
+    #[must_use]
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+    /// Returns the children of this node.
+    #[must_use]
+    pub fn children(&self) -> &[SyntaxElement<'a>] {
+        &self.children
+    }
+    /// Returns an iterator over all tokens in this node in order.
+    pub fn tokens(&self) -> Tokens<'a, '_> {
+        Tokens { stack: vec![self.children.iter()] }
+    }
+}
+
+/// A token in the syntax tree.
+#[derive(Debug)]
+pub struct SyntaxToken<'a> {
+    kind: SyntaxKind,
+    range: Span,
+    text: &'a str,
+}
+impl<'a> SyntaxToken<'a> {
+    /// Returns the kind of this token.
+    #[must_use]
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+    /// Returns the range of this token in the input.
+    #[must_use]
+    pub fn range(&self) -> Span {
+        self.range.clone()
+    }
+    /// Returns the source text of this token.
+    #[must_use]
+    pub fn text(&self) -> &'a str {
+        self.text
+    }
+}
+
+/// A [node](SyntaxNode) or [token](SyntaxToken).
+#[derive(Debug)]
+#[allow(clippy::exhaustive_enums)]
+pub enum SyntaxElement<'a> {
+    /// A node.
+    Node(SyntaxNode<'a>),
+    /// A token.
+    Token(SyntaxToken<'a>),
+}
+impl SyntaxElement<'_> {
+    /// Returns the kind of this element.
+    #[must_use]
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            Self::Node(node) => node.kind,
+            Self::Token(token) => token.kind,
+        }
+    }
+    /// Returns the range of this element in the input.
+    #[must_use]
+    pub fn range(&self) -> Span {
+        match self {
+            Self::Node(node) => node.range.clone(),
+            Self::Token(token) => token.range.clone(),
+        }
+    }
+}
+
+/// An iterator over tokens in a node.
+///
+/// This type is returned by [`SyntaxNode::tokens`].
+#[derive(Debug)]
+#[must_use = "iterators are lazy and do nothing unless consumed"]
+pub struct Tokens<'a, 't> {
+    stack: Vec<core::slice::Iter<'t, SyntaxElement<'a>>>,
+}
+impl<'a, 't> Iterator for Tokens<'a, 't> {
+    type Item = &'t SyntaxToken<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.last_mut()?.next() {
+                Some(SyntaxElement::Token(token)) => return Some(token),
+                Some(SyntaxElement::Node(node)) => self.stack.push(node.children.iter()),
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
+
+struct Builder<'a> {
+    text: &'a str,
+    bom_len: usize,
+    escape_byte: u8,
+    directive_starts: Vec<usize>,
+}
+impl<'a> Builder<'a> {
+    fn new(text: &'a str, dockerfile: &Dockerfile<'_>, default_escape_byte: u8) -> Self {
+        let bom_len = if text.as_bytes().starts_with(UTF8_BOM) { UTF8_BOM.len() } else { 0 };
+        let directives = &dockerfile.parser_directives;
+        let escape_byte =
+            directives.escape.as_ref().map_or(default_escape_byte, |e| e.value.value as u8);
+        let mut directive_starts = vec![];
+        directive_starts.extend(directives.syntax.as_ref().map(|d| d.start + bom_len));
+        directive_starts.extend(directives.escape.as_ref().map(|d| d.start + bom_len));
+        directive_starts.extend(directives.check.as_ref().map(|d| d.start + bom_len));
+        Self { text, bom_len, escape_byte, directive_starts }
+    }
+
+    fn build(&self, dockerfile: &Dockerfile<'_>) -> SyntaxNode<'a> {
+        let mut children = vec![];
+        if self.bom_len != 0 {
+            children.push(self.token(SyntaxKind::Bom, 0..self.bom_len));
+        }
+        let mut pos = self.bom_len;
+        for instruction in &dockerfile.instructions {
+            let span = instruction.span();
+            let range = span.start + self.bom_len..span.end + self.bom_len;
+            self.trivia(&mut children, pos..range.start);
+            children.push(SyntaxElement::Node(self.instruction(instruction)));
+            pos = range.end;
+        }
+        self.trivia(&mut children, pos..self.text.len());
+        self.node(SyntaxKind::Root, 0..self.text.len(), children)
+    }
+
+    fn token(&self, kind: SyntaxKind, range: Span) -> SyntaxElement<'a> {
+        SyntaxElement::Token(SyntaxToken { kind, text: &self.text[range.clone()], range })
+    }
+    fn node(
+        &self,
+        kind: SyntaxKind,
+        range: Span,
+        children: Vec<SyntaxElement<'a>>,
+    ) -> SyntaxNode<'a> {
+        SyntaxNode { kind, text: &self.text[range.clone()], range, children }
+    }
+
+    /// Tokenizes text outside of instructions.
+    fn trivia(&self, out: &mut Vec<SyntaxElement<'a>>, range: Span) {
+        let start = out.len();
+        self.tokenize(out, range, true);
+        for e in &mut out[start..] {
+            let SyntaxElement::Token(token) = e else { continue };
+            if token.kind == SyntaxKind::Comment
+                && self.directive_starts.iter().any(|&s| token.range.contains(&s))
+            {
+                let range = token.range.clone();
+                let token = self.token(SyntaxKind::Comment, range.clone());
+                *e =
+                    SyntaxElement::Node(self.node(SyntaxKind::ParserDirective, range, vec![token]));
+            }
+        }
+    }
+
+    fn instruction(&self, instruction: &Instruction<'_>) -> SyntaxNode<'a> {
+        let span = instruction.span();
+        let range = span.start + self.bom_len..span.end + self.bom_len;
+        let keyword = instruction.keyword().span.clone();
+        let keyword = keyword.start + self.bom_len..keyword.end + self.bom_len;
+        let mut children = vec![self.token(SyntaxKind::Keyword, keyword.clone())];
+        let mut pos = keyword.end;
+        if let Instruction::Onbuild(onbuild) = instruction {
+            let inner = self.instruction(&onbuild.instruction);
+            self.tokenize(&mut children, pos..inner.range.start, false);
+            pos = inner.range.end;
+            children.push(SyntaxElement::Node(inner));
+        } else {
+            let here_docs = match instruction {
+                Instruction::Add(add) => here_docs_in_sources(&add.src),
+                Instruction::Copy(copy) => here_docs_in_sources(&copy.src),
+                Instruction::Run(run) => run.here_docs.iter().collect(),
+                Instruction::Unknown(unknown) => unknown.here_docs.iter().collect(),
+                _ => vec![],
+            };
+            for here_doc in here_docs {
+                let body = here_doc.span.start + self.bom_len..here_doc.span.end + self.bom_len;
+                self.tokenize(&mut children, pos..body.start, false);
+                if !body.is_empty() {
+                    children.push(self.token(SyntaxKind::HereDocBody, body.clone()));
+                }
+                let end = here_doc.end + self.bom_len;
+                children.push(self.token(SyntaxKind::HereDocDelimiter, body.end..end));
+                pos = end;
+            }
+        }
+        self.tokenize(&mut children, pos..range.end, false);
+        self.node(SyntaxKind::Instruction, range, children)
+    }
+
+    /// Splits the given range into whitespace, newline, comment, line
+    /// continuation, and text tokens.
+    fn tokenize(&self, out: &mut Vec<SyntaxElement<'a>>, range: Span, mut line_start: bool) {
+        let s = self.text.as_bytes();
+        let mut pos = range.start;
+        let mut text_start = None;
+        while pos < range.end {
+            let b = s[pos];
+            let (kind, end) = match b {
+                b'\n' => (SyntaxKind::Newline, pos + 1),
+                b'\r' if s.get(pos + 1) == Some(&b'\n') => (SyntaxKind::Newline, pos + 2),
+                b' ' | b'\t' => (SyntaxKind::Whitespace, skip_spaces(s, pos, range.end)),
+                b'#' if line_start => {
+                    let mut end = pos;
+                    while end < range.end && s[end] != b'\n' {
+                        end += 1;
+                    }
+                    if s[end - 1] == b'\r' && end < range.end {
+                        end -= 1;
+                    }
+                    (SyntaxKind::Comment, end)
+                }
+                _ if b == self.escape_byte => {
+                    let end = skip_spaces(s, pos + 1, range.end);
+                    match s.get(end) {
+                        Some(b'\n') if end < range.end => (SyntaxKind::LineContinuation, end + 1),
+                        Some(b'\r') if end + 1 < range.end && s[end + 1] == b'\n' => {
+                            (SyntaxKind::LineContinuation, end + 2)
+                        }
+                        _ => (SyntaxKind::Text, pos + 1),
+                    }
+                }
+                _ => (SyntaxKind::Text, pos + 1),
+            };
+            if kind == SyntaxKind::Text {
+                text_start.get_or_insert(pos);
+            } else {
+                if let Some(start) = text_start.take() {
+                    out.push(self.token(SyntaxKind::Text, start..pos));
+                }
+                out.push(self.token(kind, pos..end));
+            }
+            match kind {
+                SyntaxKind::Newline | SyntaxKind::LineContinuation => line_start = true,
+                SyntaxKind::Whitespace => {}
+                _ => line_start = false,
+            }
+            pos = end;
+        }
+        if let Some(start) = text_start {
+            out.push(self.token(SyntaxKind::Text, start..range.end));
+        }
+    }
+}
+
+fn here_docs_in_sources<'b, 'a>(src: &'b [Source<'a>]) -> Vec<&'b HereDoc<'a>> {
+    src.iter()
+        .filter_map(|src| match src {
+            Source::HereDoc(here_doc) => Some(here_doc),
+            Source::Path(..) => None,
+        })
+        .collect()
+}
+
+fn skip_spaces(s: &[u8], mut pos: usize, end: usize) -> usize {
+    while pos < end && matches!(s[pos], b' ' | b'\t') {
+        pos += 1;
+    }
+    pos
+}
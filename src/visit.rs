@@ -0,0 +1,411 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Syntax tree traversal to walk a shared borrow of a dockerfile syntax tree.
+//!
+//! Each method of the [`Visit`] trait is a hook that can be overridden to
+//! customize the behavior when visiting the corresponding type of node. By
+//! default, every method recursively visits the substructure of the input by
+//! invoking the right visitor method of each of its fields.
+//!
+//! ```
+//! use parse_dockerfile::{HereDoc, parse, visit::Visit};
+//!
+//! struct HereDocCounter(usize);
+//! impl<'ast, 'a> Visit<'ast, 'a> for HereDocCounter {
+//!     fn visit_here_doc(&mut self, _node: &'ast HereDoc<'a>) {
+//!         self.0 += 1;
+//!     }
+//! }
+//!
+//! let text = "FROM alpine
+//! RUN <<EOF
+//! echo
+//! EOF
+//! ONBUILD COPY <<EOF /dest
+//! EOF
+//! ";
+//! let dockerfile = parse(text).unwrap();
+//! let mut counter = HereDocCounter(0);
+//! counter.visit_dockerfile(&dockerfile);
+//! assert_eq!(counter.0, 2);
+//! ```
+
+#![allow(missing_docs)]
+
+use crate::{
+    AddInstruction, ArgInstruction, CmdInstruction, Command, CopyInstruction, Dockerfile,
+    EntrypointInstruction, EnvInstruction, ExposeInstruction, Flag, FromInstruction,
+    HealthcheckArguments, HealthcheckInstruction, HereDoc, Instruction, JsonOrStringArray, Keyword,
+    LabelInstruction, MaintainerInstruction, OnbuildInstruction, ParserDirectives, RunInstruction,
+    ShellInstruction, Source, Spanned, StopsignalInstruction, UnescapedString, UnknownInstruction,
+    UserInstruction, VolumeInstruction, WorkdirInstruction,
+};
+
+/// Syntax tree traversal to walk a shared borrow of a dockerfile syntax tree.
+///
+/// See the [module documentation] for details.
+///
+/// [module documentation]: self
+pub trait Visit<'ast, 'a> {
+    fn visit_dockerfile(&mut self, node: &'ast Dockerfile<'a>) {
+        visit_dockerfile(self, node);
+    }
+    fn visit_parser_directives(&mut self, node: &'ast ParserDirectives<'a>) {
+        visit_parser_directives(self, node);
+    }
+    fn visit_instruction(&mut self, node: &'ast Instruction<'a>) {
+        visit_instruction(self, node);
+    }
+    fn visit_add_instruction(&mut self, node: &'ast AddInstruction<'a>) {
+        visit_add_instruction(self, node);
+    }
+    fn visit_arg_instruction(&mut self, node: &'ast ArgInstruction<'a>) {
+        visit_arg_instruction(self, node);
+    }
+    fn visit_cmd_instruction(&mut self, node: &'ast CmdInstruction<'a>) {
+        visit_cmd_instruction(self, node);
+    }
+    fn visit_copy_instruction(&mut self, node: &'ast CopyInstruction<'a>) {
+        visit_copy_instruction(self, node);
+    }
+    fn visit_entrypoint_instruction(&mut self, node: &'ast EntrypointInstruction<'a>) {
+        visit_entrypoint_instruction(self, node);
+    }
+    fn visit_env_instruction(&mut self, node: &'ast EnvInstruction<'a>) {
+        visit_env_instruction(self, node);
+    }
+    fn visit_expose_instruction(&mut self, node: &'ast ExposeInstruction<'a>) {
+        visit_expose_instruction(self, node);
+    }
+    fn visit_from_instruction(&mut self, node: &'ast FromInstruction<'a>) {
+        visit_from_instruction(self, node);
+    }
+    fn visit_healthcheck_instruction(&mut self, node: &'ast HealthcheckInstruction<'a>) {
+        visit_healthcheck_instruction(self, node);
+    }
+    fn visit_healthcheck_arguments(&mut self, node: &'ast HealthcheckArguments<'a>) {
+        visit_healthcheck_arguments(self, node);
+    }
+    fn visit_label_instruction(&mut self, node: &'ast LabelInstruction<'a>) {
+        visit_label_instruction(self, node);
+    }
+    fn visit_maintainer_instruction(&mut self, node: &'ast MaintainerInstruction<'a>) {
+        visit_maintainer_instruction(self, node);
+    }
+    fn visit_onbuild_instruction(&mut self, node: &'ast OnbuildInstruction<'a>) {
+        visit_onbuild_instruction(self, node);
+    }
+    fn visit_run_instruction(&mut self, node: &'ast RunInstruction<'a>) {
+        visit_run_instruction(self, node);
+    }
+    fn visit_shell_instruction(&mut self, node: &'ast ShellInstruction<'a>) {
+        visit_shell_instruction(self, node);
+    }
+    fn visit_stopsignal_instruction(&mut self, node: &'ast StopsignalInstruction<'a>) {
+        visit_stopsignal_instruction(self, node);
+    }
+    fn visit_user_instruction(&mut self, node: &'ast UserInstruction<'a>) {
+        visit_user_instruction(self, node);
+    }
+    fn visit_volume_instruction(&mut self, node: &'ast VolumeInstruction<'a>) {
+        visit_volume_instruction(self, node);
+    }
+    fn visit_workdir_instruction(&mut self, node: &'ast WorkdirInstruction<'a>) {
+        visit_workdir_instruction(self, node);
+    }
+    fn visit_unknown_instruction(&mut self, node: &'ast UnknownInstruction<'a>) {
+        visit_unknown_instruction(self, node);
+    }
+    fn visit_keyword(&mut self, node: &'ast Keyword) {
+        visit_keyword(self, node);
+    }
+    fn visit_flag(&mut self, node: &'ast Flag<'a>) {
+        visit_flag(self, node);
+    }
+    fn visit_source(&mut self, node: &'ast Source<'a>) {
+        visit_source(self, node);
+    }
+    fn visit_command(&mut self, node: &'ast Command<'a>) {
+        visit_command(self, node);
+    }
+    fn visit_json_or_string_array(&mut self, node: &'ast JsonOrStringArray<'a, 1>) {
+        visit_json_or_string_array(self, node);
+    }
+    fn visit_here_doc(&mut self, node: &'ast HereDoc<'a>) {
+        visit_here_doc(self, node);
+    }
+    fn visit_unescaped_string(&mut self, node: &'ast UnescapedString<'a>) {
+        visit_unescaped_string(self, node);
+    }
+    fn visit_spanned_str(&mut self, node: &'ast Spanned<&'a str>) {
+        visit_spanned_str(self, node);
+    }
+}
+
+pub fn visit_dockerfile<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast Dockerfile<'a>,
+) {
+    v.visit_parser_directives(&node.parser_directives);
+    for instruction in &node.instructions {
+        v.visit_instruction(instruction);
+    }
+}
+pub fn visit_parser_directives<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    _v: &mut V,
+    _node: &'ast ParserDirectives<'a>,
+) {
+}
+pub fn visit_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast Instruction<'a>,
+) {
+    match node {
+        Instruction::Add(node) => v.visit_add_instruction(node),
+        Instruction::Arg(node) => v.visit_arg_instruction(node),
+        Instruction::Cmd(node) => v.visit_cmd_instruction(node),
+        Instruction::Copy(node) => v.visit_copy_instruction(node),
+        Instruction::Entrypoint(node) => v.visit_entrypoint_instruction(node),
+        Instruction::Env(node) => v.visit_env_instruction(node),
+        Instruction::Expose(node) => v.visit_expose_instruction(node),
+        Instruction::From(node) => v.visit_from_instruction(node),
+        Instruction::Healthcheck(node) => v.visit_healthcheck_instruction(node),
+        Instruction::Label(node) => v.visit_label_instruction(node),
+        Instruction::Maintainer(node) => v.visit_maintainer_instruction(node),
+        Instruction::Onbuild(node) => v.visit_onbuild_instruction(node),
+        Instruction::Run(node) => v.visit_run_instruction(node),
+        Instruction::Shell(node) => v.visit_shell_instruction(node),
+        Instruction::Stopsignal(node) => v.visit_stopsignal_instruction(node),
+        Instruction::User(node) => v.visit_user_instruction(node),
+        Instruction::Volume(node) => v.visit_volume_instruction(node),
+        Instruction::Workdir(node) => v.visit_workdir_instruction(node),
+        Instruction::Unknown(node) => v.visit_unknown_instruction(node),
+    }
+}
+pub fn visit_add_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast AddInstruction<'a>,
+) {
+    v.visit_keyword(&node.add);
+    for flag in &node.options {
+        v.visit_flag(flag);
+    }
+    for src in &node.src {
+        v.visit_source(src);
+    }
+    v.visit_unescaped_string(&node.dest);
+}
+pub fn visit_arg_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast ArgInstruction<'a>,
+) {
+    v.visit_keyword(&node.arg);
+    v.visit_unescaped_string(&node.arguments);
+}
+pub fn visit_cmd_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast CmdInstruction<'a>,
+) {
+    v.visit_keyword(&node.cmd);
+    v.visit_command(&node.arguments);
+}
+pub fn visit_copy_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast CopyInstruction<'a>,
+) {
+    v.visit_keyword(&node.copy);
+    for flag in &node.options {
+        v.visit_flag(flag);
+    }
+    for src in &node.src {
+        v.visit_source(src);
+    }
+    v.visit_unescaped_string(&node.dest);
+}
+pub fn visit_entrypoint_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast EntrypointInstruction<'a>,
+) {
+    v.visit_keyword(&node.entrypoint);
+    v.visit_command(&node.arguments);
+}
+pub fn visit_env_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast EnvInstruction<'a>,
+) {
+    v.visit_keyword(&node.env);
+    v.visit_unescaped_string(&node.arguments);
+}
+pub fn visit_expose_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast ExposeInstruction<'a>,
+) {
+    v.visit_keyword(&node.expose);
+    for argument in &node.arguments {
+        v.visit_unescaped_string(argument);
+    }
+}
+pub fn visit_from_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast FromInstruction<'a>,
+) {
+    v.visit_keyword(&node.from);
+    for flag in &node.options {
+        v.visit_flag(flag);
+    }
+    v.visit_unescaped_string(&node.image);
+    if let Some((as_, name)) = &node.as_ {
+        v.visit_keyword(as_);
+        v.visit_unescaped_string(name);
+    }
+}
+pub fn visit_healthcheck_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast HealthcheckInstruction<'a>,
+) {
+    v.visit_keyword(&node.healthcheck);
+    for flag in &node.options {
+        v.visit_flag(flag);
+    }
+    v.visit_healthcheck_arguments(&node.arguments);
+}
+pub fn visit_healthcheck_arguments<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast HealthcheckArguments<'a>,
+) {
+    match node {
+        HealthcheckArguments::Cmd { cmd, arguments } => {
+            v.visit_keyword(cmd);
+            v.visit_command(arguments);
+        }
+        HealthcheckArguments::None { none } => v.visit_keyword(none),
+    }
+}
+pub fn visit_label_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast LabelInstruction<'a>,
+) {
+    v.visit_keyword(&node.label);
+    v.visit_unescaped_string(&node.arguments);
+}
+pub fn visit_maintainer_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast MaintainerInstruction<'a>,
+) {
+    v.visit_keyword(&node.maintainer);
+    v.visit_unescaped_string(&node.name);
+}
+pub fn visit_onbuild_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast OnbuildInstruction<'a>,
+) {
+    v.visit_keyword(&node.onbuild);
+    v.visit_instruction(&node.instruction);
+}
+pub fn visit_run_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast RunInstruction<'a>,
+) {
+    v.visit_keyword(&node.run);
+    for flag in &node.options {
+        v.visit_flag(flag);
+    }
+    v.visit_command(&node.arguments);
+    for here_doc in &node.here_docs {
+        v.visit_here_doc(here_doc);
+    }
+}
+pub fn visit_shell_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast ShellInstruction<'a>,
+) {
+    v.visit_keyword(&node.shell);
+    for argument in &node.arguments {
+        v.visit_unescaped_string(argument);
+    }
+}
+pub fn visit_stopsignal_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast StopsignalInstruction<'a>,
+) {
+    v.visit_keyword(&node.stopsignal);
+    v.visit_unescaped_string(&node.arguments);
+}
+pub fn visit_user_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast UserInstruction<'a>,
+) {
+    v.visit_keyword(&node.user);
+    v.visit_unescaped_string(&node.arguments);
+}
+pub fn visit_volume_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast VolumeInstruction<'a>,
+) {
+    v.visit_keyword(&node.volume);
+    v.visit_json_or_string_array(&node.arguments);
+}
+pub fn visit_workdir_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast WorkdirInstruction<'a>,
+) {
+    v.visit_keyword(&node.workdir);
+    v.visit_unescaped_string(&node.arguments);
+}
+pub fn visit_unknown_instruction<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast UnknownInstruction<'a>,
+) {
+    v.visit_keyword(&node.keyword);
+    v.visit_spanned_str(&node.arguments);
+    for here_doc in &node.here_docs {
+        v.visit_here_doc(here_doc);
+    }
+}
+pub fn visit_keyword<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(_v: &mut V, _node: &'ast Keyword) {}
+pub fn visit_flag<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(v: &mut V, node: &'ast Flag<'a>) {
+    v.visit_unescaped_string(&node.name);
+    if let Some(value) = &node.value {
+        v.visit_unescaped_string(value);
+    }
+}
+pub fn visit_source<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(v: &mut V, node: &'ast Source<'a>) {
+    match node {
+        Source::Path(node) => v.visit_unescaped_string(node),
+        Source::HereDoc(node) => v.visit_here_doc(node),
+    }
+}
+pub fn visit_command<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(v: &mut V, node: &'ast Command<'a>) {
+    match node {
+        Command::Exec(array) => {
+            for argument in &array.value {
+                v.visit_unescaped_string(argument);
+            }
+        }
+        Command::Shell(node) => v.visit_spanned_str(node),
+    }
+}
+pub fn visit_json_or_string_array<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    v: &mut V,
+    node: &'ast JsonOrStringArray<'a, 1>,
+) {
+    let arguments = match node {
+        JsonOrStringArray::Json(array) => &array.value,
+        JsonOrStringArray::String(arguments) => arguments,
+    };
+    for argument in arguments {
+        v.visit_unescaped_string(argument);
+    }
+}
+pub fn visit_here_doc<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(_v: &mut V, _node: &'ast HereDoc<'a>) {
+}
+pub fn visit_unescaped_string<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    _v: &mut V,
+    _node: &'ast UnescapedString<'a>,
+) {
+}
+pub fn visit_spanned_str<'ast, 'a, V: Visit<'ast, 'a> + ?Sized>(
+    _v: &mut V,
+    _node: &'ast Spanned<&'a str>,
+) {
+}
@@ -0,0 +1,402 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Syntax tree traversal to mutate an exclusive borrow of a dockerfile syntax tree in place.
+//!
+//! Each method of the [`VisitMut`] trait is a hook that can be overridden to
+//! customize the behavior when visiting the corresponding type of node. By
+//! default, every method recursively visits the substructure of the input by
+//! invoking the right visitor method of each of its fields.
+//!
+//! ```
+//! use parse_dockerfile::{UnescapedString, parse, visit_mut::VisitMut};
+//!
+//! struct Uppercase;
+//! impl VisitMut<'_> for Uppercase {
+//!     fn visit_unescaped_string_mut(&mut self, node: &mut UnescapedString<'_>) {
+//!         node.value = node.value.to_uppercase().into();
+//!     }
+//! }
+//!
+//! let text = "FROM alpine AS build
+//! USER app
+//! ";
+//! let mut dockerfile = parse(text).unwrap();
+//! Uppercase.visit_dockerfile_mut(&mut dockerfile);
+//! let mut stages = dockerfile.stages();
+//! let stage = stages.next().unwrap();
+//! assert_eq!(stage.from.image.value, "ALPINE");
+//! assert_eq!(stage.from.as_.as_ref().unwrap().1.value, "BUILD");
+//! ```
+
+#![allow(missing_docs)]
+
+use crate::{
+    AddInstruction, ArgInstruction, CmdInstruction, Command, CopyInstruction, Dockerfile,
+    EntrypointInstruction, EnvInstruction, ExposeInstruction, Flag, FromInstruction,
+    HealthcheckArguments, HealthcheckInstruction, HereDoc, Instruction, JsonOrStringArray, Keyword,
+    LabelInstruction, MaintainerInstruction, OnbuildInstruction, ParserDirectives, RunInstruction,
+    ShellInstruction, Source, Spanned, StopsignalInstruction, UnescapedString, UnknownInstruction,
+    UserInstruction, VolumeInstruction, WorkdirInstruction,
+};
+
+/// Syntax tree traversal to mutate an exclusive borrow of a dockerfile syntax tree in place.
+///
+/// See the [module documentation] for details.
+///
+/// [module documentation]: self
+pub trait VisitMut<'a> {
+    fn visit_dockerfile_mut(&mut self, node: &mut Dockerfile<'a>) {
+        visit_dockerfile_mut(self, node);
+    }
+    fn visit_parser_directives_mut(&mut self, node: &mut ParserDirectives<'a>) {
+        visit_parser_directives_mut(self, node);
+    }
+    fn visit_instruction_mut(&mut self, node: &mut Instruction<'a>) {
+        visit_instruction_mut(self, node);
+    }
+    fn visit_add_instruction_mut(&mut self, node: &mut AddInstruction<'a>) {
+        visit_add_instruction_mut(self, node);
+    }
+    fn visit_arg_instruction_mut(&mut self, node: &mut ArgInstruction<'a>) {
+        visit_arg_instruction_mut(self, node);
+    }
+    fn visit_cmd_instruction_mut(&mut self, node: &mut CmdInstruction<'a>) {
+        visit_cmd_instruction_mut(self, node);
+    }
+    fn visit_copy_instruction_mut(&mut self, node: &mut CopyInstruction<'a>) {
+        visit_copy_instruction_mut(self, node);
+    }
+    fn visit_entrypoint_instruction_mut(&mut self, node: &mut EntrypointInstruction<'a>) {
+        visit_entrypoint_instruction_mut(self, node);
+    }
+    fn visit_env_instruction_mut(&mut self, node: &mut EnvInstruction<'a>) {
+        visit_env_instruction_mut(self, node);
+    }
+    fn visit_expose_instruction_mut(&mut self, node: &mut ExposeInstruction<'a>) {
+        visit_expose_instruction_mut(self, node);
+    }
+    fn visit_from_instruction_mut(&mut self, node: &mut FromInstruction<'a>) {
+        visit_from_instruction_mut(self, node);
+    }
+    fn visit_healthcheck_instruction_mut(&mut self, node: &mut HealthcheckInstruction<'a>) {
+        visit_healthcheck_instruction_mut(self, node);
+    }
+    fn visit_healthcheck_arguments_mut(&mut self, node: &mut HealthcheckArguments<'a>) {
+        visit_healthcheck_arguments_mut(self, node);
+    }
+    fn visit_label_instruction_mut(&mut self, node: &mut LabelInstruction<'a>) {
+        visit_label_instruction_mut(self, node);
+    }
+    fn visit_maintainer_instruction_mut(&mut self, node: &mut MaintainerInstruction<'a>) {
+        visit_maintainer_instruction_mut(self, node);
+    }
+    fn visit_onbuild_instruction_mut(&mut self, node: &mut OnbuildInstruction<'a>) {
+        visit_onbuild_instruction_mut(self, node);
+    }
+    fn visit_run_instruction_mut(&mut self, node: &mut RunInstruction<'a>) {
+        visit_run_instruction_mut(self, node);
+    }
+    fn visit_shell_instruction_mut(&mut self, node: &mut ShellInstruction<'a>) {
+        visit_shell_instruction_mut(self, node);
+    }
+    fn visit_stopsignal_instruction_mut(&mut self, node: &mut StopsignalInstruction<'a>) {
+        visit_stopsignal_instruction_mut(self, node);
+    }
+    fn visit_user_instruction_mut(&mut self, node: &mut UserInstruction<'a>) {
+        visit_user_instruction_mut(self, node);
+    }
+    fn visit_volume_instruction_mut(&mut self, node: &mut VolumeInstruction<'a>) {
+        visit_volume_instruction_mut(self, node);
+    }
+    fn visit_workdir_instruction_mut(&mut self, node: &mut WorkdirInstruction<'a>) {
+        visit_workdir_instruction_mut(self, node);
+    }
+    fn visit_unknown_instruction_mut(&mut self, node: &mut UnknownInstruction<'a>) {
+        visit_unknown_instruction_mut(self, node);
+    }
+    fn visit_keyword_mut(&mut self, node: &mut Keyword) {
+        visit_keyword_mut(self, node);
+    }
+    fn visit_flag_mut(&mut self, node: &mut Flag<'a>) {
+        visit_flag_mut(self, node);
+    }
+    fn visit_source_mut(&mut self, node: &mut Source<'a>) {
+        visit_source_mut(self, node);
+    }
+    fn visit_command_mut(&mut self, node: &mut Command<'a>) {
+        visit_command_mut(self, node);
+    }
+    fn visit_json_or_string_array_mut(&mut self, node: &mut JsonOrStringArray<'a, 1>) {
+        visit_json_or_string_array_mut(self, node);
+    }
+    fn visit_here_doc_mut(&mut self, node: &mut HereDoc<'a>) {
+        visit_here_doc_mut(self, node);
+    }
+    fn visit_unescaped_string_mut(&mut self, node: &mut UnescapedString<'a>) {
+        visit_unescaped_string_mut(self, node);
+    }
+    fn visit_spanned_str_mut(&mut self, node: &mut Spanned<&'a str>) {
+        visit_spanned_str_mut(self, node);
+    }
+}
+
+pub fn visit_dockerfile_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, node: &mut Dockerfile<'a>) {
+    v.visit_parser_directives_mut(&mut node.parser_directives);
+    for instruction in &mut node.instructions {
+        v.visit_instruction_mut(instruction);
+    }
+}
+pub fn visit_parser_directives_mut<'a, V: VisitMut<'a> + ?Sized>(
+    _v: &mut V,
+    _node: &mut ParserDirectives<'a>,
+) {
+}
+pub fn visit_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, node: &mut Instruction<'a>) {
+    match node {
+        Instruction::Add(node) => v.visit_add_instruction_mut(node),
+        Instruction::Arg(node) => v.visit_arg_instruction_mut(node),
+        Instruction::Cmd(node) => v.visit_cmd_instruction_mut(node),
+        Instruction::Copy(node) => v.visit_copy_instruction_mut(node),
+        Instruction::Entrypoint(node) => v.visit_entrypoint_instruction_mut(node),
+        Instruction::Env(node) => v.visit_env_instruction_mut(node),
+        Instruction::Expose(node) => v.visit_expose_instruction_mut(node),
+        Instruction::From(node) => v.visit_from_instruction_mut(node),
+        Instruction::Healthcheck(node) => v.visit_healthcheck_instruction_mut(node),
+        Instruction::Label(node) => v.visit_label_instruction_mut(node),
+        Instruction::Maintainer(node) => v.visit_maintainer_instruction_mut(node),
+        Instruction::Onbuild(node) => v.visit_onbuild_instruction_mut(node),
+        Instruction::Run(node) => v.visit_run_instruction_mut(node),
+        Instruction::Shell(node) => v.visit_shell_instruction_mut(node),
+        Instruction::Stopsignal(node) => v.visit_stopsignal_instruction_mut(node),
+        Instruction::User(node) => v.visit_user_instruction_mut(node),
+        Instruction::Volume(node) => v.visit_volume_instruction_mut(node),
+        Instruction::Workdir(node) => v.visit_workdir_instruction_mut(node),
+        Instruction::Unknown(node) => v.visit_unknown_instruction_mut(node),
+    }
+}
+pub fn visit_add_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut AddInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.add);
+    for flag in &mut node.options {
+        v.visit_flag_mut(flag);
+    }
+    for src in &mut node.src {
+        v.visit_source_mut(src);
+    }
+    v.visit_unescaped_string_mut(&mut node.dest);
+}
+pub fn visit_arg_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut ArgInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.arg);
+    v.visit_unescaped_string_mut(&mut node.arguments);
+}
+pub fn visit_cmd_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut CmdInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.cmd);
+    v.visit_command_mut(&mut node.arguments);
+}
+pub fn visit_copy_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut CopyInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.copy);
+    for flag in &mut node.options {
+        v.visit_flag_mut(flag);
+    }
+    for src in &mut node.src {
+        v.visit_source_mut(src);
+    }
+    v.visit_unescaped_string_mut(&mut node.dest);
+}
+pub fn visit_entrypoint_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut EntrypointInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.entrypoint);
+    v.visit_command_mut(&mut node.arguments);
+}
+pub fn visit_env_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut EnvInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.env);
+    v.visit_unescaped_string_mut(&mut node.arguments);
+}
+pub fn visit_expose_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut ExposeInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.expose);
+    for argument in &mut node.arguments {
+        v.visit_unescaped_string_mut(argument);
+    }
+}
+pub fn visit_from_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut FromInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.from);
+    for flag in &mut node.options {
+        v.visit_flag_mut(flag);
+    }
+    v.visit_unescaped_string_mut(&mut node.image);
+    if let Some((as_, name)) = &mut node.as_ {
+        v.visit_keyword_mut(as_);
+        v.visit_unescaped_string_mut(name);
+    }
+}
+pub fn visit_healthcheck_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut HealthcheckInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.healthcheck);
+    for flag in &mut node.options {
+        v.visit_flag_mut(flag);
+    }
+    v.visit_healthcheck_arguments_mut(&mut node.arguments);
+}
+pub fn visit_healthcheck_arguments_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut HealthcheckArguments<'a>,
+) {
+    match node {
+        HealthcheckArguments::Cmd { cmd, arguments } => {
+            v.visit_keyword_mut(cmd);
+            v.visit_command_mut(arguments);
+        }
+        HealthcheckArguments::None { none } => v.visit_keyword_mut(none),
+    }
+}
+pub fn visit_label_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut LabelInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.label);
+    v.visit_unescaped_string_mut(&mut node.arguments);
+}
+pub fn visit_maintainer_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut MaintainerInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.maintainer);
+    v.visit_unescaped_string_mut(&mut node.name);
+}
+pub fn visit_onbuild_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut OnbuildInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.onbuild);
+    v.visit_instruction_mut(&mut node.instruction);
+}
+pub fn visit_run_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut RunInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.run);
+    for flag in &mut node.options {
+        v.visit_flag_mut(flag);
+    }
+    v.visit_command_mut(&mut node.arguments);
+    for here_doc in &mut node.here_docs {
+        v.visit_here_doc_mut(here_doc);
+    }
+}
+pub fn visit_shell_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut ShellInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.shell);
+    for argument in &mut node.arguments {
+        v.visit_unescaped_string_mut(argument);
+    }
+}
+pub fn visit_stopsignal_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut StopsignalInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.stopsignal);
+    v.visit_unescaped_string_mut(&mut node.arguments);
+}
+pub fn visit_user_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut UserInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.user);
+    v.visit_unescaped_string_mut(&mut node.arguments);
+}
+pub fn visit_volume_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut VolumeInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.volume);
+    v.visit_json_or_string_array_mut(&mut node.arguments);
+}
+pub fn visit_workdir_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut WorkdirInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.workdir);
+    v.visit_unescaped_string_mut(&mut node.arguments);
+}
+pub fn visit_unknown_instruction_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut UnknownInstruction<'a>,
+) {
+    v.visit_keyword_mut(&mut node.keyword);
+    v.visit_spanned_str_mut(&mut node.arguments);
+    for here_doc in &mut node.here_docs {
+        v.visit_here_doc_mut(here_doc);
+    }
+}
+pub fn visit_keyword_mut<'a, V: VisitMut<'a> + ?Sized>(_v: &mut V, _node: &mut Keyword) {}
+pub fn visit_flag_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, node: &mut Flag<'a>) {
+    v.visit_unescaped_string_mut(&mut node.name);
+    if let Some(value) = &mut node.value {
+        v.visit_unescaped_string_mut(value);
+    }
+}
+pub fn visit_source_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, node: &mut Source<'a>) {
+    match node {
+        Source::Path(node) => v.visit_unescaped_string_mut(node),
+        Source::HereDoc(node) => v.visit_here_doc_mut(node),
+    }
+}
+pub fn visit_command_mut<'a, V: VisitMut<'a> + ?Sized>(v: &mut V, node: &mut Command<'a>) {
+    match node {
+        Command::Exec(array) => {
+            for argument in &mut array.value {
+                v.visit_unescaped_string_mut(argument);
+            }
+        }
+        Command::Shell(node) => v.visit_spanned_str_mut(node),
+    }
+}
+pub fn visit_json_or_string_array_mut<'a, V: VisitMut<'a> + ?Sized>(
+    v: &mut V,
+    node: &mut JsonOrStringArray<'a, 1>,
+) {
+    let arguments = match node {
+        JsonOrStringArray::Json(array) => &mut array.value,
+        JsonOrStringArray::String(arguments) => arguments,
+    };
+    for argument in arguments {
+        v.visit_unescaped_string_mut(argument);
+    }
+}
+pub fn visit_here_doc_mut<'a, V: VisitMut<'a> + ?Sized>(_v: &mut V, _node: &mut HereDoc<'a>) {}
+pub fn visit_unescaped_string_mut<'a, V: VisitMut<'a> + ?Sized>(
+    _v: &mut V,
+    _node: &mut UnescapedString<'a>,
+) {
+}
+pub fn visit_spanned_str_mut<'a, V: VisitMut<'a> + ?Sized>(
+    _v: &mut V,
+    _node: &mut Spanned<&'a str>,
+) {
+}
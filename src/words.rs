@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+// Helpers to split raw arguments of instructions and expand variables in
+// them, like BuildKit does when dispatching instructions.
+
+use alloc::{string::String, vec, vec::Vec};
+
+use crate::{
+    Flag, Span, UnescapedString,
+    error::Result,
+    expand::{Env, Expander},
+};
+
+/// A whitespace-separated word in raw arguments.
+#[derive(Debug)]
+pub(crate) struct Word<'s> {
+    /// The raw text of this word, including quotes and escapes.
+    pub(crate) raw: &'s str,
+    /// The span of this word in the text, or `None` if the arguments contain
+    /// line continuations, in which case offsets in the arguments don't
+    /// correspond to offsets in the text.
+    pub(crate) span: Option<Span>,
+}
+
+/// Splits `s` into words, like BuildKit's `parseWords`.
+///
+/// Quotes and escapes are kept in words.
+pub(crate) fn split_words<'s>(s: &'s UnescapedString<'_>, escape: u8) -> Vec<Word<'s>> {
+    let value: &str = &s.value;
+    let bytes = value.as_bytes();
+    let mapped = s.span.len() == value.len();
+    let mut words = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        while i < bytes.len() && matches!(bytes[i], b' ' | b'\t' | b'\r' | b'\n') {
+            i += 1;
+        }
+        if i == bytes.len() {
+            break;
+        }
+        let start = i;
+        let mut quote = None;
+        while i < bytes.len() {
+            let b = bytes[i];
+            match quote {
+                None if matches!(b, b' ' | b'\t' | b'\r' | b'\n') => break,
+                None if b == b'"' || b == b'\'' => quote = Some(b),
+                Some(q) if b == q => quote = None,
+                _ if b == escape && quote != Some(b'\'') => i += 1,
+                _ => {}
+            }
+            i += 1;
+        }
+        let end = i.min(bytes.len());
+        words.push(Word {
+            raw: &value[start..end],
+            span: if mapped { Some(s.span.start + start..s.span.start + end) } else { None },
+        });
+    }
+    words
+}
+
+/// A field of the value of `--mount` flag, such as `from=build`.
+#[derive(Debug)]
+pub(crate) struct MountField {
+    /// The key in lowercase.
+    pub(crate) key: String,
+    /// The value with quotes removed. Variables are not expanded.
+    pub(crate) value: String,
+    /// The span of the value (inside quotes if quoted) in the text, or `None`
+    /// if the field cannot be mapped to the text, e.g., the flag value
+    /// contains line continuations.
+    pub(crate) span: Option<Span>,
+}
+
+/// Splits the value of `--mount` flag into fields, like BuildKit.
+///
+/// BuildKit parses the value as CSV, so a comma in a quoted part (e.g.,
+/// `"source=a,b"` or `source="a,b"`) doesn't split fields, and `""` in a
+/// quoted part is a literal `"`. Returns an empty vector if `flag` is not
+/// `--mount`.
+pub(crate) fn mount_fields(flag: &Flag<'_>) -> Vec<MountField> {
+    if flag.name.value != "mount" {
+        return vec![];
+    }
+    let Some(value) = &flag.value else { return vec![] };
+    let mapped = value.span.len() == value.value.len();
+    let s: &str = &value.value;
+    let mut fields = vec![];
+    let mut start = 0;
+    while start < s.len() {
+        let mut quoted = false;
+        let len = s[start..]
+            .bytes()
+            .position(|b| {
+                if b == b'"' {
+                    quoted = !quoted;
+                }
+                b == b',' && !quoted
+            })
+            .unwrap_or(s.len() - start);
+        let raw = &s[start..start + len];
+        let field = unquote_csv(raw);
+        let (key, v) = field.split_once('=').unwrap_or((&field, ""));
+        let span = match raw.split_once('=') {
+            Some((key, v)) if mapped && !key.contains('"') => {
+                let v_start = value.span.start + start + key.len() + 1;
+                if !v.contains('"') {
+                    Some(v_start..v_start + v.len())
+                } else if v.len() >= 2
+                    && v.starts_with('"')
+                    && v.ends_with('"')
+                    && !v[1..v.len() - 1].contains('"')
+                {
+                    Some(v_start + 1..v_start + v.len() - 1)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if !raw.is_empty() {
+            fields.push(MountField { key: key.to_ascii_lowercase(), value: String::from(v), span });
+        }
+        start += len + 1;
+    }
+    fields
+}
+
+/// Removes quotes from a CSV field.
+fn unquote_csv(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut quoted = false;
+    let mut chars = s.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                out.push('"');
+            }
+            '"' => quoted = !quoted,
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+/// Returns the field of `--mount` flag with one of `keys`. If there are
+/// multiple such fields, the last one is used, like BuildKit.
+pub(crate) fn mount_field(flag: &Flag<'_>, keys: &[&str]) -> Option<MountField> {
+    mount_fields(flag).into_iter().rev().find(|f| keys.contains(&&*f.key))
+}
+
+/// Returns the `from` field of `--mount` flag.
+pub(crate) fn mount_from(flag: &Flag<'_>) -> Option<MountField> {
+    mount_field(flag, &["from"])
+}
+
+/// Fields of `--mount` flag used to find inputs of `RUN`, with variables expanded.
+#[derive(Debug)]
+pub(crate) struct Mount {
+    /// The value of `type` field. Default: `bind`
+    pub(crate) ty: String,
+    /// The value of `from` field, or `None` if it is not set or empty.
+    pub(crate) from: Option<String>,
+    /// The value of `source` field, or `None` if it is not set.
+    pub(crate) source: Option<String>,
+    /// The value of `target` field, or `None` if it is not set.
+    pub(crate) target: Option<String>,
+}
+
+impl Mount {
+    /// Returns `true` if this is a bind mount of the build context.
+    pub(crate) fn is_context_bind(&self) -> bool {
+        self.ty == "bind" && self.from.is_none()
+    }
+}
+
+/// Parses `--mount` flag like BuildKit: the value is split into fields as
+/// CSV, and then variables in the values of fields are expanded with `env`.
+///
+/// Returns `None` if `flag` is not `--mount`.
+pub(crate) fn expand_mount<E: ?Sized + Env>(
+    flag: &Flag<'_>,
+    expander: Expander,
+    env: &E,
+) -> Result<Option<Mount>> {
+    if flag.name.value != "mount" {
+        return Ok(None);
+    }
+    let mut mount = Mount { ty: String::from("bind"), from: None, source: None, target: None };
+    for field in mount_fields(flag) {
+        let slot = match &*field.key {
+            "type" => &mut mount.ty,
+            "from" => mount.from.get_or_insert_with(String::new),
+            "source" | "src" => mount.source.get_or_insert_with(String::new),
+            "target" | "dst" | "destination" => mount.target.get_or_insert_with(String::new),
+            _ => continue,
+        };
+        *slot = expander.expand(&field.value, env)?.value;
+    }
+    mount.from = mount.from.filter(|from| !from.is_empty());
+    Ok(Some(mount))
+}
+
+/// A key and optional raw value in arguments of `ARG`, `ENV`, or `LABEL`.
+#[derive(Debug)]
+pub(crate) struct KeyValue<'s> {
+    pub(crate) key: &'s str,
+    /// The raw value, including quotes and escapes.
+    pub(crate) value: Option<&'s str>,
+    /// The span of `key=value` in the text, or the span of the whole
+    /// arguments if the arguments contain line continuations.
+    pub(crate) span: Span,
+}
+
+/// Splits arguments of `ARG`, `ENV`, or `LABEL` into keys and values.
+///
+/// If `legacy` is `true` and the first word doesn't contain `=`, the
+/// arguments are parsed as legacy `<key> <value>` form, in which the value is
+/// the rest of the arguments.
+pub(crate) fn key_values<'s>(
+    s: &'s UnescapedString<'_>,
+    escape: u8,
+    legacy: bool,
+) -> Vec<KeyValue<'s>> {
+    let words = split_words(s, escape);
+    if legacy && words.first().is_some_and(|w| !w.raw.contains('=')) {
+        let key = words[0].raw;
+        let rest = s.value[key.len()..].trim_start_matches([' ', '\t', '\r', '\n']);
+        return vec![KeyValue {
+            key,
+            value: Some(rest.trim_end_matches([' ', '\t', '\r', '\n'])),
+            span: s.span.clone(),
+        }];
+    }
+    words
+        .into_iter()
+        .map(|word| {
+            let (key, value) = match word.raw.split_once('=') {
+                Some((key, value)) => (key, Some(value)),
+                None => (word.raw, None),
+            };
+            KeyValue { key, value, span: word.span.unwrap_or_else(|| s.span.clone()) }
+        })
+        .collect()
+}
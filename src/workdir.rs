@@ -0,0 +1,208 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use alloc::{format, string::String, vec, vec::Vec};
+
+use crate::{Dockerfile, Instruction, error, error::Result, expand::Env};
+
+/// The effective working directory at each instruction of a dockerfile.
+///
+/// See [`Dockerfile::working_dirs`].
+#[derive(Debug, Clone)]
+pub struct WorkingDirs {
+    instructions: Vec<Option<String>>,
+    stage_ends: Vec<String>,
+}
+
+impl WorkingDirs {
+    /// Returns the working directory at the instruction at `index` in
+    /// [`Dockerfile::instructions`], i.e., before the instruction is executed.
+    ///
+    /// Returns `None` for instructions before the first `FROM`.
+    #[must_use]
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.instructions.get(index)?.as_deref()
+    }
+    /// Returns the working directory at the end of the stage at `index`.
+    #[must_use]
+    pub fn stage_end(&self, index: usize) -> Option<&str> {
+        self.stage_ends.get(index).map(String::as_str)
+    }
+}
+
+/// The destination of a `COPY` or `ADD` instruction, resolved to an absolute path.
+///
+/// See [`Dockerfile::copy_destinations`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Destination {
+    /// The index of the instruction in [`Dockerfile::instructions`].
+    pub instruction: usize,
+    /// The absolute path of the destination.
+    ///
+    /// Like BuildKit, a trailing slash (or `/.`) of the destination is
+    /// preserved, since it means that sources are copied into the directory.
+    pub path: String,
+    /// Whether sources are copied into the destination as a directory, i.e.,
+    /// the destination ends with `/` or `/.`, or is `.`.
+    pub is_dir: bool,
+}
+
+impl Destination {
+    /// Returns the absolute path where a source file named `src` is copied to.
+    ///
+    /// If the destination is a directory, this is the file name of `src` in
+    /// the directory, otherwise the destination itself.
+    #[must_use]
+    pub fn target(&self, src: &str) -> String {
+        if !self.is_dir {
+            return self.path.clone();
+        }
+        let src = src.trim_end_matches('/');
+        let name = src.rsplit('/').next().unwrap_or(src);
+        join_path(&self.path, name)
+    }
+}
+
+impl Dockerfile<'_> {
+    /// Returns the effective working directory at each instruction.
+    ///
+    /// `build_args` are values passed with `--build-arg`.
+    ///
+    /// A relative `WORKDIR` is resolved against the previous working directory,
+    /// and a stage based on another stage starts from the working directory at
+    /// the end of that stage. Stages based on external images (or `scratch`)
+    /// start from `/`, since the configs of external images are unknown.
+    ///
+    /// Variables in `WORKDIR` are expanded with the variables visible at the
+    /// instruction (see [`Dockerfile::scopes`]). References to variables that
+    /// are not set (e.g., `ENV` of external images) are left as is.
+    ///
+    /// ```
+    /// use parse_dockerfile::parse;
+    ///
+    /// let text = "FROM alpine AS base
+    /// ENV APP=/srv/app
+    /// WORKDIR $APP
+    ///
+    /// FROM base
+    /// WORKDIR bin
+    /// COPY server ./
+    /// COPY config.toml ../etc/app.toml
+    /// ";
+    /// let dockerfile = parse(text).unwrap();
+    /// let working_dirs = dockerfile.working_dirs(&[("", ""); 0]).unwrap();
+    /// assert_eq!(working_dirs.stage_end(1), Some("/srv/app/bin"));
+    ///
+    /// let destinations = dockerfile.copy_destinations(&[("", ""); 0]).unwrap();
+    /// assert_eq!(destinations[0].path, "/srv/app/bin/");
+    /// assert_eq!(destinations[0].target("server"), "/srv/app/bin/server");
+    /// assert_eq!(destinations[1].path, "/srv/app/etc/app.toml");
+    /// assert_eq!(destinations[1].target("config.toml"), "/srv/app/etc/app.toml");
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if expanding variables failed.
+    pub fn working_dirs<E: ?Sized + Env>(&self, build_args: &E) -> Result<WorkingDirs> {
+        let scopes = self.scopes(build_args)?;
+        let expander = self.expander().skip_unset(true);
+        let first_from = self.stages.first().map_or(self.instructions.len(), |s| s.start);
+        let mut working_dirs = WorkingDirs {
+            instructions: vec![None; first_from],
+            stage_ends: Vec::with_capacity(self.stages.len()),
+        };
+        working_dirs.instructions.reserve(self.instructions.len() - first_from);
+        for stage in self.stages() {
+            let start = self.stages[stage.index()].start;
+            let scope = scopes.at(start);
+            let image = expander.expand(&stage.from.image.value, scope)?.value;
+            let mut dir = match self.stage(&image).filter(|b| b.index() < stage.index()) {
+                Some(base) => working_dirs.stage_ends[base.index()].clone(),
+                None => String::from("/"),
+            };
+            working_dirs.instructions.push(Some(dir.clone()));
+            for (i, instruction) in stage.instructions.iter().enumerate() {
+                working_dirs.instructions.push(Some(dir.clone()));
+                if let Instruction::Workdir(workdir) = instruction {
+                    let scope = scopes.at(start + 1 + i);
+                    let path = expander.expand(&workdir.arguments.value, scope)?.value;
+                    dir = join_path(&dir, &path);
+                }
+            }
+            working_dirs.stage_ends.push(dir);
+        }
+        debug_assert_eq!(working_dirs.instructions.len(), self.instructions.len());
+        Ok(working_dirs)
+    }
+
+    /// Returns the destinations of `COPY` and `ADD` instructions (excluding
+    /// `ONBUILD` triggers), resolved to absolute paths.
+    ///
+    /// `build_args` are values passed with `--build-arg`.
+    ///
+    /// Like BuildKit, a relative destination is resolved against the working
+    /// directory (see [`Dockerfile::working_dirs`]), and a trailing slash is
+    /// preserved. Variables are expanded in the same way as `WORKDIR`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if expanding variables failed, or the destination of
+    /// an instruction with multiple sources is not a directory (i.e., does not
+    /// end with `/`), like BuildKit.
+    pub fn copy_destinations<E: ?Sized + Env>(&self, build_args: &E) -> Result<Vec<Destination>> {
+        let scopes = self.scopes(build_args)?;
+        let working_dirs = self.working_dirs(build_args)?;
+        let expander = self.expander().skip_unset(true);
+        let mut destinations = vec![];
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let (name, src, dest) = match instruction {
+                Instruction::Add(add) => ("ADD", &add.src, &add.dest),
+                Instruction::Copy(copy) => ("COPY", &copy.src, &copy.dest),
+                _ => continue,
+            };
+            // Instructions outside stages (see `ParseOptions::allow_no_stages`)
+            // have no working directory since they are never executed.
+            let Some(dir) = working_dirs.get(index) else { continue };
+            let dest = expander.expand(&dest.value, scopes.at(index))?.value;
+            let is_dir = dest == "." || dest.ends_with('/') || dest.ends_with("/.");
+            if src.len() > 1 && !is_dir {
+                return Err(error::without_location(
+                    format!(
+                        "When using {name} with more than one source file, the destination must be a directory and end with a /"
+                    )
+                    .into(),
+                ));
+            }
+            let mut path = join_path(dir, &dest);
+            if is_dir && path != "/" {
+                path.push('/');
+            }
+            destinations.push(Destination { instruction: index, path, is_dir });
+        }
+        Ok(destinations)
+    }
+}
+
+/// Resolves `path` against the working directory `base`, and normalizes it.
+pub(crate) fn join_path(base: &str, path: &str) -> String {
+    let mut components: Vec<&str> = vec![];
+    let joined = if path.starts_with('/') { [path, ""] } else { [base, path] };
+    for component in joined.iter().flat_map(|s| s.split('/')) {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    let mut out = String::new();
+    for component in components {
+        out.push('/');
+        out.push_str(component);
+    }
+    if out.is_empty() {
+        out.push('/');
+    }
+    out
+}
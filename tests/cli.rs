@@ -3,7 +3,7 @@
 #![cfg(feature = "default")]
 #![cfg(not(miri))] // Miri doesn't support std::process::Command: https://github.com/rust-lang/miri/issues/3374
 
-use std::{ffi::OsStr, path::Path, process::Command};
+use std::{ffi::OsStr, fs, path::Path, process::Command};
 
 use test_helper::cli::{ChildExt as _, CommandExt as _};
 
@@ -34,6 +34,224 @@ fn failure() {
         );
 }
 
+#[test]
+fn path_named_like_subcommand() {
+    // A path that is also the name of a subcommand needs a directory prefix.
+    let dir = &Path::new(env!("CARGO_TARGET_TMPDIR")).join("subcommand-names");
+    fs::create_dir_all(dir).unwrap();
+    for name in ["fmt", "set-image", "prune", "graph", "config", "inputs", "plan"] {
+        fs::write(dir.join(name), "FROM alpine\n").unwrap();
+        parse_dockerfile([format!("./{name}")])
+            .current_dir(dir)
+            .assert_success()
+            .stdout_contains(r#""value":"alpine""#);
+        parse_dockerfile([name])
+            .current_dir(dir)
+            .assert_failure()
+            .stderr_contains("no dockerfile path specified");
+    }
+}
+
+#[test]
+fn fmt() {
+    parse_dockerfile(["fmt", "-"])
+        .spawn_with_stdin("from  alpine as build\nrun a&&b\n\n\nFROM build\n")
+        .assert_success()
+        .stdout_eq("FROM alpine AS build\nRUN a \\\n    && b\n\nFROM build\n");
+    parse_dockerfile(["fmt", "--indent-width", "2", "--no-split-commands", "-"])
+        .spawn_with_stdin("FROM alpine\nRUN a&&b \\\nc\n")
+        .assert_success()
+        .stdout_eq("FROM alpine\nRUN a&&b \\\n  c\n");
+
+    parse_dockerfile(["fmt", "--check", "-"]).spawn_with_stdin("FROM alpine\n").assert_success();
+    parse_dockerfile(["fmt", "--check", "-"])
+        .spawn_with_stdin("from alpine\n")
+        .assert_failure()
+        .stderr_contains("dockerfile (standard input) is not formatted");
+
+    let path = &Path::new(env!("CARGO_TARGET_TMPDIR")).join("fmt.Dockerfile");
+    fs::write(path, "from alpine\n").unwrap();
+    parse_dockerfile(["fmt", "--write"]).arg(path).assert_success();
+    assert_eq!(fs::read_to_string(path).unwrap(), "FROM alpine\n");
+    parse_dockerfile(["fmt", "--check"]).arg(path).assert_success();
+
+    parse_dockerfile(["fmt", "--check", "--write", "-"])
+        .assert_failure()
+        .stderr_contains("--check and --write may not be used together");
+    parse_dockerfile(["fmt", "--write", "-"])
+        .assert_failure()
+        .stderr_contains("--write may not be used with standard input");
+    parse_dockerfile(["fmt", "--indent-width", "a", "-"])
+        .assert_failure()
+        .stderr_contains("invalid value \"a\" for '--indent-width <WIDTH>'");
+    parse_dockerfile(["fmt"]).assert_failure().stderr_contains("no dockerfile path specified");
+}
+
+#[test]
+fn set_image() {
+    parse_dockerfile(["set-image", "--image", "node:18=node:20", "--image", "nginx:1.25=nginx:1.27", "-"])
+        .spawn_with_stdin(
+            "ARG NODE=node:18\nFROM ${NODE} AS build\nFROM nginx:1.25\nCOPY --from=build /a /b\nFROM $BASE\n",
+        )
+        .assert_success()
+        .stdout_eq(
+            "ARG NODE=node:20\nFROM ${NODE} AS build\nFROM nginx:1.27\nCOPY --from=build /a /b\nFROM $BASE\n",
+        )
+        .stderr_contains(
+            "dockerfile (standard input):1:10: `node:18` -> `node:20`
+dockerfile (standard input):3:6: `nginx:1.25` -> `nginx:1.27`
+warning: dockerfile (standard input):5:6: `$BASE` contains variables that cannot be resolved statically",
+        );
+
+    let path = &Path::new(env!("CARGO_TARGET_TMPDIR")).join("set-image.Dockerfile");
+    fs::write(path, "FROM  node:18  AS build\n").unwrap();
+    parse_dockerfile(["set-image", "--write", "--image", "node:18=node:20@sha256:abc"])
+        .arg(path)
+        .assert_success();
+    assert_eq!(fs::read_to_string(path).unwrap(), "FROM  node:20@sha256:abc  AS build\n");
+
+    parse_dockerfile(["set-image", "-"])
+        .assert_failure()
+        .stderr_contains("no image specified; use --image <OLD>=<NEW>");
+    parse_dockerfile(["set-image", "--image", "node", "-"])
+        .assert_failure()
+        .stderr_contains("invalid value \"node\" for '--image <OLD>=<NEW>'");
+    parse_dockerfile(["set-image", "--write", "--image", "a=b", "-"])
+        .assert_failure()
+        .stderr_contains("--write may not be used with standard input");
+}
+
+#[test]
+fn prune() {
+    parse_dockerfile(["prune", "--target", "b", "-"])
+        .spawn_with_stdin(
+            "FROM alpine AS a\nFROM alpine AS b\nCOPY --from=c /a /b\nFROM alpine AS c\n",
+        )
+        .assert_success()
+        .stdout_eq("FROM alpine AS b\nCOPY --from=c /a /b\n\nFROM alpine AS c\n");
+    parse_dockerfile(["prune", "--target", "d", "-"])
+        .spawn_with_stdin("FROM alpine AS a\n")
+        .assert_failure()
+        .stderr_contains("stage \"d\" not found");
+    parse_dockerfile(["prune", "-"])
+        .assert_failure()
+        .stderr_contains("no target specified; use --target <NAME>");
+}
+
+#[test]
+fn graph() {
+    let text = "FROM alpine AS a\nCOPY x /x\nFROM golang AS b\nFROM a\nCOPY --from=a /x /y/\nRUN --mount=from=b,target=/b true\n";
+    parse_dockerfile(["graph", "-"]).spawn_with_stdin(text).assert_success().stdout_eq(
+        r#"digraph {
+    stage_0 [label="a", shape=box];
+    stage_1 [label="b", shape=box];
+    stage_2 [label="stage 2", shape=box];
+    image_0 [label="alpine", shape=ellipse];
+    image_1 [label="golang", shape=ellipse];
+    context [label="build context", shape=folder];
+    image_0 -> stage_0 [style=bold];
+    context -> stage_0 [label="x"];
+    image_1 -> stage_1 [style=bold];
+    stage_0 -> stage_2 [style=bold];
+    stage_0 -> stage_2 [label="/x"];
+    stage_1 -> stage_2 [style=dashed, label="mount"];
+}
+"#,
+    );
+    parse_dockerfile(["graph", "--format", "mermaid", "--target", "a", "-"])
+        .spawn_with_stdin(text)
+        .assert_success()
+        .stdout_eq(
+            r#"flowchart TD
+    stage_0["a"]
+    stage_1["b"]
+    stage_2["stage 2"]
+    image_0(["alpine"])
+    image_1(["golang"])
+    context[("build context")]
+    image_0 ==> stage_0
+    context -->|"x"| stage_0
+    image_1 ==> stage_1
+    stage_0 ==> stage_2
+    stage_0 -->|"/x"| stage_2
+    stage_1 -.->|"mount"| stage_2
+    classDef unused color:gray,stroke:gray
+    class stage_1,stage_2,image_1 unused
+    linkStyle 2,3,4,5 stroke:gray
+    classDef target stroke-width:3px
+    class stage_0 target
+"#,
+        );
+    parse_dockerfile(["graph", "--build-arg", "BASE=a", "-"])
+        .spawn_with_stdin("ARG BASE=alpine\nFROM alpine AS a\nFROM $BASE\n")
+        .assert_success()
+        .stdout_contains("stage_0 -> stage_1 [style=bold];");
+    parse_dockerfile(["graph", "--target", "c", "-"])
+        .spawn_with_stdin("FROM alpine AS a\n")
+        .assert_failure()
+        .stderr_contains("stage \"c\" not found");
+    parse_dockerfile(["graph", "--format", "svg", "-"])
+        .assert_failure()
+        .stderr_contains("invalid value \"svg\" for '--format <FORMAT>'");
+    parse_dockerfile(["graph", "-"])
+        .spawn_with_stdin("FROM alpine AS a\nCOPY --from=a /x /x\n")
+        .assert_failure()
+        .stderr_contains("circular dependency detected on stage \"a\"");
+}
+
+#[test]
+fn config() {
+    let text = "FROM alpine AS base\nENV A=1\nWORKDIR /app\nCMD [\"serve\"]\nFROM base\nARG PORT\nEXPOSE $PORT\nENTRYPOINT [\"/app/bin\"]\n";
+    parse_dockerfile(["config", "--build-arg", "PORT=8080", "-"])
+        .spawn_with_stdin(text)
+        .assert_success()
+        .stdout_eq(
+            r#"{"ExposedPorts":{"8080/tcp":{}},"Env":["A=1"],"Entrypoint":["/app/bin"],"WorkingDir":"/app"}"#,
+        );
+    parse_dockerfile(["config", "--target", "base", "-"])
+        .spawn_with_stdin(text)
+        .assert_success()
+        .stdout_eq(r#"{"Env":["A=1"],"Cmd":["serve"],"WorkingDir":"/app"}"#);
+    parse_dockerfile(["config", "--target", "c", "-"])
+        .spawn_with_stdin(text)
+        .assert_failure()
+        .stderr_contains("stage \"c\" not found");
+    parse_dockerfile(["config", "--build-arg", "PORT", "-"])
+        .assert_failure()
+        .stderr_contains("invalid value \"PORT\" for '--build-arg <NAME>=<VALUE>'");
+}
+
+#[test]
+fn inputs() {
+    let text =
+        "FROM rust AS build\nCOPY --exclude=*.md src/ ./\nFROM alpine\nARG DIR\nCOPY $DIR /a/\n";
+    parse_dockerfile(["inputs", "--target", "build", "-"]).spawn_with_stdin(text).assert_success().stdout_eq(
+        r#"[{"stage":0,"instruction":1,"kind":"copy","source":"src/","destination":"./","excludes":["*.md"],"parents":false,"has_variables":false,"has_globs":false}]"#,
+    );
+    parse_dockerfile(["inputs", "--build-arg", "DIR=lib", "-"]).spawn_with_stdin(text).assert_success().stdout_eq(
+        r#"[{"stage":0,"instruction":1,"kind":"copy","source":"src/","destination":"./","excludes":["*.md"],"parents":false,"has_variables":false,"has_globs":false},{"stage":1,"instruction":4,"kind":"copy","source":"lib","destination":"/a/","excludes":[],"parents":false,"has_variables":true,"has_globs":false}]"#,
+    );
+    parse_dockerfile(["inputs", "--target", "c", "-"])
+        .spawn_with_stdin(text)
+        .assert_failure()
+        .stderr_contains("stage \"c\" not found");
+}
+
+#[test]
+fn plan() {
+    let text = "ARG V=1\nFROM alpine:$V AS a\nCOPY x /x\nFROM a\nCMD [\"b\"]\n";
+    parse_dockerfile(["plan", "--build-arg", "V=2", "--target", "a", "-"])
+        .spawn_with_stdin(text)
+        .assert_success()
+        .stdout_eq(
+            r#"[{"stage":0,"instruction":1,"kind":"FROM","layer":false,"text":"FROM alpine:2 AS a","context_files":[],"from_stages":[],"from_images":["alpine:2"],"mounts":[],"build_args":{"V":"2"}},{"stage":0,"instruction":2,"kind":"COPY","layer":true,"text":"COPY x /x","context_files":["x"],"from_stages":[],"from_images":[],"mounts":[],"build_args":{}}]"#,
+        );
+    parse_dockerfile(["plan", "--target", "c", "-"])
+        .spawn_with_stdin(text)
+        .assert_failure()
+        .stderr_contains("stage \"c\" not found");
+}
+
 #[test]
 fn help() {
     let short = parse_dockerfile(["-h"]).assert_success();
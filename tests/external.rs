@@ -94,12 +94,41 @@ fn test() {
                 if let Some(expected_err) = expected_err {
                     let err = &*res.unwrap_err().to_string();
                     assert!(err.contains(expected_err), "expected '{expected_err}' actual '{err}'");
+                    if let Some(name) = expected_err.strip_prefix("unknown instruction ") {
+                        let options =
+                            parse_dockerfile::ParseOptions::new().unknown_instructions(true);
+                        let dockerfile = parse_dockerfile::parse_with(text, options).unwrap();
+                        assert!(dockerfile.instructions.iter().any(|i| matches!(
+                            i,
+                            parse_dockerfile::Instruction::Unknown(i) if *name == format!("'{}'", i.name)
+                        )));
+                    }
                     continue;
                 }
                 let dockerfile = res.unwrap();
                 for r in parse_dockerfile::parse_iter(text).unwrap() {
                     r.unwrap();
                 }
+                let tree = parse_dockerfile::SyntaxTree::parse(text).unwrap();
+                assert_eq!(
+                    tree.root()
+                        .tokens()
+                        .map(parse_dockerfile::SyntaxToken::text)
+                        .collect::<String>(),
+                    text
+                );
+                let style = &parse_dockerfile::FormatStyle::new();
+                let formatted = tree.format(style);
+                let reformatted = parse_dockerfile::SyntaxTree::parse(&formatted).unwrap();
+                assert_eq!(reformatted.format(style), formatted);
+                assert_eq!(
+                    reformatted.dockerfile().instructions.len(),
+                    dockerfile.instructions.len(),
+                    "{formatted}"
+                );
+                let printed = dockerfile.to_string();
+                let reparsed = parse_dockerfile::parse(&printed).unwrap();
+                assert_eq!(without_spans(&reparsed), without_spans(&dockerfile), "{printed}");
                 let dump = serde_json::to_vec_pretty(&dockerfile).unwrap();
                 let mut dump_path = external_dir.join("dump").join(base).join(&rel);
                 let file_name = dump_path.file_name().unwrap().to_str().unwrap();
@@ -139,3 +168,22 @@ fn test() {
         }
     }
 }
+
+/// Removes spans from the JSON representation of the dockerfile.
+fn without_spans(dockerfile: &parse_dockerfile::Dockerfile<'_>) -> serde_json::Value {
+    fn remove(v: &mut serde_json::Value) {
+        match v {
+            serde_json::Value::Object(map) => {
+                map.remove("span");
+                map.remove("start");
+                map.remove("flag_start");
+                map.values_mut().for_each(remove);
+            }
+            serde_json::Value::Array(array) => array.iter_mut().for_each(remove),
+            _ => {}
+        }
+    }
+    let mut v = serde_json::to_value(dockerfile).unwrap();
+    remove(&mut v);
+    v
+}
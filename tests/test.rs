@@ -376,3 +376,2115 @@ fn dump() {
         assert_diff(dump_path, dump);
     }
 }
+
+#[test]
+fn instruction() {
+    let text = "\u{FEFF}FROM --platform=$BUILDPLATFORM alpine AS build
+RUN --mount=type=cache,target=/root/.cache \\
+    echo a \\
+    && echo b
+RUN <<EOF
+echo c
+EOF
+COPY <<-EOF2 /dest
+	d
+	EOF2
+ONBUILD COPY --from=build /src /dest
+SHELL [ \"/bin/sh\", \"-c\" ]
+";
+    let dockerfile = parse(text).unwrap();
+    let tests: &[(InstructionKind, &str, &[&str], &str)] = &[
+        (
+            InstructionKind::From,
+            "FROM",
+            &["platform"],
+            "FROM --platform=$BUILDPLATFORM alpine AS build",
+        ),
+        (
+            InstructionKind::Run,
+            "RUN",
+            &["mount"],
+            "RUN --mount=type=cache,target=/root/.cache \\\n    echo a \\\n    && echo b",
+        ),
+        (InstructionKind::Run, "RUN", &[], "RUN <<EOF\necho c\nEOF"),
+        (InstructionKind::Copy, "COPY", &[], "COPY <<-EOF2 /dest\n\td\n\tEOF2"),
+        (InstructionKind::Onbuild, "ONBUILD", &["from"], "ONBUILD COPY --from=build /src /dest"),
+        (InstructionKind::Shell, "SHELL", &[], "SHELL [ \"/bin/sh\", \"-c\" ]"),
+    ];
+    assert_eq!(dockerfile.instructions.len(), tests.len());
+    for (instruction, &(kind, keyword, options, raw)) in dockerfile.instructions.iter().zip(tests) {
+        assert_eq!(instruction.kind(), kind);
+        assert_eq!(kind.to_string(), keyword);
+        assert_eq!(keyword.parse::<InstructionKind>().unwrap(), kind);
+        assert_eq!(keyword.to_ascii_lowercase().parse::<InstructionKind>().unwrap(), kind);
+        assert_eq!(&text[3..][instruction.keyword().span.clone()], keyword);
+        let names: Vec<_> = instruction.options().iter().map(|f| &*f.name.value).collect();
+        assert_eq!(names, options);
+        assert_eq!(instruction.raw_text(text), raw);
+    }
+    let Instruction::Onbuild(onbuild) = &dockerfile.instructions[4] else { panic!() };
+    assert_eq!(onbuild.instruction.kind(), InstructionKind::Copy);
+    assert_eq!(&text[3..][onbuild.instruction.keyword().span.clone()], "COPY");
+    assert_eq!(
+        "INVALID".parse::<InstructionKind>().unwrap_err().to_string(),
+        "unknown instruction"
+    );
+    assert_eq!(InstructionKind::Unknown.to_string(), "UNKNOWN");
+    assert!("UNKNOWN".parse::<InstructionKind>().is_err());
+}
+
+#[test]
+fn visit() {
+    use parse_dockerfile::{visit::Visit, visit_mut::VisitMut};
+
+    #[derive(Default)]
+    struct Collect(Vec<String>);
+    impl<'ast, 'a> Visit<'ast, 'a> for Collect {
+        fn visit_unescaped_string(&mut self, node: &'ast UnescapedString<'a>) {
+            self.0.push(node.value.to_string());
+        }
+        fn visit_here_doc(&mut self, node: &'ast HereDoc<'a>) {
+            self.0.push(format!("<<{}", node.value));
+        }
+        fn visit_spanned_str(&mut self, node: &'ast Spanned<&'a str>) {
+            self.0.push(format!("${}", node.value));
+        }
+    }
+    struct Prefix;
+    impl<'a> VisitMut<'a> for Prefix {
+        fn visit_unescaped_string_mut(&mut self, node: &mut UnescapedString<'_>) {
+            node.value = format!("_{}", node.value).into();
+        }
+        fn visit_spanned_str_mut(&mut self, node: &mut Spanned<&'a str>) {
+            node.value = node.value.trim_start_matches("echo ");
+        }
+    }
+
+    let text = "FROM --platform=linux/amd64 alpine AS base
+HEALTHCHECK --interval=5s CMD [\"true\"]
+VOLUME [\"/a\", \"/b\"]
+ONBUILD COPY --from=base <<EOF /dest
+a
+EOF
+";
+    let mut dockerfile = parse(text).unwrap();
+    let mut collect = Collect::default();
+    collect.visit_dockerfile(&dockerfile);
+    assert_eq!(collect.0, [
+        "platform",
+        "linux/amd64",
+        "alpine",
+        "base",
+        "interval",
+        "5s",
+        "true",
+        "/a",
+        "/b",
+        "from",
+        "base",
+        "<<a\n",
+        "/dest"
+    ]);
+    Prefix.visit_dockerfile_mut(&mut dockerfile);
+    let mut collect = Collect::default();
+    collect.visit_dockerfile(&dockerfile);
+    assert_eq!(collect.0[..4], ["_platform", "_linux/amd64", "_alpine", "_base"]);
+    assert_eq!(collect.0.last().unwrap(), "_/dest");
+
+    let text = "FROM scratch
+RUN echo a
+CMD [\"echo\", \"b\"]
+FOO echo c
+";
+    let mut dockerfile = parse_with(text, ParseOptions::new().unknown_instructions(true)).unwrap();
+    let mut collect = Collect::default();
+    collect.visit_dockerfile(&dockerfile);
+    assert_eq!(collect.0, ["scratch", "$echo a", "echo", "b", "$echo c"]);
+    Prefix.visit_dockerfile_mut(&mut dockerfile);
+    let mut collect = Collect::default();
+    collect.visit_dockerfile(&dockerfile);
+    assert_eq!(collect.0, ["_scratch", "$a", "_echo", "_b", "$c"]);
+}
+
+#[test]
+fn stage() {
+    let text = "ARG BASE=alpine
+FROM scratch AS Base
+COPY a /
+
+FROM --platform=$BUILDPLATFORM golang AS builder
+RUN \\
+  go build
+
+FROM BASE
+FROM builder AS final
+FROM final
+";
+    let dockerfile = parse(text).unwrap();
+    let stages: Vec<_> = dockerfile.stages().collect();
+    assert_eq!(stages.len(), 5);
+    assert_eq!(stages.iter().map(Stage::index).collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+    assert_eq!(stages[0].name().unwrap().value, "Base");
+    assert_eq!(&text[stages[0].span()], "FROM scratch AS Base\nCOPY a /");
+    assert_eq!(
+        &text[stages[1].span()],
+        "FROM --platform=$BUILDPLATFORM golang AS builder\nRUN \\\n  go build"
+    );
+    assert_eq!(&text[stages[2].span()], "FROM BASE");
+    assert!(stages[2].name().is_none());
+    assert_eq!(stages[1].platform().unwrap().value, "$BUILDPLATFORM");
+    assert!(stages[0].platform().is_none());
+    assert!(matches!(stages[0].base(), StageBase::Scratch));
+    assert!(matches!(stages[1].base(), StageBase::Image(image) if image.value == "golang"));
+    assert!(matches!(stages[2].base(), StageBase::Stage(stage) if stage.index() == 0));
+    assert!(matches!(stages[3].base(), StageBase::Stage(stage) if stage.index() == 1));
+    assert!(matches!(stages[4].base(), StageBase::Stage(stage) if stage.index() == 3));
+
+    assert_eq!(dockerfile.stage("base").unwrap().index(), 0);
+    assert_eq!(dockerfile.stage("BUILDER").unwrap().index(), 1);
+    assert!(dockerfile.stage("none").is_none());
+    assert_eq!(dockerfile.stage_by_index(3).unwrap().name().unwrap().value, "final");
+    assert!(dockerfile.stage_by_index(5).is_none());
+    assert_eq!(dockerfile.target().unwrap().index(), 4);
+
+    // A stage can only be based on an earlier stage.
+    let dockerfile = parse("FROM later AS self\nFROM self AS later\n").unwrap();
+    let stages: Vec<_> = dockerfile.stages().collect();
+    assert!(matches!(stages[0].base(), StageBase::Image(image) if image.value == "later"));
+    assert!(matches!(stages[1].base(), StageBase::Stage(stage) if stage.index() == 0));
+
+    // Stage names are case-insensitive.
+    assert_eq!(
+        parse("FROM a AS foo\nFROM b AS FOO\n").unwrap_err().to_string(),
+        "duplicate stage name 'foo' at line 2 column 11"
+    );
+}
+
+#[test]
+fn parse_options() {
+    // escape
+    let options = ParseOptions::new().escape('`');
+    let dockerfile = parse_with("FROM a\nRUN a `\n  b \\\n", options).unwrap();
+    let Instruction::Run(run) = &dockerfile.instructions[1] else { panic!() };
+    assert!(matches!(&run.arguments, Command::Shell(s) if s.value == "a `\n  b \\"));
+    let dockerfile = parse_with("# escape=\\\nFROM a\nRUN a \\\n  b\n", options).unwrap();
+    let Instruction::Run(run) = &dockerfile.instructions[1] else { panic!() };
+    assert!(matches!(&run.arguments, Command::Shell(s) if s.value == "a \\\n  b"));
+
+    // here_docs
+    let text = "FROM a\nCOPY <<EOF /dest\nRUN <<EOF\n";
+    assert_eq!(
+        parse(text).unwrap_err().to_string(),
+        "expected end of here-document (EOF), but reached eof at line 4 column 1"
+    );
+    let dockerfile = parse_with(text, ParseOptions::new().here_docs(false)).unwrap();
+    let Instruction::Copy(copy) = &dockerfile.instructions[1] else { panic!() };
+    assert!(matches!(&copy.src[0], Source::Path(p) if p.value == "<<EOF"));
+    let Instruction::Run(run) = &dockerfile.instructions[2] else { panic!() };
+    assert!(run.here_docs.is_empty());
+    assert!(matches!(&run.arguments, Command::Shell(s) if s.value == "<<EOF"));
+
+    // allow_no_stages
+    let options = ParseOptions::new().allow_no_stages(true);
+    assert_eq!(parse("RUN a").unwrap_err().to_string(), "expected FROM at line 1 column 1");
+    assert_eq!(parse("").unwrap_err().to_string(), "expected at least one FROM instruction");
+    let dockerfile = parse_with("", options).unwrap();
+    assert!(dockerfile.instructions.is_empty());
+    assert!(dockerfile.target().is_none());
+    let dockerfile = parse_with("ARG a\nRUN a\nARG b\nFROM c\n", options).unwrap();
+    assert_eq!(dockerfile.instructions.len(), 4);
+    assert_eq!(dockerfile.global_args().len(), 1);
+    assert_eq!(dockerfile.target().unwrap().from.image.value, "c");
+    let mut iter = parse_iter_with("RUN a\n", options).unwrap();
+    assert!(matches!(iter.next(), Some(Ok(Instruction::Run(..)))));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn unknown_instruction() {
+    let text = "FROM a
+INCLUDE ./common.Dockerfile \\
+  --flag
+RUNX foo
+BOGUS
+ONBUILD INCLUDE b
+EXTEND <<EOF cat <<-'EOT' x<<EOX
+a
+EOF
+\tb
+\tEOT
+";
+    assert_eq!(
+        parse(text).unwrap_err().to_string(),
+        "unknown instruction 'INCLUDE' at line 2 column 1"
+    );
+    let options = ParseOptions::new().unknown_instructions(true);
+    let dockerfile = parse_with(text, options).unwrap();
+    let i = &dockerfile.instructions;
+    assert_eq!(i.len(), 6);
+    assert!(i[1..].iter().all(|i| i.kind() != InstructionKind::From));
+    let Instruction::Unknown(include) = &i[1] else { panic!() };
+    assert_eq!(include.name, "INCLUDE");
+    assert_eq!(&text[include.keyword.span.clone()], "INCLUDE");
+    assert_eq!(include.arguments.value, "./common.Dockerfile \\\n  --flag");
+    assert_eq!(&text[include.arguments.span.clone()], include.arguments.value);
+    assert_eq!(i[1].raw_text(text), "INCLUDE ./common.Dockerfile \\\n  --flag");
+    let Instruction::Unknown(runx) = &i[2] else { panic!() };
+    assert_eq!((&*runx.name, runx.arguments.value), ("RUNX", "foo"));
+    let Instruction::Unknown(bogus) = &i[3] else { panic!() };
+    assert_eq!((&*bogus.name, bogus.arguments.value), ("BOGUS", ""));
+    assert_eq!(i[3].raw_text(text), "BOGUS");
+    assert_eq!(i[4].kind(), InstructionKind::Onbuild);
+    let Instruction::Unknown(extend) = &i[5] else { panic!() };
+    assert_eq!(extend.arguments.value, "<<EOF cat <<-'EOT' x<<EOX");
+    assert_eq!(extend.here_docs.len(), 2);
+    assert_eq!(extend.here_docs[0].value, "a\n");
+    assert!(extend.here_docs[0].expand);
+    assert_eq!(extend.here_docs[1].value, "b\n");
+    assert!(!extend.here_docs[1].expand);
+    assert!(i[5].raw_text(text).ends_with("\tEOT"));
+    assert_eq!(i[5].kind().as_str(), "UNKNOWN");
+
+    // Without here-documents, their bodies are parsed as instructions.
+    let options = options.here_docs(false);
+    let dockerfile = parse_with(text, options).unwrap();
+    assert_eq!(dockerfile.instructions.len(), 10);
+    let Instruction::Unknown(a) = &dockerfile.instructions[6] else { panic!() };
+    assert_eq!(a.name, "a");
+}
+
+/// Removes spans from the JSON representation of the dockerfile.
+fn without_spans(dockerfile: &Dockerfile<'_>) -> serde_json::Value {
+    fn remove(v: &mut serde_json::Value) {
+        match v {
+            serde_json::Value::Object(map) => {
+                map.remove("span");
+                map.remove("start");
+                map.remove("flag_start");
+                map.values_mut().for_each(remove);
+            }
+            serde_json::Value::Array(array) => array.iter_mut().for_each(remove),
+            _ => {}
+        }
+    }
+    let mut v = serde_json::to_value(dockerfile).unwrap();
+    remove(&mut v);
+    v
+}
+
+#[test]
+fn print() {
+    let text = "# check=skip=all
+# syntax=docker/dockerfile:1
+ARG  BASE=alpine
+from  --platform=$BUILDPLATFORM ${BASE} as build
+# comment
+RUN --mount=type=cache,target=/cache \\
+  echo a \\
+  && echo b
+RUN [ \"echo\", \"a\\\"b\\\\c\\n\" ]
+COPY --chmod=755 <<EOF1 <<-'EOF2' /dest
+a
+EOF1
+\tb
+\tEOF2
+COPY [\"a b\", \"/c\"]
+ONBUILD RUN <<EOF cat
+x
+EOF
+HEALTHCHECK --interval=5s CMD curl -f http://localhost/
+HEALTHCHECK NONE
+VOLUME /a /b
+FROM build
+EXPOSE 80/tcp 443
+SHELL [\"/bin/sh\", \"-c\"]
+CMD
+";
+    let dockerfile = parse(text).unwrap();
+    let printed = dockerfile.to_string();
+    assert_eq!(
+        printed,
+        "# check=skip=all
+# syntax=docker/dockerfile:1
+
+ARG BASE=alpine
+
+FROM --platform=$BUILDPLATFORM ${BASE} AS build
+RUN --mount=type=cache,target=/cache echo a \\
+  && echo b
+RUN [\"echo\", \"a\\\"b\\\\c\\n\"]
+COPY --chmod=755 <<EOF1 <<'EOF2' /dest
+a
+EOF1
+b
+EOF2
+COPY [\"a b\", \"/c\"]
+ONBUILD RUN <<EOF cat
+x
+EOF
+HEALTHCHECK --interval=5s CMD curl -f http://localhost/
+HEALTHCHECK NONE
+VOLUME /a /b
+
+FROM build
+EXPOSE 80/tcp 443
+SHELL [\"/bin/sh\", \"-c\"]
+CMD
+"
+    );
+    assert_eq!(without_spans(&parse(&printed).unwrap()), without_spans(&dockerfile));
+    assert_eq!(
+        dockerfile.instructions[1].to_string(),
+        "FROM --platform=$BUILDPLATFORM ${BASE} AS build"
+    );
+    assert!(
+        dockerfile
+            .to_string_with(PrintStyle::new().blank_line_between_stages(false))
+            .contains("\nARG BASE=alpine\nFROM ")
+    );
+
+    // escape directive
+    let text = "# escape=`\nFROM a\nRUN a `\n  b\nWORKDIR C:\\\n";
+    let dockerfile = parse(text).unwrap();
+    let printed = dockerfile.to_string();
+    assert_eq!(printed, "# escape=`\n\nFROM a\nRUN a `\n  b\nWORKDIR C:\\\n");
+    assert_eq!(without_spans(&parse(&printed).unwrap()), without_spans(&dockerfile));
+
+    // fixtures
+    for e in fs::read_dir(fixtures_dir()).unwrap() {
+        let p = &e.unwrap().path();
+        if p.is_dir() {
+            continue;
+        }
+        let text = &fs::read_to_string(p).unwrap();
+        let dockerfile = parse(text).unwrap();
+        let printed = dockerfile.to_string();
+        let reparsed = parse(&printed).unwrap();
+        assert_eq!(without_spans(&reparsed), without_spans(&dockerfile), "{}", p.display());
+        assert_eq!(reparsed.to_string(), printed);
+    }
+}
+
+#[test]
+fn syntax_tree() {
+    fn assert_lossless(tree: &SyntaxTree<'_>, text: &str) {
+        assert_eq!(tree.root().text(), text);
+        let mut pos = 0;
+        for token in tree.root().tokens() {
+            assert_eq!(token.range().start, pos);
+            assert_eq!(&text[token.range()], token.text());
+            pos = token.range().end;
+        }
+        assert_eq!(pos, text.len());
+    }
+
+    let text = "\u{FEFF}# syntax=docker/dockerfile:1\r\n# escape=`\r\n\r\nfrom alpine AS base\r\n  # comment\r\nRUN echo `\r\n  hello\r\nRUN <<EOF cat\r\nfoo\r\nEOF\r\nONBUILD COPY --chown=1 a b";
+    let tree = SyntaxTree::parse(text).unwrap();
+    assert_lossless(&tree, text);
+    assert_eq!(tree.bom_len(), 3);
+    assert_eq!(tree.to_string(), text);
+    let kinds: Vec<_> = tree
+        .root()
+        .children()
+        .iter()
+        .map(|e| match e {
+            SyntaxElement::Node(n) => n.kind(),
+            SyntaxElement::Token(t) => t.kind(),
+        })
+        .collect();
+    assert_eq!(kinds, [
+        SyntaxKind::Bom,
+        SyntaxKind::ParserDirective,
+        SyntaxKind::Newline,
+        SyntaxKind::ParserDirective,
+        SyntaxKind::Newline,
+        SyntaxKind::Newline,
+        SyntaxKind::Instruction,
+        SyntaxKind::Newline,
+        SyntaxKind::Whitespace,
+        SyntaxKind::Comment,
+        SyntaxKind::Newline,
+        SyntaxKind::Instruction,
+        SyntaxKind::Newline,
+        SyntaxKind::Instruction,
+        SyntaxKind::Newline,
+        SyntaxKind::Instruction,
+    ]);
+    let instructions: Vec<_> = tree.instructions().collect();
+    assert_eq!(instructions.len(), 4);
+    assert_eq!(instructions[0].keyword().text(), "from");
+    assert!(matches!(instructions[0].instruction(), Instruction::From(..)));
+    let tokens: Vec<_> = instructions[1].syntax().tokens().map(|t| (t.kind(), t.text())).collect();
+    assert_eq!(tokens, [
+        (SyntaxKind::Keyword, "RUN"),
+        (SyntaxKind::Whitespace, " "),
+        (SyntaxKind::Text, "echo"),
+        (SyntaxKind::Whitespace, " "),
+        (SyntaxKind::LineContinuation, "`\r\n"),
+        (SyntaxKind::Whitespace, "  "),
+        (SyntaxKind::Text, "hello"),
+    ]);
+    let tokens: Vec<_> = instructions[2].syntax().tokens().map(|t| (t.kind(), t.text())).collect();
+    assert_eq!(tokens, [
+        (SyntaxKind::Keyword, "RUN"),
+        (SyntaxKind::Whitespace, " "),
+        (SyntaxKind::Text, "<<EOF"),
+        (SyntaxKind::Whitespace, " "),
+        (SyntaxKind::Text, "cat"),
+        (SyntaxKind::Newline, "\r\n"),
+        (SyntaxKind::HereDocBody, "foo\r\n"),
+        (SyntaxKind::HereDocDelimiter, "EOF"),
+    ]);
+    let trigger = instructions[3].onbuild_trigger().unwrap();
+    assert_eq!(trigger.syntax().text(), "COPY --chown=1 a b");
+    assert_eq!(trigger.keyword().text(), "COPY");
+    assert!(matches!(trigger.instruction(), Instruction::Copy(..)));
+    assert!(instructions[0].onbuild_trigger().is_none());
+
+    // typed views
+    assert_eq!(instructions[0].arguments().unwrap().text(), "alpine AS base");
+    let arguments = instructions[1].arguments().unwrap();
+    assert_eq!(arguments.text(), "echo `\r\n  hello");
+    assert_eq!(arguments.elements().len(), 5);
+    assert_eq!(instructions[2].arguments().unwrap().text(), "<<EOF cat\r\nfoo\r\nEOF");
+    let here_docs: Vec<_> = instructions[2].here_docs().collect();
+    assert_eq!(here_docs.len(), 1);
+    assert_eq!(here_docs[0].text(), "foo\r\nEOF");
+    assert_eq!(here_docs[0].ast().value, "foo\r\n");
+    let kinds: Vec<_> = here_docs[0].elements().iter().map(SyntaxElement::kind).collect();
+    assert_eq!(kinds, [SyntaxKind::HereDocBody, SyntaxKind::HereDocDelimiter]);
+    assert_eq!(instructions[3].flags().len(), 0);
+    assert_eq!(instructions[3].arguments().unwrap().text(), "COPY --chown=1 a b");
+    assert_eq!(trigger.flags().map(|f| f.text()).collect::<Vec<_>>(), ["--chown=1"]);
+    assert_eq!(trigger.arguments().unwrap().text(), "a b");
+    let text = "FROM alpine\nCOPY --chown=1 \\\n  # comment\n  --link a \\\n b\nHEALTHCHECK NONE\n";
+    let tree = SyntaxTree::parse(text).unwrap();
+    let instructions: Vec<_> = tree.instructions().collect();
+    let flags: Vec<_> = instructions[1].flags().map(|f| (&*f.ast().name.value, f.text())).collect();
+    assert_eq!(flags, [("chown", "--chown=1"), ("link", "--link")]);
+    let arguments = instructions[1].arguments().unwrap();
+    assert_eq!(arguments.text(), "a \\\n b");
+    assert_eq!(arguments.range(), 50..56);
+    assert!(instructions[1].here_docs().next().is_none());
+    assert_eq!(instructions[2].arguments().unwrap().text(), "NONE");
+
+    // fixtures
+    for e in fs::read_dir(fixtures_dir()).unwrap() {
+        let p = &e.unwrap().path();
+        if p.is_dir() {
+            continue;
+        }
+        let text = &fs::read_to_string(p).unwrap();
+        let tree = SyntaxTree::parse(text).unwrap();
+        assert_lossless(&tree, text);
+        assert_eq!(tree.instructions().count(), tree.dockerfile().instructions.len());
+        for instruction in tree.instructions() {
+            let span = instruction.instruction().span();
+            assert_eq!(
+                instruction.syntax().range(),
+                span.start + tree.bom_len()..span.end + tree.bom_len()
+            );
+        }
+    }
+}
+
+#[test]
+fn edit() {
+    let text = "FROM --platform=$BUILDPLATFORM rust:1.79 AS build\n  RUN <<EOF\ncargo build\nEOF\nCMD [\"a\", \"b\"]\r\nCOPY --link --chown=1 a b\n";
+    let dockerfile = parse(text).unwrap();
+    let [Instruction::From(from), run, Instruction::Cmd(cmd), Instruction::Copy(copy)] =
+        &*dockerfile.instructions
+    else {
+        panic!()
+    };
+    let Command::Exec(args) = &cmd.arguments else { panic!() };
+    let edits = [
+        from.image.replace_with("rust:1.80"),
+        from.options[0].set_value("linux/amd64"),
+        run.remove(),
+        args.value[1].replace_with("say \"hi\"\n"),
+        copy.options[0].set_value("true"),
+        copy.options[1].remove(),
+    ];
+    assert_eq!(
+        apply_edits(text, &edits).unwrap(),
+        "FROM --platform=linux/amd64 rust:1.80 AS build\nCMD [\"a\", \"say \\\"hi\\\"\\n\"]\r\nCOPY --link=true a b\n"
+    );
+    // BOM
+    let bom_text = format!("\u{FEFF}{text}");
+    assert_eq!(
+        apply_edits(&bom_text, &[dockerfile.instructions[3].remove()]).unwrap(),
+        format!(
+            "\u{FEFF}FROM --platform=$BUILDPLATFORM rust:1.79 AS build\n  RUN <<EOF\ncargo build\nEOF\nCMD [\"a\", \"b\"]\r\n"
+        )
+    );
+    // insertions at the same position are applied in order
+    let edits = [TextEdit::insert(0, "# a\n"), TextEdit::insert(0, "# b\n")];
+    assert!(apply_edits(text, &edits).unwrap().starts_with("# a\n# b\nFROM"));
+    // values are escaped for their positions
+    for (escape, expected) in [
+        (
+            "",
+            "FROM --platform=\\$P a\\$B AS build\nCOPY --chown=\\$U --link=\\\"x\\\" [\"a b\\\\$c\", \"d\"]\nWORKDIR /my dir/\\$D\\\\\nENV A=1 B=$B\nCMD [\"$HOME\"]\n",
+        ),
+        (
+            "# escape=`\n",
+            "# escape=`\nFROM --platform=`$P a`$B AS build\nCOPY --chown=`$U --link=`\"x`\" [\"a b`$c\", \"d\"]\nWORKDIR /my dir/`$D\\\nENV A=1 B=$B\nCMD [\"$HOME\"]\n",
+        ),
+    ] {
+        let text = &format!(
+            "{escape}FROM --platform=x a AS build\nCOPY --chown=1 --link [\"a\", \"d\"]\nWORKDIR /\nENV A=1\nCMD [\"echo\"]\n"
+        );
+        let dockerfile = parse(text).unwrap();
+        let [
+            Instruction::From(from),
+            Instruction::Copy(copy),
+            Instruction::Workdir(workdir),
+            Instruction::Env(env),
+            Instruction::Cmd(cmd),
+        ] = &*dockerfile.instructions
+        else {
+            panic!()
+        };
+        let Source::Path(src) = &copy.src[0] else { panic!() };
+        let Command::Exec(args) = &cmd.arguments else { panic!() };
+        let edits = [
+            from.options[0].set_value("$P"),
+            from.image.replace_with("a$B"),
+            copy.options[0].set_value("$U"),
+            copy.options[1].set_value("\"x\""),
+            src.replace_with("a b$c"),
+            workdir.arguments.replace_with("/my dir/$D\\"),
+            env.arguments.replace_with("A=1 B=$B"),
+            args.value[0].replace_with("$HOME"),
+        ];
+        assert_eq!(apply_edits(text, &edits).unwrap(), expected, "{escape:?}");
+        let edits = [from.image.replace_with("a b")];
+        assert_eq!(
+            apply_edits(text, &edits).unwrap_err().to_string(),
+            format!(
+                "value containing whitespace cannot be escaped at line {} column 19",
+                1 + escape.lines().count()
+            )
+        );
+    }
+
+    // errors
+    let edits = [run.remove(), TextEdit::replace(60..61, "x")];
+    assert_eq!(
+        apply_edits(text, &edits).unwrap_err().to_string(),
+        "overlapping edits at line 2 column 11"
+    );
+    let edits = [TextEdit::delete(text.len()..text.len() + 1)];
+    assert_eq!(
+        apply_edits(text, &edits).unwrap_err().to_string(),
+        "edit out of range at line 7 column 1"
+    );
+    let edits = [dockerfile.instructions[0].remove()];
+    assert_eq!(
+        apply_edits(text, &edits).unwrap_err().to_string(),
+        "expected FROM at line 1 column 3"
+    );
+}
+
+#[test]
+fn builder() {
+    let text = DockerfileBuilder::new()
+        .syntax("docker/dockerfile:1")
+        .arg_default("VERSION", "1.80")
+        .comment("build stage")
+        .from("rust:${VERSION}")
+        .platform("$BUILDPLATFORM")
+        .as_("build")
+        .workdir("/app")
+        .copy(".", ".")
+        .run_shell("cargo build --release")
+        .flag("mount", "type=cache,target=/app/target")
+        .from("debian:bookworm-slim")
+        .env("PATH", "/app/bin:$PATH")
+        .env("GREETING", "hello \"world\"")
+        .label("org.opencontainers.image.title", "")
+        .copy_from("build", "/app/my app", "/usr/local/bin/")
+        .bool_flag("link")
+        .expose("8080/tcp")
+        .user("app")
+        .entrypoint_exec(["/usr/local/bin/my app", "--name=\"x\""])
+        .build()
+        .unwrap();
+    assert_eq!(
+        text,
+        "# syntax=docker/dockerfile:1
+
+ARG VERSION=1.80
+# build stage
+FROM --platform=$BUILDPLATFORM rust:${VERSION} AS build
+WORKDIR /app
+COPY . .
+RUN --mount=type=cache,target=/app/target cargo build --release
+
+FROM debian:bookworm-slim
+ENV PATH=\"/app/bin:\\$PATH\"
+ENV GREETING=\"hello \\\"world\\\"\"
+LABEL org.opencontainers.image.title=\"\"
+COPY --from=build --link [\"/app/my app\", \"/usr/local/bin/\"]
+EXPOSE 8080/tcp
+USER app
+ENTRYPOINT [\"/usr/local/bin/my app\", \"--name=\\\"x\\\"\"]
+"
+    );
+    let dockerfile = parse(&text).unwrap();
+    assert_eq!(dockerfile.stages().count(), 2);
+    let Instruction::Env(env) = &dockerfile.instructions[7] else { panic!() };
+    assert_eq!(env.arguments.value, "GREETING=\"hello \\\"world\\\"\"");
+
+    // escape directive
+    let text = DockerfileBuilder::new()
+        .escape('`')
+        .from("mcr.microsoft.com/windows/servercore")
+        .env("DIR", "C:\\Program Files\\app")
+        .env("PRICE", "`$1")
+        .build()
+        .unwrap();
+    assert_eq!(
+        text,
+        "# escape=`\n\nFROM mcr.microsoft.com/windows/servercore\nENV DIR=\"C:\\Program Files\\app\"\nENV PRICE=\"```$1\"\n"
+    );
+    let dockerfile = parse(&text).unwrap();
+    let Instruction::Env(env) = &dockerfile.instructions[2] else { panic!() };
+    assert_eq!(env.arguments.value, "PRICE=\"```$1\"");
+
+    // comment that looks like a parser directive
+    let text = DockerfileBuilder::new()
+        .comment("escape=`")
+        .from("alpine")
+        .env("A", "C:\\")
+        .build()
+        .unwrap();
+    assert_eq!(text, "\n# escape=`\nFROM alpine\nENV A=\"C:\\\\\"\n");
+    assert!(parse(&text).unwrap().parser_directives.escape.is_none());
+    let text = DockerfileBuilder::new().comment("see a=b").from("alpine").build().unwrap();
+    assert_eq!(text, "# see a=b\nFROM alpine\n");
+
+    // errors
+    let e = DockerfileBuilder::new().from("a").run_shell("a\nb").build().unwrap_err();
+    assert_eq!(e.to_string(), "RUN instruction must not contain newline: \"a\\nb\"");
+    let e = DockerfileBuilder::new().from("a").run_shell("a \\").build().unwrap_err();
+    assert_eq!(e.to_string(), "RUN instruction must not end with escape character");
+    let e = DockerfileBuilder::new().from("a b").build().unwrap_err();
+    assert_eq!(e.to_string(), "invalid value for FROM instruction: \"a b\"");
+    let e = DockerfileBuilder::new().from("a").env("A=B", "c").build().unwrap_err();
+    assert_eq!(e.to_string(), "invalid value for ENV instruction: \"A=B\"");
+    // no stage
+    let e = DockerfileBuilder::new().run_shell("a").build().unwrap_err();
+    assert_eq!(e.line(), 1);
+}
+
+#[test]
+fn rename_stage() {
+    let text = "\u{FEFF}ARG BASE=base
+# Base is the base stage.
+FROM alpine AS Base
+# build builds the app
+# more description
+FROM base AS build
+RUN --mount=type=cache,target=/cache --mount=type=bind,from=BASE,target=/base make
+COPY --from=base /a /b
+ONBUILD COPY --from=base /a /b
+COPY --from=${BASE} /a /b
+FROM $BASE
+COPY --from=build /c /d
+";
+    let dockerfile = parse(text).unwrap();
+    let rename = dockerfile.rename_stage(text, "BASE", "runtime-base").unwrap();
+    assert_eq!(rename.unresolved.len(), 2);
+    assert_eq!(&text[3..][rename.unresolved[0].clone()], "${BASE}");
+    assert_eq!(&text[3..][rename.unresolved[1].clone()], "$BASE");
+    assert_eq!(
+        apply_edits(text, &rename.edits).unwrap(),
+        "\u{FEFF}ARG BASE=base
+# Base is the base stage.
+FROM alpine AS runtime-base
+# build builds the app
+# more description
+FROM runtime-base AS build
+RUN --mount=type=cache,target=/cache --mount=type=bind,from=runtime-base,target=/base make
+COPY --from=runtime-base /a /b
+ONBUILD COPY --from=runtime-base /a /b
+COPY --from=${BASE} /a /b
+FROM $BASE
+COPY --from=build /c /d
+"
+    );
+    let rename = dockerfile.rename_stage(text, "build", "builder").unwrap();
+    assert_eq!(
+        apply_edits(text, &rename.edits).unwrap().lines().filter(|l| l.contains("builder")).count(),
+        3
+    );
+    // change case only
+    let rename = dockerfile.rename_stage(text, "build", "BUILD").unwrap();
+    assert_eq!(rename.edits.len(), 3);
+    // description comment is matched against the lowercased stage name
+    let text2 = "# build builds the app\nFROM rust AS Build\n";
+    let rename = parse(text2).unwrap().rename_stage(text2, "build", "builder").unwrap();
+    assert_eq!(
+        apply_edits(text2, &rename.edits).unwrap(),
+        "# builder builds the app\nFROM rust AS builder\n"
+    );
+    let text2 = "# Build builds the app\nFROM rust AS Build\n";
+    let rename = parse(text2).unwrap().rename_stage(text2, "build", "builder").unwrap();
+    assert_eq!(
+        apply_edits(text2, &rename.edits).unwrap(),
+        "# Build builds the app\nFROM rust AS builder\n"
+    );
+
+    // errors
+    let e = dockerfile.rename_stage(text, "foo", "bar").unwrap_err();
+    assert_eq!(e.to_string(), "stage \"foo\" not found");
+    let e = dockerfile.rename_stage(text, "base", "Build").unwrap_err();
+    assert_eq!(e.to_string(), "stage named \"Build\" already exists");
+    let e = dockerfile.rename_stage(text, "base", "Scratch").unwrap_err();
+    assert_eq!(e.to_string(), "stage name \"Scratch\" is reserved");
+    let e = dockerfile.rename_stage(text, "base", "context").unwrap_err();
+    assert_eq!(e.to_string(), "stage name \"context\" is reserved");
+    let e = dockerfile.rename_stage(text, "base", "1st").unwrap_err();
+    assert_eq!(e.to_string(), "invalid stage name \"1st\"");
+}
+
+#[test]
+fn update_images() {
+    let text = "ARG REGISTRY=docker.io
+ARG NODE_VERSION=\"18\"
+ARG RUST
+FROM ${REGISTRY}/library/node:${NODE_VERSION} AS node
+FROM node:${NODE_VERSION} AS node2
+FROM node:18 AS build
+FROM node
+COPY --from=node /a /b
+COPY --from=0 /a /b
+COPY --from=alpine:3 /a /b
+ONBUILD RUN --mount=from=alpine:3,target=/x true
+COPY --from=$X /a /b
+FROM rust:${RUST}
+FROM alpine:3
+";
+    let dockerfile = parse(text).unwrap();
+    let update = dockerfile
+        .update_images(&[("node:18", "node:20.11@sha256:abc"), ("alpine:3", "alpine:3.20")]);
+    let unresolved: Vec<_> = update.unresolved.iter().map(|s| &text[s.clone()]).collect();
+    assert_eq!(unresolved, ["$X", "rust:${RUST}"]);
+    assert_eq!(
+        apply_edits(text, &update.edits).unwrap(),
+        "ARG REGISTRY=docker.io
+ARG NODE_VERSION=20.11@sha256:abc
+ARG RUST
+FROM ${REGISTRY}/library/node:${NODE_VERSION} AS node
+FROM node:${NODE_VERSION} AS node2
+FROM node:20.11@sha256:abc AS build
+FROM node
+COPY --from=node /a /b
+COPY --from=0 /a /b
+COPY --from=alpine:3.20 /a /b
+ONBUILD RUN --mount=from=alpine:3.20,target=/x true
+COPY --from=$X /a /b
+FROM rust:${RUST}
+FROM alpine:3.20
+"
+    );
+
+    // conflicting updates of the same ARG
+    let text = "ARG V=18\nFROM node:${V}\nFROM deno:${V}\n";
+    let dockerfile = parse(text).unwrap();
+    let update = dockerfile.update_images(&[("node:18", "node:20"), ("deno:18", "deno:19")]);
+    assert!(update.edits.is_empty());
+    assert_eq!(update.unresolved.len(), 1);
+    assert_eq!(&text[update.unresolved[0].clone()], "deno:${V}");
+
+    // variables with operators
+    let text = "ARG V=18\nFROM node:${V:-16}\nFROM node:${X:-18}\n";
+    let dockerfile = parse(text).unwrap();
+    let update = dockerfile.update_images(&[("node:18", "node:20")]);
+    assert!(update.edits.is_empty());
+    let unresolved: Vec<_> = update.unresolved.iter().map(|s| &text[s.clone()]).collect();
+    assert_eq!(unresolved, ["node:${V:-16}", "node:${X:-18}"]);
+}
+
+#[test]
+fn convert() {
+    let text = "FROM alpine AS base
+CMD  app  --port=8080  # trailing
+CMD ./app --verbose
+CMD echo $HOME
+CMD FOO=bar app
+CMD app \\
+  --verbose
+CMD cd /app
+CMD . /env
+CMD app 100%
+HEALTHCHECK --interval=5s CMD curl -f http://localhost/
+HEALTHCHECK CMD exit 1
+HEALTHCHECK NONE
+ONBUILD CMD app
+ONBUILD HEALTHCHECK CMD app
+ENV A 1
+ENV B hello  world
+ENV C \"hello world\"
+ENV D=1 E=2
+LABEL version 1.0
+MAINTAINER Jane \"JD\" Doe <jane@example.com> $1
+
+FROM scratch
+ENTRYPOINT tini -- app
+
+FROM base
+ENTRYPOINT tini -- app
+
+FROM alpine
+ENTRYPOINT nginx -g daemon
+CMD echo hi
+
+FROM alpine AS bash
+SHELL [\"/bin/bash\", \"-c\"]
+CMD app
+FROM bash
+HEALTHCHECK CMD app
+";
+    let dockerfile = parse(text).unwrap();
+    let edits = dockerfile.convert(ConvertOptions::new());
+    assert_eq!(
+        apply_edits(text, &edits).unwrap(),
+        "FROM alpine AS base
+CMD  app  --port=8080  # trailing
+CMD [\"./app\", \"--verbose\"]
+CMD echo $HOME
+CMD FOO=bar app
+CMD app \\
+  --verbose
+CMD cd /app
+CMD . /env
+CMD app 100%
+HEALTHCHECK --interval=5s CMD [\"curl\", \"-f\", \"http://localhost/\"]
+HEALTHCHECK CMD exit 1
+HEALTHCHECK NONE
+ONBUILD CMD app
+ONBUILD HEALTHCHECK CMD app
+ENV A=1
+ENV B=\"hello  world\"
+ENV C \"hello world\"
+ENV D=1 E=2
+LABEL version=1.0
+LABEL org.opencontainers.image.authors=\"Jane \\\"JD\\\" Doe <jane@example.com> \\$1\"
+
+FROM scratch
+ENTRYPOINT [\"tini\", \"--\", \"app\"]
+
+FROM base
+ENTRYPOINT tini -- app
+
+FROM alpine
+ENTRYPOINT nginx -g daemon
+CMD echo hi
+
+FROM alpine AS bash
+SHELL [\"/bin/bash\", \"-c\"]
+CMD app
+FROM bash
+HEALTHCHECK CMD app
+"
+    );
+    let edits = dockerfile.convert(
+        ConvertOptions::new().exec_form(false).key_value_form(false).maintainer_to_label(false),
+    );
+    assert!(edits.is_empty());
+    let edits = dockerfile.convert(ConvertOptions::new().exec_form(false).key_value_form(false));
+    assert_eq!(edits.len(), 1);
+
+    // escape directive
+    let text = "# escape=`\nFROM alpine\nMAINTAINER C:\\Users\\` $USER\n";
+    let dockerfile = parse(text).unwrap();
+    let edits = dockerfile.convert(ConvertOptions::new());
+    let new = apply_edits(text, &edits).unwrap();
+    assert_eq!(
+        new,
+        "# escape=`\nFROM alpine\nLABEL org.opencontainers.image.authors=\"C:\\Users\\`` `$USER\"\n"
+    );
+}
+
+#[test]
+fn prune() {
+    let text = "\u{FEFF}# syntax=docker/dockerfile:1
+# check=error=true
+
+ARG BASE=alpine:3
+ARG UNUSED=1
+ARG TOOL_VERSION=1.0 TOOL=tool:${TOOL_VERSION}
+ARG TEST_STAGE=test-base
+FROM ${BASE} AS base
+RUN apk add make
+
+FROM ${TOOL} AS tool
+FROM base AS build
+# comment in stage
+RUN --mount=type=cache,target=/cache \\
+    --mount=from=tool,target=/tool make
+FROM base AS docs
+FROM $BASE AS test-base
+FROM ${TEST_STAGE} AS test
+ARG UNUSED
+COPY --from=1 /a /b
+COPY --from=build /c /d
+COPY --from=docker.io/library/alpine /e /f
+";
+    let dockerfile = parse(text).unwrap();
+    assert_eq!(
+        dockerfile.prune(text, "test").unwrap(),
+        "\u{FEFF}# syntax=docker/dockerfile:1
+# check=error=true
+
+ARG BASE=alpine:3
+ARG UNUSED=1
+ARG TOOL_VERSION=1.0 TOOL=tool:${TOOL_VERSION}
+ARG TEST_STAGE=test-base
+
+FROM ${BASE} AS base
+RUN apk add make
+
+FROM ${TOOL} AS tool
+
+FROM base AS build
+# comment in stage
+RUN --mount=type=cache,target=/cache \\
+    --mount=from=tool,target=/tool make
+
+FROM $BASE AS test-base
+
+FROM ${TEST_STAGE} AS test
+ARG UNUSED
+COPY --from=1 /a /b
+COPY --from=build /c /d
+COPY --from=docker.io/library/alpine /e /f
+"
+    );
+    assert_eq!(
+        dockerfile.prune(text, "DOCS").unwrap(),
+        "\u{FEFF}# syntax=docker/dockerfile:1\n# check=error=true\n\nARG BASE=alpine:3\n\nFROM ${BASE} AS base\nRUN apk add make\n\nFROM base AS docs\n"
+    );
+    assert_eq!(dockerfile.prune(text, "foo").unwrap_err().to_string(), "stage \"foo\" not found");
+    let text = "FROM a AS x\r\nFROM b AS y\r\n";
+    let dockerfile = parse(text).unwrap();
+    assert_eq!(dockerfile.prune(text, "y").unwrap(), "FROM b AS y\r\n");
+    // escaped references are not variables
+    let text = "ARG A=a\nARG B=b\nFROM --platform=\\$B ${A:-alpine} AS x\n";
+    let dockerfile = parse(text).unwrap();
+    assert_eq!(
+        dockerfile.prune(text, "x").unwrap(),
+        "ARG A=a\n\nFROM --platform=\\$B ${A:-alpine} AS x\n"
+    );
+    // references by index are rewritten to the new indices
+    let text = "FROM alpine AS unused
+FROM alpine AS a
+FROM alpine AS test
+COPY --from=1 /a /b
+RUN --mount=from=1,target=/a --mount=type=bind,from=\"1\",target=/b make
+";
+    let dockerfile = parse(text).unwrap();
+    let pruned = dockerfile.prune(text, "test").unwrap();
+    assert_eq!(
+        pruned,
+        "FROM alpine AS a
+
+FROM alpine AS test
+COPY --from=0 /a /b
+RUN --mount=from=0,target=/a --mount=type=bind,from=\"0\",target=/b make
+"
+    );
+    parse(&pruned).unwrap().graph().unwrap();
+    let text = "ARG N=1\nFROM alpine AS unused\nFROM alpine AS a\nFROM alpine AS test\nARG N\nCOPY --from=$N /a /b\n";
+    let dockerfile = parse(text).unwrap();
+    assert_eq!(
+        dockerfile.prune(text, "test").unwrap_err().to_string(),
+        "cannot rewrite reference to stage by index 1 in stage \"test\""
+    );
+}
+
+#[test]
+fn flatten_stages() {
+    let text = "ARG BASE=alpine
+FROM $BASE AS base
+RUN apk add make
+
+FROM base AS deps
+COPY deps /deps
+
+# build
+FROM deps AS build
+RUN make
+
+FROM build AS Final
+COPY --from=tools /x /y
+
+FROM alpine AS tools
+ONBUILD RUN x
+FROM tools AS tools2
+FROM tools2 AS Tools3
+ARG VERSION
+FROM Tools3
+";
+    let dockerfile = parse(text).unwrap();
+    let flattening = dockerfile.flatten_stages(text);
+    // tools has ONBUILD and is referenced by COPY --from.
+    assert_eq!(flattening.merged, [vec![0, 1, 2, 3], vec![5, 6, 7]]);
+    assert_eq!(
+        apply_edits(text, &flattening.edits).unwrap(),
+        "ARG BASE=alpine
+
+FROM $BASE AS Final
+RUN apk add make
+COPY deps /deps
+RUN make
+COPY --from=tools /x /y
+
+FROM alpine AS tools
+ONBUILD RUN x
+FROM tools
+ARG VERSION
+"
+    );
+
+    // ARG declared in an earlier merged stage
+    let text = "FROM alpine AS a\nARG V=1\nFROM a AS b\nFROM b AS c\nRUN make\n";
+    let dockerfile = parse(text).unwrap();
+    assert_eq!(dockerfile.flatten_stages(text).merged, [[0, 1]]);
+
+    // refused cases
+    for text in [
+        // referenced by COPY --from
+        "FROM alpine AS a\nFROM a AS b\nFROM alpine\nCOPY --from=a /x /y\n",
+        // referenced by RUN --mount=from=
+        "FROM alpine AS a\nFROM a AS b\nFROM alpine\nRUN --mount=from=A,target=/x true\n",
+        // used as base of multiple stages
+        "FROM alpine AS a\nFROM a AS b\nFROM a AS c\n",
+        // ARG would change scope
+        "FROM alpine AS a\nARG V=1\nFROM a AS b\nRUN echo ${V:-x}\n",
+        "FROM alpine AS a\nARG V=1\nFROM a AS b\nARG V\n",
+        "FROM alpine AS a\nARG V=1\nFROM a AS b\nRUN make\n",
+        // ONBUILD
+        "FROM alpine AS a\nONBUILD RUN x\nFROM a AS b\n",
+        // flags
+        "FROM alpine AS a\nFROM --platform=linux/amd64 a AS b\n",
+        // index reference
+        "FROM alpine AS a\nFROM a AS b\nFROM alpine\nCOPY --from=2 /x /y\n",
+        // unresolved reference
+        "FROM alpine AS a\nFROM a AS b\nFROM alpine\nCOPY --from=$X /x /y\n",
+        // referenced via a variable
+        "ARG S=a\nFROM alpine AS a\nFROM a AS b\nFROM alpine\nARG S\nCOPY --from=${S} /x /y\n",
+    ] {
+        let dockerfile = parse(text).unwrap();
+        let flattening = dockerfile.flatten_stages(text);
+        assert!(flattening.merged.is_empty(), "{text}");
+        assert!(flattening.edits.is_empty(), "{text}");
+    }
+    // references resolved to images
+    let text = "FROM alpine AS a\nFROM a AS b\nFROM alpine\nCOPY --from=${X:-busybox} /x /y\nCOPY --from=\\$X /x /y\n";
+    let dockerfile = parse(text).unwrap();
+    assert_eq!(dockerfile.flatten_stages(text).merged, [[0, 1]]);
+}
+
+#[test]
+fn graph() {
+    let text = "ARG BASE=alpine
+FROM $BASE AS base
+COPY . /src
+
+FROM base AS build
+RUN --mount=from=cache,target=/cache --mount=type=bind,target=/ctx make
+ADD https://example.com/x /x
+
+FROM golang AS tools
+COPY --from=busybox /bin/sh /sh
+
+FROM base AS test
+ARG BASE
+COPY --from=1 /out /out
+COPY --from=${BASE} /etc /etc
+
+FROM scratch
+COPY --from=build /out /out
+";
+    let dockerfile = parse(text).unwrap();
+    let graph = dockerfile.graph().unwrap();
+    assert_eq!(graph.images(), ["alpine", "cache", "golang", "busybox"]);
+    let edges: Vec<_> = graph.edges().iter().map(|e| (e.from, e.to, e.kind)).collect();
+    assert_eq!(edges, [
+        (Node::Image(0), 0, EdgeKind::From),
+        (Node::Context, 0, EdgeKind::Copy),
+        (Node::Stage(0), 1, EdgeKind::From),
+        (Node::Image(1), 1, EdgeKind::Mount),
+        (Node::Context, 1, EdgeKind::Mount),
+        (Node::Image(2), 2, EdgeKind::From),
+        (Node::Image(3), 2, EdgeKind::Copy),
+        (Node::Stage(0), 3, EdgeKind::From),
+        (Node::Stage(1), 3, EdgeKind::Copy),
+        (Node::Image(0), 3, EdgeKind::Copy),
+        (Node::Stage(1), 4, EdgeKind::Copy),
+    ]);
+    assert!(matches!(graph.edges()[1].instruction, Instruction::Copy(..)));
+    assert_eq!(graph.dependencies(4).count(), 1);
+    assert_eq!(graph.topological_order(), [0, 1, 2, 3, 4]);
+    assert_eq!(graph.build_order(4), [0, 1, 4]);
+    assert_eq!(graph.build_order(3), [0, 1, 3]);
+    assert_eq!(graph.unreachable_stages(4), [2, 3]);
+    assert_eq!(graph.unreachable_stages(2), [0, 1, 3, 4]);
+
+    // Variables are expanded like BuildKit, with build args if given.
+    let text = r"ARG BASE=alpine
+ARG STAGE
+FROM ${BASE:-scratch} AS a
+FROM a AS b
+COPY --from=$BASE /x /x
+ARG STAGE
+COPY --from=${STAGE:-a} /x /x
+COPY --from=\$BASE /x /x
+RUN --mount=from=$STAGE,target=/x true
+";
+    let dockerfile = parse(text).unwrap();
+    let graph = dockerfile.graph().unwrap();
+    // Global args are not visible in stages unless redeclared, and unset
+    // variables are left as is.
+    assert_eq!(graph.images(), ["alpine", "$BASE", "$STAGE"]);
+    let edges: Vec<_> = graph.edges().iter().map(|e| (e.from, e.to, e.kind)).collect();
+    assert_eq!(edges, [
+        (Node::Image(0), 0, EdgeKind::From),
+        (Node::Stage(0), 1, EdgeKind::From),
+        (Node::Image(1), 1, EdgeKind::Copy),
+        (Node::Stage(0), 1, EdgeKind::Copy),
+        (Node::Image(1), 1, EdgeKind::Copy),
+        (Node::Image(2), 1, EdgeKind::Mount),
+    ]);
+    let graph = dockerfile.graph_with_args(&[("BASE", "debian"), ("STAGE", "0")]).unwrap();
+    assert_eq!(graph.images(), ["debian", "$BASE"]);
+    let edges: Vec<_> = graph.edges().iter().map(|e| (e.from, e.to, e.kind)).collect();
+    assert_eq!(edges, [
+        (Node::Image(0), 0, EdgeKind::From),
+        (Node::Stage(0), 1, EdgeKind::From),
+        (Node::Image(1), 1, EdgeKind::Copy),
+        (Node::Stage(0), 1, EdgeKind::Copy),
+        (Node::Image(1), 1, EdgeKind::Copy),
+        (Node::Stage(0), 1, EdgeKind::Mount),
+    ]);
+    // `from` of a mount is parsed as CSV.
+    let text = "FROM alpine AS a\nFROM alpine\nRUN --mount=\"from=a\",target=/x true\n";
+    let dockerfile = parse(text).unwrap();
+    let deps: Vec<_> = dockerfile.graph().unwrap().dependencies(1).map(|e| e.from).collect();
+    assert_eq!(deps, [Node::Image(0), Node::Stage(0)]);
+
+    // COPY --from may refer to a later stage.
+    let text = "FROM alpine\nCOPY --from=b /x /x\nFROM alpine AS b\n";
+    let dockerfile = parse(text).unwrap();
+    let graph = dockerfile.graph().unwrap();
+    assert_eq!(graph.topological_order(), [1, 0]);
+    assert_eq!(graph.build_order(0), [1, 0]);
+    // FROM refers to an image of the same name as the stage.
+    let text = "FROM alpine AS alpine\n";
+    let dockerfile = parse(text).unwrap();
+    assert_eq!(dockerfile.graph().unwrap().images(), ["alpine"]);
+
+    // errors
+    for (text, msg) in [
+        ("FROM b AS a\nFROM alpine AS b\n", "stage \"a\" refers to later stage \"b\" in FROM"),
+        (
+            "FROM alpine\nCOPY --from=0 /x /x\n",
+            "stage 0 refers to current or later stage by index 0",
+        ),
+        (
+            "FROM alpine AS a\nCOPY --from=5 /x /x\n",
+            "stage \"a\" refers to current or later stage by index 5",
+        ),
+        (
+            "FROM alpine AS a\nCOPY --from=b /x /x\nFROM a AS b\n",
+            "circular dependency detected on stage \"a\"",
+        ),
+        (
+            "FROM alpine AS a\nRUN --mount=from=a true\n",
+            "circular dependency detected on stage \"a\"",
+        ),
+    ] {
+        let dockerfile = parse(text).unwrap();
+        assert_eq!(dockerfile.graph().unwrap_err().to_string(), msg, "{text}");
+    }
+}
+
+#[test]
+fn expand() {
+    #[track_caller]
+    fn assert_expand(expander: Expander, env: &[(&str, &str)], s: &str, expected: &str) {
+        assert_eq!(expander.expand(s, env).unwrap().value, expected, "{s}");
+    }
+
+    let env = [
+        ("A", "a"),
+        ("EMPTY", ""),
+        ("PATH", "/usr/local/bin:/usr/bin"),
+        ("FILE", "archive.tar.gz"),
+        ("WORDS", " x  y "),
+    ];
+    let expander = Expander::new();
+    for (s, expected) in [
+        ("$A", "a"),
+        ("${A}b", "ab"),
+        ("$Ab", ""),
+        ("$UNSET-", "-"),
+        ("$ $1 $", "$  $"),
+        ("${A:-d} ${EMPTY:-d} ${UNSET:-d}", "a d d"),
+        ("${A-d} ${EMPTY-d} ${UNSET-d}", "a  d"),
+        ("${A:+x} ${EMPTY:+x} ${UNSET:+x}", "x  "),
+        ("${A+x} ${EMPTY+x} ${UNSET+x}", "x x "),
+        ("${A:?m} ${EMPTY?m}", "a "),
+        ("${UNSET:-$A$A}", "aa"),
+        ("${UNSET:-'$A'}", "$A"),
+        ("${FILE#*.}", "tar.gz"),
+        ("${FILE##*.}", "gz"),
+        ("${FILE%.*}", "archive.tar"),
+        ("${FILE%%.*}", "archive"),
+        ("${FILE#[a-c]*}", "rchive.tar.gz"),
+        ("${FILE#[!a]}", "archive.tar.gz"),
+        ("${FILE%\\.gz}", "archive.tar"),
+        ("${PATH/\\/usr/\\/opt}", "/opt/local/bin:/usr/bin"),
+        ("${PATH//\\/usr/\\/opt}", "/opt/local/bin:/opt/bin"),
+        ("${PATH//:*}", "/usr/local/bin"),
+        ("${FILE/?}", "rchive.tar.gz"),
+        ("${FILE#*a*r}", "chive.tar.gz"),
+        ("${FILE##*[.]}", "gz"),
+        ("${FILE%%*.t*}", ""),
+        ("${FILE/a*e/x}", "x.tar.gz"),
+        ("${FILE/*a*x*}", "archive.tar.gz"),
+        ("${UNSET#x}", ""),
+        ("'$A' \"$A\" \"'$A'\"", "$A a 'a'"),
+        ("\\$A \"\\$A\" \"\\a\\\"\"", "$A $A \\a\""),
+        ("a\\", "a"),
+        ("$WORDS", " x  y "),
+    ] {
+        assert_expand(expander, &env, s, expected);
+    }
+    assert_expand(expander.escape('`'), &env, "`$A \"`$A\" \\$A", "$A $A \\a");
+    assert_expand(expander.raw_quotes(true), &env, "'$A' \"$A\"", "'$A' \"a\"");
+    assert_expand(expander.raw_escapes(true), &env, "\\$A \"\\$A\"", "\\$A \\$A");
+    assert_expand(
+        expander.skip_unset(true),
+        &env,
+        "$A $UNSET ${UNSET} ${UNSET:-d}",
+        "a $UNSET ${UNSET} d",
+    );
+    // patterns with many `*` against a long value don't backtrack exponentially
+    let long = "a".repeat(200);
+    let long_env = [("LONG", &*long)];
+    assert_expand(expander, &long_env, "${LONG#*a*a*a*a*a*a*a*a*a*a*b}", &long);
+    assert_expand(expander, &long_env, "${LONG//*a*a*a*a*a*a*a*a*a*a*b/x}", &long);
+    assert_expand(expander, &long_env, "${LONG##*a*a*a*a*a*a*a*a*a*a}", "");
+
+    // errors
+    for (s, msg) in [
+        ("'a", "unexpected end of statement while looking for matching single-quote"),
+        ("\"a", "unexpected end of statement while looking for matching double-quote"),
+        ("${A", "syntax error: missing '}'"),
+        ("${A:-x", "syntax error: missing '}'"),
+        ("${}", "bad substitution"),
+        ("${A:=x}", "unsupported modifier (:=) in substitution"),
+        ("${A^}", "unsupported modifier (^) in substitution"),
+        ("${EMPTY:?}", "EMPTY: is not allowed to be empty"),
+        ("${UNSET?}", "UNSET: is not allowed to be unset"),
+        ("${UNSET:?must be set}", "UNSET: must be set"),
+    ] {
+        assert_eq!(expander.expand(s, &env).unwrap_err().to_string(), msg, "{s}");
+    }
+
+    // spans
+    let s = "x${A}y $FILE ${UNSET:-z}";
+    let expanded = expander.expand(s, &env).unwrap();
+    assert_eq!(expanded.value, "xay archive.tar.gz z");
+    let substitutions: Vec<_> = expanded
+        .substitutions
+        .iter()
+        .map(|s| (s.name, s.value_range.clone(), &expanded.value[s.range.clone()]))
+        .collect();
+    assert_eq!(substitutions, [
+        ("A", 1..5, "a"),
+        ("FILE", 7..12, "archive.tar.gz"),
+        ("UNSET", 13..24, "z"),
+    ]);
+
+    // word splitting
+    let words = expander.expand_words("a$WORDS\"b c\" '' \"$WORDS\" ${UNSET}", &env).unwrap();
+    let values: Vec<_> = words.iter().map(|w| &*w.value).collect();
+    assert_eq!(values, ["a", "x", "y", "b c", "", " x  y "]);
+    let ranges: Vec<_> = words
+        .iter()
+        .map(|w| {
+            w.substitutions
+                .iter()
+                .map(|s| (s.value_range.clone(), s.range.clone()))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let expected =
+        [vec![], vec![(1..7, 0..1)], vec![(1..7, 0..1)], vec![], vec![], vec![(17..23, 0..6)]];
+    assert_eq!(ranges, expected);
+
+    // escape character of the dockerfile
+    let text = "# escape=`\nFROM alpine\nWORKDIR C:\\`$A\n";
+    let dockerfile = parse(text).unwrap();
+    assert_eq!(dockerfile.expander().expand("C:\\`$A", &env).unwrap().value, "C:\\$A");
+}
+
+#[test]
+fn scopes() {
+    use parse_dockerfile::VariableKind::*;
+
+    fn variables<'a>(scope: &'a Scope<'_>) -> Vec<(&'a str, VariableKind, Option<&'a str>)> {
+        scope
+            .iter()
+            .filter(|v| v.kind != VariableKind::Predefined || v.value.is_some())
+            .map(|v| (v.name, v.kind, v.value.as_deref()))
+            .collect()
+    }
+
+    let text = r#"ARG BASE=alpine
+ARG TAG=${BASE}:3 UNSET
+FROM $TAG AS base
+ARG TAG
+ENV PATH=/usr/bin HOME="/home/$TAG"
+ARG PATH=x
+ENV LEGACY a b \
+    c
+RUN true
+
+FROM base AS child
+ARG TARGETARCH
+ARG BASE=default
+ENV HOME=/root OLD=$HOME
+RUN true
+"#;
+    let dockerfile = parse(text).unwrap();
+    let scopes = dockerfile.scopes(&[("BASE", "debian"), ("TARGETARCH", "amd64")]).unwrap();
+    assert_eq!(variables(scopes.get(0).unwrap()), [("TARGETARCH", Predefined, Some("amd64"))]);
+    // FROM
+    assert_eq!(variables(scopes.get(2).unwrap()), [
+        ("TARGETARCH", Predefined, Some("amd64")),
+        ("BASE", GlobalArg, Some("debian")),
+        ("TAG", GlobalArg, Some("debian:3")),
+        ("UNSET", GlobalArg, None),
+    ]);
+    // first instruction in stage
+    assert_eq!(variables(scopes.get(3).unwrap()), []);
+    // RUN in base
+    assert_eq!(variables(scopes.get(7).unwrap()), [
+        ("TAG", StageArg, Some("debian:3")),
+        ("PATH", Env, Some("/usr/bin")),
+        ("HOME", Env, Some("/home/debian:3")),
+        ("LEGACY", Env, Some("a b     c")),
+    ]);
+    // RUN in child
+    let scope = scopes.get(12).unwrap();
+    assert_eq!(variables(scope), [
+        ("PATH", Inherited, Some("/usr/bin")),
+        ("HOME", Env, Some("/root")),
+        ("LEGACY", Inherited, Some("a b     c")),
+        ("TARGETARCH", StageArg, Some("amd64")),
+        ("BASE", StageArg, Some("debian")),
+        ("OLD", Env, Some("/home/debian:3")),
+    ]);
+    assert_eq!(&text[scope.get("HOME").unwrap().span.clone().unwrap()], "HOME=/root");
+    assert_eq!(&text[scope.get("PATH").unwrap().span.clone().unwrap()], "PATH=/usr/bin");
+    assert_eq!(&text[scope.get("LEGACY").unwrap().span.clone().unwrap()], "LEGACY a b \\\n    c");
+    assert!(scope.get("UNSET").is_none());
+    assert!(scope.get("HTTP_PROXY").is_some_and(|v| v.kind == Predefined && v.value.is_none()));
+    assert_eq!(dockerfile.expander().expand("$HOME:$BASE", scope).unwrap().value, "/root:debian");
+    let end = scopes.stage_end(1).unwrap();
+    assert_eq!(end.get("OLD").unwrap().value.as_deref(), Some("/home/debian:3"));
+
+    assert_eq!(
+        parse("ARG A=${B:?required}\nFROM alpine\n")
+            .unwrap()
+            .scopes(&[] as &[(&str, &str)])
+            .unwrap_err()
+            .to_string(),
+        "B: required"
+    );
+}
+
+#[test]
+fn variable_references() {
+    use parse_dockerfile::Operator::*;
+
+    let text = r#"ARG A=$B C=${D:-$E}
+FROM --platform=$BUILDPLATFORM ${IMAGE} AS build
+ENV X='$NO' Y="$YES" \
+    Z=\$NO
+COPY --chown=${USER#*:} ${SRC%%/*} ${SRC%/*} "${DEST//a/$R}" ${F/x} ${G-} ${H+}
+COPY <<EOF <<'NO' /$DIR/
+'$I' "${J:?x}"
+EOF
+$NO
+NO
+RUN --mount=target=${K:+x} echo $NO
+CMD $NO
+LABEL ${L?}
+EXPOSE $PORT/tcp
+USER $UID:$GID
+WORKDIR ${W##/}
+VOLUME ["$V1", "$V2"]
+STOPSIGNAL $SIG
+ONBUILD COPY $O /
+"#;
+    let dockerfile = parse(text).unwrap();
+    let references = dockerfile.variable_references(text);
+    let references: Vec<(&str, Option<Operator>, &str, usize)> = references
+        .iter()
+        .map(|r| (r.name, r.operator, &text[r.span.clone()], r.instruction))
+        .collect();
+    assert_eq!(references, [
+        ("B", None, "$B", 0),
+        ("D", Some(DefaultIfEmpty), "${D:-$E}", 0),
+        ("E", None, "$E", 0),
+        ("BUILDPLATFORM", None, "$BUILDPLATFORM", 1),
+        ("IMAGE", None, "${IMAGE}", 1),
+        ("YES", None, "$YES", 2),
+        ("USER", Some(RemoveShortestPrefix), "${USER#*:}", 3),
+        ("SRC", Some(RemoveLongestSuffix), "${SRC%%/*}", 3),
+        ("SRC", Some(RemoveShortestSuffix), "${SRC%/*}", 3),
+        ("DEST", Some(ReplaceAll), "${DEST//a/$R}", 3),
+        ("R", None, "$R", 3),
+        ("F", Some(ReplaceFirst), "${F/x}", 3),
+        ("G", Some(DefaultIfUnset), "${G-}", 3),
+        ("H", Some(AlternativeIfSet), "${H+}", 3),
+        ("DIR", None, "$DIR", 4),
+        ("I", None, "$I", 4),
+        ("J", Some(ErrorIfEmpty), "${J:?x}", 4),
+        ("K", Some(AlternativeIfNonEmpty), "${K:+x}", 5),
+        ("L", Some(ErrorIfUnset), "${L?}", 7),
+        ("PORT", None, "$PORT", 8),
+        ("UID", None, "$UID", 9),
+        ("GID", None, "$GID", 9),
+        ("W", Some(RemoveLongestPrefix), "${W##/}", 10),
+        ("V1", None, "$V1", 11),
+        ("V2", None, "$V2", 11),
+        ("SIG", None, "$SIG", 12),
+        ("O", None, "$O", 13),
+    ]);
+
+    // escape character
+    let text = "# escape=`\nFROM alpine\nWORKDIR C:\\$A\\`$B\\$C\n";
+    let dockerfile = parse(text).unwrap();
+    let references: Vec<_> = dockerfile.variable_references(text).iter().map(|r| r.name).collect();
+    assert_eq!(references, ["A", "C"]);
+}
+
+#[test]
+fn resolve_images() {
+    fn resolve(text: &str, options: &ResolveOptions) -> Vec<(usize, String, String)> {
+        let dockerfile = parse(text).unwrap();
+        let resolution = dockerfile.resolve_images(options).unwrap();
+        assert_eq!(resolution.problems, []);
+        resolution
+            .stages
+            .iter()
+            .map(|s| {
+                let base = match &s.base {
+                    ResolvedBase::Scratch => "scratch".to_owned(),
+                    ResolvedBase::Stage(i) => format!("stage {i}"),
+                    ResolvedBase::Image(image) => image.clone(),
+                    _ => unreachable!(),
+                };
+                (s.index, base, s.platform.to_string())
+            })
+            .collect()
+    }
+    fn resolve_err(text: &str, options: &ResolveOptions) -> String {
+        parse(text).unwrap().resolve_images(options).unwrap_err().to_string()
+    }
+    fn problems(text: &str, options: &ResolveOptions) -> Vec<(usize, String, String)> {
+        let dockerfile = parse(text).unwrap();
+        let resolution = dockerfile.resolve_images(options).unwrap();
+        resolution
+            .problems
+            .iter()
+            .map(|p| {
+                let kind = match &p.kind {
+                    ResolveProblemKind::UnsetVariable(name) => format!("unset {name}"),
+                    ResolveProblemKind::BlankBase => "blank".to_owned(),
+                    ResolveProblemKind::InvalidPlatform => "platform".to_owned(),
+                    _ => unreachable!(),
+                };
+                (p.stage, kind, text[p.span.clone()].to_owned())
+            })
+            .collect()
+    }
+
+    // platform parsing
+    for (s, expected) in [
+        ("linux/amd64", "linux/amd64"),
+        ("Linux/x86_64", "linux/amd64"),
+        ("linux/amd64/v1", "linux/amd64"),
+        ("linux/amd64/v3", "linux/amd64/v3"),
+        ("linux/aarch64", "linux/arm64"),
+        ("linux/arm64/v8", "linux/arm64"),
+        ("linux/arm", "linux/arm/v7"),
+        ("linux/arm/6", "linux/arm/v6"),
+        ("linux/armhf", "linux/arm/v7"),
+        ("linux/i386", "linux/386"),
+        ("macos/arm64", "darwin/arm64"),
+        ("windows/amd64", "windows/amd64"),
+    ] {
+        assert_eq!(s.parse::<Platform>().unwrap().to_string(), expected, "{s}");
+    }
+    for s in ["", "linux", "linux/", "/amd64", "linux/arm64/", "linux/arm64/v8/x"] {
+        assert_eq!(
+            s.parse::<Platform>().unwrap_err().to_string(),
+            format!("invalid platform {s:?}")
+        );
+    }
+
+    let text = "ARG BASE=alpine VERSION
+ARG TAG=${VERSION:-latest}
+FROM --platform=$BUILDPLATFORM rust AS build
+FROM ${BASE}:${TAG} AS base
+FROM base AS test
+FROM scratch AS bin
+COPY --from=build /app /app
+FROM --platform=linux/arm/v7 base AS arm
+";
+    let options = ResolveOptions::new().platform("linux/arm64/v8".parse().unwrap());
+    assert_eq!(resolve(text, &options), [
+        (1, "alpine:latest".to_owned(), "linux/arm64".to_owned()),
+        (4, "stage 1".to_owned(), "linux/arm/v7".to_owned()),
+    ]);
+    let options = options.build_arg("VERSION", "3").build_arg("BASE", "debian").target("bin");
+    assert_eq!(resolve(text, &options), [
+        (0, "rust".to_owned(), "linux/amd64".to_owned()),
+        (3, "scratch".to_owned(), "linux/arm64".to_owned()),
+    ]);
+    let options = ResolveOptions::new()
+        .build_platform(Platform::new("linux", "arm64", None))
+        .build_arg("TAG", "edge")
+        .target("test");
+    assert_eq!(resolve(text, &options), [
+        (1, "alpine:edge".to_owned(), "linux/arm64".to_owned()),
+        (2, "stage 1".to_owned(), "linux/arm64".to_owned()),
+    ]);
+
+    // stage references are expanded with the build args
+    let text = "FROM alpine AS a
+FROM busybox AS b
+FROM scratch
+ARG STAGE=a
+COPY --from=$STAGE / /
+";
+    let options = ResolveOptions::new();
+    assert_eq!(resolve(text, &options), [
+        (0, "alpine".to_owned(), "linux/amd64".to_owned()),
+        (2, "scratch".to_owned(), "linux/amd64".to_owned()),
+    ]);
+    assert_eq!(resolve(text, &options.build_arg("STAGE", "b")), [
+        (1, "busybox".to_owned(), "linux/amd64".to_owned()),
+        (2, "scratch".to_owned(), "linux/amd64".to_owned()),
+    ]);
+    // stages that the target doesn't depend on are not resolved
+    let options = ResolveOptions::new();
+    assert_eq!(resolve("FROM $X AS a\nFROM --platform=x y AS b\nFROM alpine\n", &options), [(
+        2,
+        "alpine".to_owned(),
+        "linux/amd64".to_owned()
+    )]);
+
+    // errors
+    let options = &ResolveOptions::new();
+    assert_eq!(resolve_err(text, &ResolveOptions::new().target("x")), "stage \"x\" not found");
+    assert_eq!(resolve_err("ARG A\nFROM alpine:${A:?required}\n", options), "A: required");
+
+    // problems
+    assert_eq!(problems("ARG TAG\nFROM alpine:$TAG\n", options), [(
+        0,
+        "unset TAG".to_owned(),
+        "$TAG".to_owned()
+    )]);
+    assert_eq!(problems("FROM $IMAGE AS a\nFROM --platform=${OS}/$ARCH a\n", options), [
+        (0, "unset IMAGE".to_owned(), "$IMAGE".to_owned()),
+        (0, "blank".to_owned(), "$IMAGE".to_owned()),
+        (1, "unset OS".to_owned(), "${OS}".to_owned()),
+        (1, "platform".to_owned(), "${OS}/$ARCH".to_owned()),
+        (1, "unset ARCH".to_owned(), "$ARCH".to_owned()),
+    ]);
+    assert_eq!(problems("ARG IMAGE=\nFROM $IMAGE\n", options), [(
+        0,
+        "blank".to_owned(),
+        "$IMAGE".to_owned()
+    )]);
+    assert_eq!(problems("FROM --platform=linux alpine\n", options), [(
+        0,
+        "platform".to_owned(),
+        "linux".to_owned()
+    )]);
+    let dockerfile = parse("FROM --platform=linux alpine AS a\nFROM a\n").unwrap();
+    let resolution = dockerfile.resolve_images(options).unwrap();
+    assert_eq!(resolution.stages[0].platform.to_string(), "linux/amd64");
+    assert_eq!(resolution.stages[1].base, ResolvedBase::Stage(0));
+    assert_eq!(resolve("ARG A\nFROM alpine${A:+:$A}\n", options), [(
+        0,
+        "alpine".to_owned(),
+        "linux/amd64".to_owned()
+    )]);
+}
+
+#[test]
+fn image_configs() {
+    use std::time::Duration;
+
+    let text = r#"ARG BASE=base
+FROM alpine AS base
+ENV A=1 B="x y"
+WORKDIR /app
+WORKDIR sub/../src
+USER 1000:1000
+CMD echo \
+  hi
+LABEL a=b "c d"=e
+EXPOSE 80 53/UDP 8000-8002
+VOLUME ["/data", "/cache"]
+HEALTHCHECK --interval=1m30s --timeout=500ms --retries=3 CMD curl -f localhost || exit 1
+STOPSIGNAL SIGTERM
+ONBUILD RUN echo \
+  x
+
+FROM ${BASE} AS child
+ARG V
+ENV C=$V
+SHELL ["/bin/bash", "-c"]
+ENTRYPOINT echo $C
+
+FROM child
+CMD ["a"]
+ENTRYPOINT ["b"]
+HEALTHCHECK NONE
+VOLUME /$C
+
+FROM scratch
+"#;
+    let dockerfile = parse(text).unwrap();
+    let configs = dockerfile.image_configs(text, &[("V", "2")]).unwrap();
+    assert_eq!(configs.len(), 4);
+
+    let base = &configs[0];
+    assert_eq!(base.env, ["A=1", "B=x y"]);
+    assert_eq!(base.working_dir, "/app/src");
+    assert_eq!(base.user, "1000:1000");
+    assert_eq!(base.cmd, ["/bin/sh", "-c", "echo   hi"]);
+    assert!(base.entrypoint.is_empty());
+    assert_eq!(base.labels.iter().collect::<Vec<_>>(), [
+        (&"a".to_owned(), &"b".to_owned()),
+        (&"c d".to_owned(), &"e".to_owned())
+    ]);
+    assert_eq!(base.exposed_ports.iter().collect::<Vec<_>>(), [
+        "53/udp", "80/tcp", "8000/tcp", "8001/tcp", "8002/tcp"
+    ]);
+    assert_eq!(base.volumes.iter().collect::<Vec<_>>(), ["/cache", "/data"]);
+    let healthcheck = base.healthcheck.as_ref().unwrap();
+    assert_eq!(healthcheck.test, ["CMD-SHELL", "curl -f localhost || exit 1"]);
+    assert_eq!(healthcheck.interval, Some(Duration::from_secs(90)));
+    assert_eq!(healthcheck.timeout, Some(Duration::from_millis(500)));
+    assert_eq!(healthcheck.start_period, None);
+    assert_eq!(healthcheck.retries, Some(3));
+    assert_eq!(base.stop_signal, "SIGTERM");
+    assert_eq!(base.on_build, ["RUN echo   x"]);
+
+    // inherited from base, and `ENTRYPOINT` resets inherited `CMD`
+    let child = &configs[1];
+    assert_eq!(child.env, ["A=1", "B=x y", "C=2"]);
+    assert_eq!(child.working_dir, "/app/src");
+    assert_eq!(child.shell, ["/bin/bash", "-c"]);
+    assert_eq!(child.entrypoint, ["/bin/bash", "-c", "echo $C"]);
+    assert!(child.cmd.is_empty());
+    assert!(child.on_build.is_empty());
+    assert_eq!(child.healthcheck, base.healthcheck);
+
+    // `CMD` before `ENTRYPOINT` in the same stage is kept
+    let last = &configs[2];
+    assert_eq!(last.cmd, ["a"]);
+    assert_eq!(last.entrypoint, ["b"]);
+    assert_eq!(last.healthcheck.as_ref().unwrap().test, ["NONE"]);
+    assert_eq!(last.volumes.iter().collect::<Vec<_>>(), ["/2", "/cache", "/data"]);
+
+    assert_eq!(configs[3], ImageConfig::default());
+
+    // errors
+    let text = "FROM alpine\nVOLUME $V\n";
+    assert_eq!(
+        parse(text).unwrap().image_configs(text, &[("", ""); 0]).unwrap_err().to_string(),
+        "VOLUME specified can not be an empty string"
+    );
+    let text = "FROM alpine\nHEALTHCHECK --interval=1x CMD true\n";
+    assert_eq!(
+        parse(text).unwrap().image_configs(text, &[("", ""); 0]).unwrap_err().to_string(),
+        "invalid duration \"1x\" for --interval in HEALTHCHECK"
+    );
+}
+
+#[test]
+fn working_dirs() {
+    let text = r"ARG ROOT=/srv
+FROM alpine AS base
+ARG ROOT
+WORKDIR $ROOT
+WORKDIR app/./x/..
+COPY a b ./
+COPY c .
+COPY d e
+COPY f /opt/
+ADD g ../etc/.
+
+FROM base AS child
+ENV SUB=bin
+WORKDIR $SUB
+COPY --from=base /a $HOME/
+WORKDIR ${HOME}/y
+
+FROM debian
+COPY h i/
+";
+    let dockerfile = parse(text).unwrap();
+    let working_dirs = dockerfile.working_dirs(&[("ROOT", "/data")]).unwrap();
+    let dirs: Vec<_> =
+        (0..dockerfile.instructions.len()).map(|i| working_dirs.get(i).unwrap_or("-")).collect();
+    assert_eq!(dirs, [
+        "-",
+        "/",
+        "/",
+        "/",
+        "/data",
+        "/data/app",
+        "/data/app",
+        "/data/app",
+        "/data/app",
+        "/data/app",
+        "/data/app",
+        "/data/app",
+        "/data/app",
+        "/data/app/bin",
+        "/data/app/bin",
+        "/",
+        "/",
+    ]);
+    assert_eq!(working_dirs.stage_end(0), Some("/data/app"));
+    // references to variables that are not set are left as is
+    assert_eq!(working_dirs.stage_end(1), Some("/data/app/bin/${HOME}/y"));
+    assert_eq!(working_dirs.stage_end(2), Some("/"));
+    assert_eq!(working_dirs.stage_end(3), None);
+
+    let destinations = dockerfile.copy_destinations(&[("ROOT", "/data")]).unwrap();
+    let destinations: Vec<_> =
+        destinations.iter().map(|d| (d.instruction, &*d.path, d.is_dir)).collect();
+    assert_eq!(destinations, [
+        (5, "/data/app/", true),
+        (6, "/data/app/", true),
+        (7, "/data/app/e", false),
+        (8, "/opt/", true),
+        (9, "/data/etc/", true),
+        (13, "/data/app/bin/$HOME/", true),
+        (16, "/i/", true),
+    ]);
+    let destinations = dockerfile.copy_destinations(&[("", ""); 0]).unwrap();
+    assert_eq!(destinations[0].path, "/srv/app/");
+    assert_eq!(destinations[0].target("dir/a"), "/srv/app/a");
+    assert_eq!(destinations[2].target("d"), "/srv/app/e");
+
+    let text = "FROM alpine\nWORKDIR /app\nCOPY a b c\n";
+    assert_eq!(
+        parse(text).unwrap().copy_destinations(&[("", ""); 0]).unwrap_err().to_string(),
+        "When using COPY with more than one source file, the destination must be a directory and end with a /"
+    );
+}
+
+#[test]
+fn context_inputs() {
+    fn summary(inputs: &[ContextInput]) -> Vec<(usize, ContextInputKind, &str, &str, bool, bool)> {
+        inputs
+            .iter()
+            .map(|i| {
+                (i.instruction, i.kind, &*i.source, &*i.destination, i.has_variables, i.has_globs)
+            })
+            .collect()
+    }
+
+    let text = r"FROM rust AS deps
+COPY --parents --exclude=target --exclude=*.md Cargo.toml crates/*/Cargo.toml ./
+RUN --mount=type=bind,source=vendor,target=/vendor \
+    --mount=type=cache,target=/cache \
+    --mount=from=rust,target=/r \
+    cargo fetch
+
+FROM deps AS build
+ADD https://example.com/a.tar.gz app.tar.gz /tmp/
+COPY <<EOF ${SRC:-src} /src/
+x
+EOF
+RUN --mount=target=/ctx,readonly cargo build
+
+FROM alpine AS test
+COPY --from=build /app /app
+COPY --from=context tests/ /tests/
+COPY --parents=false test[0-9].sh /
+
+FROM scratch
+COPY --from=build /app /app
+";
+    let dockerfile = parse(text).unwrap();
+    let no_args = &[("", ""); 0];
+    let inputs = dockerfile.context_inputs(no_args).unwrap();
+    assert_eq!(summary(&inputs), [
+        (1, ContextInputKind::Copy, "Cargo.toml", "./", false, false),
+        (1, ContextInputKind::Copy, "crates/*/Cargo.toml", "./", false, true),
+        (2, ContextInputKind::Mount, "vendor", "/vendor", false, false),
+        (4, ContextInputKind::Add, "app.tar.gz", "/tmp/", false, false),
+        (5, ContextInputKind::Copy, "src", "/src/", true, false),
+        (6, ContextInputKind::Mount, ".", "/ctx", false, false),
+        (10, ContextInputKind::Copy, "test[0-9].sh", "/", false, true),
+    ]);
+    for input in &inputs[..2] {
+        assert_eq!(input.stage, 0);
+        assert_eq!(input.excludes, ["target", "*.md"]);
+        assert!(input.parents);
+    }
+    assert!(inputs[2..].iter().all(|i| i.excludes.is_empty() && !i.parents));
+
+    let build = dockerfile.context_inputs_for("build", no_args).unwrap();
+    assert_eq!(build.iter().map(|i| i.instruction).collect::<Vec<_>>(), [1, 1, 2, 4, 5, 6]);
+    let test = dockerfile.context_inputs_for("test", no_args).unwrap();
+    assert_eq!(test.iter().map(|i| i.instruction).collect::<Vec<_>>(), [1, 1, 2, 4, 5, 6, 10]);
+    assert_eq!(
+        dockerfile.context_inputs_for("x", no_args).unwrap_err().to_string(),
+        "stage \"x\" not found"
+    );
+
+    // variables are expanded with build args, and values are unquoted
+    let text = r#"FROM alpine AS a
+FROM alpine AS b
+ARG DIR=src STAGE
+COPY --exclude="*.md" --exclude=$DIR/tmp $DIR/ /app/
+RUN --mount="source=a,b",target=/x --mount=type=bind,source=$DIR,target="/y" true
+COPY --from=$STAGE /lib /lib
+"#;
+    let dockerfile = parse(text).unwrap();
+    let inputs = dockerfile.context_inputs(no_args).unwrap();
+    assert_eq!(summary(&inputs), [
+        (3, ContextInputKind::Copy, "src/", "/app/", true, false),
+        (4, ContextInputKind::Mount, "a,b", "/x", false, false),
+        (4, ContextInputKind::Mount, "src", "/y", true, false),
+        (5, ContextInputKind::Copy, "/lib", "/lib", false, false),
+    ]);
+    assert_eq!(inputs[0].excludes, ["*.md", "src/tmp"]);
+    let args = [("DIR", "lib"), ("STAGE", "a")];
+    let inputs = dockerfile.context_inputs(&args).unwrap();
+    assert_eq!(summary(&inputs), [
+        (3, ContextInputKind::Copy, "lib/", "/app/", true, false),
+        (4, ContextInputKind::Mount, "a,b", "/x", false, false),
+        (4, ContextInputKind::Mount, "lib", "/y", true, false),
+    ]);
+    assert_eq!(inputs[0].excludes, ["*.md", "lib/tmp"]);
+    // `a` is needed only with the build arg
+    assert_eq!(dockerfile.context_inputs_for("b", no_args).unwrap().len(), 4);
+    assert_eq!(dockerfile.graph_with_args(&args).unwrap().build_order(1), [0, 1],);
+    assert_eq!(
+        parse("FROM alpine\nCOPY ${A:?required} /\n")
+            .unwrap()
+            .context_inputs(no_args)
+            .unwrap_err()
+            .to_string(),
+        "A: required"
+    );
+}
+
+#[test]
+fn build_plan() {
+    fn build_args(step: &PlanStep) -> Vec<(&str, &str)> {
+        step.build_args.iter().map(|(k, v)| (&**k, &**v)).collect()
+    }
+
+    let text = r"ARG BASE=alpine
+FROM --platform=$BUILDPLATFORM ${BASE} AS base
+ARG A=1 B
+ARG HTTP_PROXY
+ENV E=$A
+COPY --chown=$A ./a ./a ${B:-dst}/
+RUN --mount=type=bind,source=src,target=/src \
+    --mount=from=golang,target=/b,source=/ \
+    echo $E
+RUN <<EOF
+x $A \
+y
+EOF
+ONBUILD COPY $O /o
+LABEL version=$B
+
+FROM base
+RUN true
+";
+    let dockerfile = parse(text).unwrap();
+    let plan = dockerfile
+        .build_plan(text, &[
+            ("BASE", "debian"),
+            ("BUILDPLATFORM", "linux/amd64"),
+            ("HTTP_PROXY", "p"),
+        ])
+        .unwrap();
+    let summary: Vec<_> =
+        plan.iter().map(|s| (s.stage, s.instruction, s.kind, s.layer, &*s.text)).collect();
+    assert_eq!(summary, [
+        (0, 1, InstructionKind::From, false, "FROM --platform=linux/amd64 debian AS base"),
+        (0, 2, InstructionKind::Arg, false, "ARG A=1 B"),
+        (0, 3, InstructionKind::Arg, false, "ARG HTTP_PROXY"),
+        (0, 4, InstructionKind::Env, false, "ENV E=1"),
+        (0, 5, InstructionKind::Copy, true, "COPY --chown=1 ./a ./a dst/"),
+        (
+            0,
+            6,
+            InstructionKind::Run,
+            true,
+            "RUN --mount=type=bind,source=src,target=/src     --mount=from=golang,target=/b,source=/     echo $E"
+        ),
+        (0, 7, InstructionKind::Run, true, "RUN <<EOF\nx $A \\\ny\nEOF"),
+        (0, 8, InstructionKind::Onbuild, false, "ONBUILD COPY $O /o"),
+        (0, 9, InstructionKind::Label, false, "LABEL version="),
+        (1, 10, InstructionKind::From, false, "FROM base"),
+        (1, 11, InstructionKind::Run, true, "RUN true"),
+    ]);
+
+    // FROM consumes global args and platform args
+    assert_eq!(plan[0].from_images, ["debian"]);
+    assert_eq!(build_args(&plan[0]), [("BASE", "debian"), ("BUILDPLATFORM", "linux/amd64")]);
+    // COPY consumes referenced args, and reads context files (deduplicated)
+    assert_eq!(build_args(&plan[4]), [("A", "1")]);
+    assert_eq!(plan[4].context_files, ["./a"]);
+    // RUN consumes all stage args with values (proxy args only if declared)
+    assert_eq!(build_args(&plan[5]), [("A", "1"), ("HTTP_PROXY", "p")]);
+    assert_eq!(plan[5].context_files, ["src"]);
+    assert_eq!(plan[5].mounts, [
+        "type=bind,source=src,target=/src",
+        "from=golang,target=/b,source=/"
+    ]);
+    assert_eq!(plan[5].from_images, ["golang"]);
+    assert!(plan[5].from_stages.is_empty());
+    assert_eq!(build_args(&plan[6]), [("A", "1"), ("HTTP_PROXY", "p")]);
+    assert!(plan[7].build_args.is_empty());
+    assert!(plan[8].build_args.is_empty());
+    // stage based on another stage
+    assert_eq!(plan[9].from_stages, [0]);
+    assert!(plan[9].from_images.is_empty());
+    assert!(plan[10].build_args.is_empty());
+}
+
+#[test]
+fn stageless() {
+    // Analyses skip instructions outside stages, which are allowed by
+    // ParseOptions::allow_no_stages.
+    let options = ParseOptions::new().allow_no_stages(true);
+    let no_args = &[("", ""); 0];
+    for text in ["", "ARG A=1\nWORKDIR /$A\nCOPY a b/\nRUN --mount=type=bind,source=c echo $A\n"] {
+        let dockerfile = parse_with(text, options).unwrap();
+        let scopes = dockerfile.scopes(no_args).unwrap();
+        assert!(scopes.stage_end(0).is_none());
+        assert!(dockerfile.resolve_images(&ResolveOptions::new()).unwrap().stages.is_empty());
+        assert!(dockerfile.image_configs(text, no_args).unwrap().is_empty());
+        let working_dirs = dockerfile.working_dirs(no_args).unwrap();
+        assert!((0..dockerfile.instructions.len()).all(|i| working_dirs.get(i).is_none()));
+        assert!(dockerfile.copy_destinations(no_args).unwrap().is_empty());
+        assert!(dockerfile.context_inputs(no_args).unwrap().is_empty());
+        assert!(dockerfile.build_plan(text, no_args).unwrap().is_empty());
+        assert!(dockerfile.graph().unwrap().build_order(0).is_empty());
+    }
+
+    // Instructions before the first FROM.
+    let text = "COPY a b/\nFROM alpine\nWORKDIR /x\nCOPY c d/\n";
+    let dockerfile = parse_with(text, options).unwrap();
+    assert!(dockerfile.working_dirs(no_args).unwrap().get(0).is_none());
+    let destinations = dockerfile.copy_destinations(no_args).unwrap();
+    assert_eq!(destinations.len(), 1);
+    assert_eq!(destinations[0].path, "/x/d/");
+    let configs = dockerfile.image_configs(text, no_args).unwrap();
+    assert_eq!(configs.len(), 1);
+    assert_eq!(configs[0].working_dir, "/x");
+    let inputs = dockerfile.context_inputs(no_args).unwrap();
+    assert_eq!(inputs.len(), 1);
+    assert_eq!(inputs[0].source, "c");
+    let plan = dockerfile.build_plan(text, no_args).unwrap();
+    assert_eq!(plan.iter().map(|s| s.instruction).collect::<Vec<_>>(), [1, 2, 3]);
+}
+
+#[test]
+fn format() {
+    #[track_caller]
+    fn assert_format(style: &FormatStyle, text: &str, expected: &str) {
+        let formatted = SyntaxTree::parse(text).unwrap().format(style);
+        assert_eq!(formatted, expected);
+        assert_eq!(SyntaxTree::parse(&formatted).unwrap().format(style), formatted);
+    }
+    fn here_docs(dockerfile: &Dockerfile<'_>) -> Vec<String> {
+        let mut here_docs = vec![];
+        for instruction in &dockerfile.instructions {
+            match instruction {
+                Instruction::Run(run) => {
+                    here_docs.extend(run.here_docs.iter().map(|h| h.value.to_string()));
+                }
+                Instruction::Add(AddInstruction { src, .. })
+                | Instruction::Copy(CopyInstruction { src, .. }) => {
+                    for src in src {
+                        if let Source::HereDoc(h) = src {
+                            here_docs.push(h.value.to_string());
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        here_docs
+    }
+
+    let style = &FormatStyle::new();
+    assert_format(
+        style,
+        "# syntax=docker/dockerfile:1\n# comment\nfrom --platform=$BUILDPLATFORM   alpine as   build\n  run apt-get update && apt-get install -y \\\n      foo \\\n  bar && \\\n  # a comment\n     echo \"a && b   \\\n  c\" && rm -rf x\n\n\ncopy --link --chown=1   a b\nRUN <<EOF bash && echo hi\n  x && y\nEOF\n# next stage\nFROM build\nHEALTHCHECK --interval=5s cmd curl a&&curl b\nonbuild   run a&&b\nCMD [\"a\",    \\\n   \"b\"]\nENV A=1   B=\"x   y\" \\\n  C=3\nENV LEGACY a   b \\\n  c\nWORKDIR /a  b\n",
+        "# syntax=docker/dockerfile:1\n\n# comment\nFROM --platform=$BUILDPLATFORM alpine AS build\nRUN apt-get update \\\n    && apt-get install -y \\\n    foo \\\n    bar \\\n    # a comment\n    && echo \"a && b   \\\n  c\" \\\n    && rm -rf x\n\nCOPY --chown=1 --link a b\nRUN <<EOF bash && echo hi\n  x && y\nEOF\n\n# next stage\nFROM build\nHEALTHCHECK --interval=5s CMD curl a&&curl b\nONBUILD RUN a \\\n    && b\nCMD [\"a\", \\\n    \"b\"]\nENV A=1 B=\"x   y\" \\\n    C=3\nENV LEGACY a   b \\\n  c\nWORKDIR /a  b\n",
+    );
+    // shell comments, words split by line continuation, escape directive, CRLF, BOM
+    assert_format(
+        style,
+        "\u{FEFF}# escape=`\r\nFROM a\r\nRUN a && b # c && d\r\nRUN ec`\r\nho `\r\n\r\n  hi\r\n",
+        "\u{FEFF}# escape=`\r\n\r\nFROM a\r\nRUN a && b # c && d\r\nRUN ec`\r\nho `\r\n    hi\r\n",
+    );
+    assert_format(
+        &FormatStyle::new().indent_width(2).split_commands(false).blank_line_between_stages(false),
+        "FROM a\nRUN a && b \\\n    c\n\n\nFROM b\nRUN \\\n# comment\n  d\n",
+        "FROM a\nRUN a && b \\\n  c\n\nFROM b\nRUN \\\n  # comment\n  d\n",
+    );
+    assert_format(style, "FROM a\n# comment\nFROM b\n", "FROM a\n\n# comment\nFROM b\n");
+    // whitespace, `&&`, and `#` in `${...}`
+    assert_format(
+        style,
+        "FROM a\nRUN echo   ${X:-a  b} ${Y:-c && d}  ${Z:- #e}&&  f\n",
+        "FROM a\nRUN echo ${X:-a  b} ${Y:-c && d} ${Z:- #e} \\\n    && f\n",
+    );
+
+    // fixtures
+    for e in fs::read_dir(fixtures_dir()).unwrap() {
+        let p = &e.unwrap().path();
+        if p.is_dir() {
+            continue;
+        }
+        let text = &fs::read_to_string(p).unwrap();
+        let tree = SyntaxTree::parse(text).unwrap();
+        let formatted = tree.format(style);
+        let reformatted = SyntaxTree::parse(&formatted).unwrap();
+        assert_eq!(reformatted.format(style), formatted, "{}", p.display());
+        let kinds =
+            |d: &Dockerfile<'_>| d.instructions.iter().map(Instruction::kind).collect::<Vec<_>>();
+        assert_eq!(kinds(reformatted.dockerfile()), kinds(tree.dockerfile()), "{}", p.display());
+        assert_eq!(here_docs(reformatted.dockerfile()), here_docs(tree.dockerfile()));
+    }
+}